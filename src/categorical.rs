@@ -0,0 +1,616 @@
+//! Dirichlet beliefs about how often each of several categories occurs, such as the root causes of incidents.
+//!
+//! The weight of each category in the prior counts as that many observations of it, and every update adds the observed counts to those weights.
+//! With two categories this is a Beta-Binomial, and the proportion of each follows a Beta distribution.
+use crate::store::{collect_records, find_record, open_store, remove_record, set_record, Store};
+use crate::{OutputFormat, Rounded};
+use anyhow::{anyhow, Error, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use prettytable::{format, Cell, Row, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use statrs::distribution::{Beta, ContinuousCDF};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The kind of metadata holding the beliefs about category frequencies, keyed by the name of the belief
+pub(crate) const CATEGORICAL_METADATA: &str = "categorical";
+
+/// A category and, optionally, the weight of the prior belief in it, written as `name` or `name=weight`
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct CategoryPrior {
+    /// The name of the category
+    pub name: String,
+    /// How many observations of the category the prior belief is worth, if not the default
+    pub weight: Option<f64>,
+}
+
+impl FromStr for CategoryPrior {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, weight) = match s.split_once('=') {
+            Some((name, weight)) => (name, Some(weight.trim().parse::<f64>()?)),
+            None => (s, None),
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Invalid category: {}", s));
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            weight,
+        })
+    }
+}
+
+impl Display for CategoryPrior {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.weight {
+            Some(weight) => write!(f, "{}={weight}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The number of times a category was observed, written as `name=count`, or as `name` for a single observation
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CategoryCount {
+    /// The name of the category
+    pub name: String,
+    /// The number of times the category was observed
+    pub count: u64,
+}
+
+impl FromStr for CategoryCount {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, count) = match s.split_once('=') {
+            Some((name, count)) => (name, count.trim().parse::<u64>()?),
+            None => (s, 1),
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Invalid category count: {}", s));
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            count,
+        })
+    }
+}
+
+impl Display for CategoryCount {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.count)
+    }
+}
+
+/// A category of a Dirichlet belief
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Category {
+    /// The name of the category
+    pub name: String,
+    /// How many observations of the category the prior belief is worth
+    pub weight: f64,
+    /// How many times the category has been observed
+    pub count: u64,
+}
+
+impl Category {
+    /// The concentration parameter of the category in the posterior Dirichlet distribution: the prior weight plus the observed count
+    #[inline]
+    #[must_use]
+    pub fn concentration(&self) -> f64 {
+        // Counts beyond 2^53 lose precision, which is far beyond any realistic tally
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.count as f64;
+        self.weight + count
+    }
+}
+
+/// The posterior estimate of the proportion of observations in a category
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct CategoryEstimate {
+    /// The name of the category
+    pub name: String,
+    /// How many times the category has been observed
+    pub count: u64,
+    /// The expected proportion of observations in the category
+    pub expected_proportion: f64,
+    /// The central interval holding the proportion with the requested probability
+    pub credible_interval: (f64, f64),
+}
+
+/// A change to the belief about the category frequencies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CategoricalEntry {
+    /// When the change was made
+    pub timestamp: DateTime<Utc>,
+    /// The counts the belief was updated with, or none if the prior was set
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub counts: BTreeMap<String, u64>,
+}
+
+/// A Dirichlet belief about how often each category occurs, with its history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CategoricalBelief {
+    /// The categories, in the order they were given
+    pub categories: Vec<Category>,
+    /// The changes to the belief, oldest first
+    #[serde(default)]
+    pub history: Vec<CategoricalEntry>,
+}
+
+impl CategoricalBelief {
+    /// Creates a prior belief over the categories, each worth `default_weight` observations unless it is given its own weight
+    /// # Errors
+    /// - If there are fewer than two categories, or a category is repeated
+    /// - If a weight is not a number above 0
+    #[inline]
+    pub fn new(categories: &[CategoryPrior], default_weight: f64) -> Result<Self> {
+        if categories.len() < 2 {
+            return Err(anyhow!("A belief needs at least two categories"));
+        }
+        let mut prior = Vec::with_capacity(categories.len());
+        for category in categories {
+            if prior
+                .iter()
+                .any(|existing: &Category| existing.name == category.name)
+            {
+                return Err(anyhow!("Category {} is given twice", category.name));
+            }
+            let weight = category.weight.unwrap_or(default_weight);
+            if !weight.is_finite() || weight <= 0.0_f64 {
+                return Err(anyhow!(
+                    "The weight of category {} must be a number above 0",
+                    category.name
+                ));
+            }
+            prior.push(Category {
+                name: category.name.clone(),
+                weight,
+                count: 0,
+            });
+        }
+        Ok(Self {
+            categories: prior,
+            history: vec![CategoricalEntry {
+                timestamp: Utc::now(),
+                counts: BTreeMap::new(),
+            }],
+        })
+    }
+
+    /// Adds the counts to the categories, recording the update in the history
+    /// # Errors
+    /// - If there are no counts
+    /// - If a category is not one of the categories of the belief
+    /// - If a count, or the number of observations across the categories, would be too large to hold
+    #[inline]
+    pub fn update(&mut self, counts: &[CategoryCount]) -> Result<()> {
+        if counts.is_empty() {
+            return Err(anyhow!("There are no counts to update with"));
+        }
+        let mut totals = BTreeMap::new();
+        for count in counts {
+            if !self
+                .categories
+                .iter()
+                .any(|category| category.name == count.name)
+            {
+                return Err(anyhow!(
+                    "{} is not one of the categories: {}",
+                    count.name,
+                    self.category_names().join(", ")
+                ));
+            }
+            let total = totals.entry(count.name.clone()).or_insert(0_u64);
+            *total = total
+                .checked_add(count.count)
+                .ok_or_else(|| anyhow!("There are too many observations of {}", count.name))?;
+        }
+        let mut updated = Vec::with_capacity(self.categories.len());
+        for category in &self.categories {
+            let added = totals.get(&category.name).copied().unwrap_or(0_u64);
+            updated.push(
+                category.count.checked_add(added).ok_or_else(|| {
+                    anyhow!("There are too many observations of {}", category.name)
+                })?,
+            );
+        }
+        updated
+            .iter()
+            .try_fold(0_u64, |total, count| total.checked_add(*count))
+            .ok_or_else(|| anyhow!("There are too many observations to count"))?;
+        for (category, count) in self.categories.iter_mut().zip(updated) {
+            category.count = count;
+        }
+        self.history.push(CategoricalEntry {
+            timestamp: Utc::now(),
+            counts: totals,
+        });
+        Ok(())
+    }
+
+    /// The names of the categories
+    #[inline]
+    #[must_use]
+    pub fn category_names(&self) -> Vec<&str> {
+        self.categories
+            .iter()
+            .map(|category| category.name.as_str())
+            .collect()
+    }
+
+    /// The total number of observations across the categories
+    /// # Errors
+    /// - If the total is too large to hold
+    #[inline]
+    pub fn observation_count(&self) -> Result<u64> {
+        self.categories
+            .iter()
+            .try_fold(0_u64, |total, category| total.checked_add(category.count))
+            .ok_or_else(|| anyhow!("There are too many observations to count"))
+    }
+
+    /// The expected proportion of each category, with the central interval holding it with probability `level`, from the Beta marginal of the Dirichlet distribution.  Ordered from the most to the least common.
+    /// # Errors
+    /// - If the level is not between 0 and 1
+    #[inline]
+    pub fn estimates(&self, level: f64) -> Result<Vec<CategoryEstimate>> {
+        if !(level > 0.0_f64 && level < 1.0_f64) {
+            return Err(anyhow!("The credible level must be between 0 and 1"));
+        }
+        let total = self
+            .categories
+            .iter()
+            .map(Category::concentration)
+            .sum::<f64>();
+        let tail = (1.0_f64 - level) / 2.0_f64;
+        let mut estimates = self
+            .categories
+            .iter()
+            .map(|category| {
+                let concentration = category.concentration();
+                let marginal = Beta::new(concentration, total - concentration)?;
+                Ok(CategoryEstimate {
+                    name: category.name.clone(),
+                    count: category.count,
+                    expected_proportion: concentration / total,
+                    credible_interval: (
+                        marginal.inverse_cdf(tail),
+                        marginal.inverse_cdf(1.0_f64 - tail),
+                    ),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        estimates.sort_by(|a, b| b.expected_proportion.total_cmp(&a.expected_proportion));
+        Ok(estimates)
+    }
+}
+
+/// Reads the belief from the store
+fn find_categorical(store: &dyn Store, name: &str) -> Result<CategoricalBelief> {
+    find_record(store, CATEGORICAL_METADATA, name)?
+        .ok_or_else(|| anyhow!("Could not find categories {name}"))
+}
+
+/// Saves the prior belief in place of any saved before, carrying over the history of the saved belief
+fn save_categorical(
+    store: &dyn Store,
+    name: &str,
+    belief: &CategoricalBelief,
+) -> Result<CategoricalBelief> {
+    let mut saved_belief = None;
+    store.transact(&mut |store| {
+        let mut history = find_record::<CategoricalBelief>(store, CATEGORICAL_METADATA, name)?
+            .map(|saved| saved.history)
+            .unwrap_or_default();
+        history.extend(belief.history.iter().cloned());
+        let replacement = CategoricalBelief {
+            categories: belief.categories.clone(),
+            history,
+        };
+        let operation = set_record(CATEGORICAL_METADATA, name, &replacement)?;
+        saved_belief = Some(replacement);
+        Ok(vec![operation])
+    })?;
+    saved_belief.ok_or_else(|| anyhow!("Could not save categories {name}"))
+}
+
+/// Adds the counts to the saved belief
+fn record_counts(
+    store: &dyn Store,
+    name: &str,
+    counts: &[CategoryCount],
+) -> Result<CategoricalBelief> {
+    let mut updated_belief = None;
+    store.transact(&mut |store| {
+        let mut belief = find_categorical(store, name)?;
+        belief.update(counts)?;
+        let operation = set_record(CATEGORICAL_METADATA, name, &belief)?;
+        updated_belief = Some(belief);
+        Ok(vec![operation])
+    })?;
+    updated_belief.ok_or_else(|| anyhow!("Could not update categories {name}"))
+}
+
+/// Removes the belief from the store
+fn delete_categorical(store: &dyn Store, name: &str) -> Result<()> {
+    store.transact(&mut |store| {
+        find_categorical(store, name)?;
+        Ok(vec![remove_record(CATEGORICAL_METADATA, name)])
+    })
+}
+
+/// Saves a prior belief about the category frequencies, returning the belief as saved.
+///
+/// A belief saved before under the name is replaced and its counts start again from the new prior, but its history is kept, with an entry without counts marking where the new prior was set.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the belief cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn set_categorical(name: &str, belief: &CategoricalBelief) -> Result<CategoricalBelief> {
+    save_categorical(&*open_store()?, name, belief)
+}
+
+/// Updates the saved belief about the category frequencies with the counts, recording the update in its history
+/// # Errors
+/// - If the database cannot be opened
+/// - If the belief has not been saved
+/// - If there are no counts, or a category is not one of the categories of the belief
+/// - If the belief cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn update_categorical(name: &str, counts: &[CategoryCount]) -> Result<CategoricalBelief> {
+    record_counts(&*open_store()?, name, counts)
+}
+
+/// Gets the saved belief about the category frequencies
+/// # Errors
+/// - If the database cannot be opened
+/// - If the belief has not been saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn get_categorical(name: &str) -> Result<CategoricalBelief> {
+    find_categorical(&*open_store()?, name)
+}
+
+/// Removes the belief about the category frequencies and its history from the database
+/// # Errors
+/// - If the database cannot be opened
+/// - If the belief has not been saved
+/// - If the belief cannot be removed
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn remove_categorical(name: &str) -> Result<()> {
+    delete_categorical(&*open_store()?, name)
+}
+
+/// Lists the saved beliefs about category frequencies in alphabetical order
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved beliefs cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn list_categoricals() -> Result<Vec<(String, CategoricalBelief)>> {
+    collect_records(&*open_store()?, CATEGORICAL_METADATA)
+}
+
+/// Reports the expected proportion of each category, with the interval holding it with probability `level`, from the most to the least common
+/// # Errors
+/// - If the level is not between 0 and 1
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_categorical(
+    name: &str,
+    belief: &CategoricalBelief,
+    level: f64,
+    output_format: &OutputFormat,
+) -> Result<()> {
+    let estimates = belief.estimates(level)?;
+    let percentage = format!("{}%", Rounded(level * 100.0_f64));
+    match *output_format {
+        OutputFormat::Json => {
+            let categories: Vec<_> = estimates
+                .iter()
+                .map(|estimate| {
+                    json!({
+                        "category": estimate.name,
+                        "count": estimate.count,
+                        "expected_proportion": estimate.expected_proportion,
+                        "credible_interval": [estimate.credible_interval.0, estimate.credible_interval.1],
+                    })
+                })
+                .collect();
+            let json = json!({
+                "name": name,
+                "observations": belief.observation_count()?,
+                "level": level,
+                "categories": categories,
+            });
+            info!("{}", json.to_string());
+        }
+        OutputFormat::Simple => {
+            for estimate in &estimates {
+                info!(
+                    "E[p({})] = {}, {percentage} credible interval = [{}, {}]",
+                    estimate.name,
+                    Rounded(estimate.expected_proportion),
+                    Rounded(estimate.credible_interval.0),
+                    Rounded(estimate.credible_interval.1)
+                );
+            }
+        }
+        OutputFormat::Table | OutputFormat::Icons => {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(Row::new(vec![
+                Cell::new("Category"),
+                Cell::new("Count"),
+                Cell::new("Expected Proportion"),
+                Cell::new(&format!("{percentage} Credible Interval")),
+            ]));
+            for estimate in &estimates {
+                table.add_row(Row::new(vec![
+                    Cell::new(&estimate.name),
+                    Cell::new(&estimate.count.to_string()),
+                    Cell::new(&Rounded(estimate.expected_proportion).to_string()),
+                    Cell::new(&format!(
+                        "[{}, {}]",
+                        Rounded(estimate.credible_interval.0),
+                        Rounded(estimate.credible_interval.1)
+                    )),
+                ]));
+            }
+            table.printstd();
+            info!(
+                "{name}: {} observations across {} categories",
+                belief.observation_count()?,
+                belief.categories.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Reports the beliefs about category frequencies with their most common category
+/// # Errors
+/// - If the number of observations of a belief is too large to count
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_categoricals(beliefs: &[(String, CategoricalBelief)]) -> Result<()> {
+    if beliefs.is_empty() {
+        info!("No categories have been saved");
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Name"),
+        Cell::new("Categories"),
+        Cell::new("Observations"),
+        Cell::new("Most Common"),
+    ]));
+    for (name, belief) in beliefs {
+        let most_common = belief
+            .categories
+            .iter()
+            .max_by(|a, b| a.concentration().total_cmp(&b.concentration()))
+            .map(|category| category.name.as_str())
+            .unwrap_or_default();
+        table.add_row(Row::new(vec![
+            Cell::new(name),
+            Cell::new(&belief.category_names().join(", ")),
+            Cell::new(&belief.observation_count()?.to_string()),
+            Cell::new(most_common),
+        ]));
+    }
+    table.printstd();
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temporary_path;
+    use crate::store::SledStore;
+
+    fn categories(specs: &[&str]) -> Result<Vec<CategoryPrior>> {
+        specs.iter().map(|spec| spec.parse()).collect()
+    }
+
+    fn counts(specs: &[&str]) -> Result<Vec<CategoryCount>> {
+        specs.iter().map(|spec| spec.parse()).collect()
+    }
+
+    #[test]
+    fn it_updates_a_dirichlet_belief_with_counts() -> Result<()> {
+        let mut belief = CategoricalBelief::new(&categories(&["db=2", "network", "deploy"])?, 1.0)?;
+        belief.update(&counts(&["network=4", "deploy", "network=2"])?)?;
+        assert_eq!(belief.observation_count()?, 7);
+        assert_eq!(
+            belief.history.last().map(|entry| entry.counts.clone()),
+            Some(BTreeMap::from([
+                ("deploy".to_owned(), 1),
+                ("network".to_owned(), 6)
+            ]))
+        );
+        let estimates = belief.estimates(0.95)?;
+        let names: Vec<&str> = estimates
+            .iter()
+            .map(|estimate| estimate.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["network", "db", "deploy"]);
+        // Concentrations of 7, 2 and 2 out of 11, with ties kept in the order the categories were given
+        assert!((estimates[0].expected_proportion - 7.0 / 11.0).abs() < 1e-12);
+        assert!((estimates[1].expected_proportion - 2.0 / 11.0).abs() < 1e-12);
+        let (low, high) = estimates[0].credible_interval;
+        assert!(low < 7.0 / 11.0 && 7.0 / 11.0 < high);
+
+        assert!(belief.update(&counts(&["dns"])?).is_err());
+        assert!(belief.update(&[]).is_err());
+        let too_many = format!("db={}", u64::MAX);
+        assert!(belief.update(&counts(&[&too_many])?).is_err());
+        assert!(belief.update(&counts(&[&too_many, "db"])?).is_err());
+        assert_eq!(belief.observation_count()?, 7);
+        assert!(belief.estimates(0.0).is_err());
+        assert!(CategoricalBelief::new(&categories(&["db"])?, 1.0).is_err());
+        assert!(CategoricalBelief::new(&categories(&["db", "db"])?, 1.0).is_err());
+        assert!(CategoricalBelief::new(&categories(&["db=0", "network"])?, 1.0).is_err());
+        assert!("=2".parse::<CategoryPrior>().is_err());
+        assert!("db=-1".parse::<CategoryCount>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_reduces_to_a_beta_binomial_with_two_categories() -> Result<()> {
+        // A uniform prior over two categories is a Beta(1, 1) on the proportion of either
+        let belief = CategoricalBelief::new(&categories(&["heads", "tails"])?, 1.0)?;
+        let (low, high) = belief.estimates(0.95)?[0].credible_interval;
+        assert!((low - 0.025).abs() < 1e-6);
+        assert!((high - 0.975).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn it_restarts_the_counts_but_keeps_the_history_when_categories_are_set_again() -> Result<()> {
+        let store = SledStore::open(&temporary_path("categorical"))?;
+        let belief = CategoricalBelief::new(&categories(&["db", "network"])?, 1.0)?;
+        save_categorical(&store, "root-causes", &belief)?;
+        assert!(record_counts(&store, "outages", &counts(&["db"])?).is_err());
+        let updated = record_counts(&store, "root-causes", &counts(&["db=3"])?)?;
+        assert!(record_counts(&store, "root-causes", &counts(&["dns"])?).is_err());
+        assert_eq!(find_categorical(&store, "root-causes")?, updated);
+
+        let widened = CategoricalBelief::new(&categories(&["db", "network", "deploy"])?, 0.5)?;
+        let reset = save_categorical(&store, "root-causes", &widened)?;
+        assert_eq!(reset.categories, widened.categories);
+        assert_eq!(reset.observation_count()?, 0);
+        let recorded: Vec<u64> = reset
+            .history
+            .iter()
+            .map(|entry| entry.counts.values().sum())
+            .collect();
+        assert_eq!(recorded, vec![0, 3, 0]);
+        delete_categorical(&store, "root-causes")?;
+        assert!(delete_categorical(&store, "root-causes").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+//! Comparison of two competing models of the same data by their Bayes factor, the ratio of the likelihood of the data under each.
+use crate::elicitation::{evidence_strength, EvidenceStrength};
+use crate::{log_odds, parse_rational, probability_from_log_odds, validate_probability};
+use crate::{OutputFormat, Rounded};
+use anyhow::{anyhow, Error, Result};
+use log::info;
+use num_traits::ToPrimitive;
+use prettytable::{format, Cell, Row, Table};
+use serde_json::json;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The likelihood of the data, or of a single observation, under a model.  For continuous data this is a density, which may be above 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ModelLikelihood(pub f64);
+
+impl FromStr for ModelLikelihood {
+    type Err = Error;
+
+    /// Parses a likelihood written as a decimal (`0.02`, `1.5e-8`) or a fraction (`1/50`)
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let input = s.trim();
+        let likelihood = parse_rational(input)?
+            .to_f64()
+            .ok_or_else(|| anyhow!("Invalid likelihood: {}", input))?;
+        if !likelihood.is_finite() || likelihood < 0.0_f64 {
+            return Err(anyhow!("A likelihood must be a number of at least 0"));
+        }
+        Ok(Self(likelihood))
+    }
+}
+
+impl Display for ModelLikelihood {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The comparison of two models of the same data
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ModelComparison {
+    /// The natural log of the marginal likelihood of the data under model A, ln P(D|A)
+    pub log_marginal_likelihood_a: f64,
+    /// The natural log of the marginal likelihood of the data under model B, ln P(D|B)
+    pub log_marginal_likelihood_b: f64,
+    /// How many times more likely the data are under model A than under model B, P(D|A) / P(D|B)
+    pub bayes_factor: f64,
+    /// The base 10 log of the Bayes factor
+    pub log10_bayes_factor: f64,
+    /// The prior probability of model A, P(A), model B having the remaining probability
+    pub prior_a: f64,
+    /// The posterior probability of model A given the data, P(A|D)
+    pub posterior_a: f64,
+    /// The posterior probability of model B given the data, P(B|D)
+    pub posterior_b: f64,
+}
+
+impl ModelComparison {
+    /// The marginal likelihood of the data under model A, P(D|A), which may round to 0 for a long series of observations
+    #[inline]
+    #[must_use]
+    pub fn marginal_likelihood_a(&self) -> f64 {
+        self.log_marginal_likelihood_a.exp()
+    }
+
+    /// The marginal likelihood of the data under model B, P(D|B), which may round to 0 for a long series of observations
+    #[inline]
+    #[must_use]
+    pub fn marginal_likelihood_b(&self) -> f64 {
+        self.log_marginal_likelihood_b.exp()
+    }
+}
+
+/// The strength of the evidence given by a Bayes factor on Kass and Raftery's scale: `not worth more than a bare mention` below 3, `positive` below 20, `strong` below 150, and `very strong` above.
+///
+/// A Bayes factor below 1 is as strong as its reciprocal.
+#[inline]
+#[must_use]
+pub fn kass_raftery_strength(bayes_factor: f64) -> &'static str {
+    let strength = if bayes_factor < 1.0_f64 {
+        bayes_factor.recip()
+    } else {
+        bayes_factor
+    };
+    match strength {
+        s if s.is_nan() || s < 3.0_f64 => "not worth more than a bare mention",
+        s if s < 20.0_f64 => "positive",
+        s if s < 150.0_f64 => "strong",
+        _ => "very strong",
+    }
+}
+
+/// Compares two models by the likelihoods of the data under each, given the prior probability of model A.
+///
+/// Each model is given either a single marginal likelihood of all the data or the likelihood of each of a series of independent observations, which are multiplied.  The likelihoods are combined in log space so that long series of observations do not underflow.
+/// # Errors
+/// - If no likelihoods are given, or not the same number for each model
+/// - If any likelihood is negative or not finite
+/// - If the prior is not a probability
+/// - If the data are impossible under both models
+/// - If the posterior probabilities are undefined, because the data are impossible under the only model with a prior probability
+#[inline]
+pub fn compare_models(
+    likelihoods_a: &[f64],
+    likelihoods_b: &[f64],
+    prior_a: f64,
+) -> Result<ModelComparison> {
+    if likelihoods_a.is_empty() || likelihoods_a.len() != likelihoods_b.len() {
+        return Err(anyhow!(
+            "Give a likelihood under each model for the data, or for each of the same observations"
+        ));
+    }
+    if likelihoods_a
+        .iter()
+        .chain(likelihoods_b)
+        .any(|likelihood| !likelihood.is_finite() || *likelihood < 0.0_f64)
+    {
+        return Err(anyhow!("A likelihood must be a number of at least 0"));
+    }
+    validate_probability(prior_a)?;
+    let log_marginal_likelihood_a: f64 =
+        likelihoods_a.iter().map(|likelihood| likelihood.ln()).sum();
+    let log_marginal_likelihood_b: f64 =
+        likelihoods_b.iter().map(|likelihood| likelihood.ln()).sum();
+    if log_marginal_likelihood_a == f64::NEG_INFINITY
+        && log_marginal_likelihood_b == f64::NEG_INFINITY
+    {
+        return Err(anyhow!("The data are impossible under both models"));
+    }
+    let log_bayes_factor = log_marginal_likelihood_a - log_marginal_likelihood_b;
+    let log_posterior_odds = log_odds(prior_a) + log_bayes_factor;
+    if log_posterior_odds.is_nan() {
+        return Err(anyhow!(
+            "The posterior probabilities are undefined, because the data are impossible under the only model with a prior probability"
+        ));
+    }
+    let posterior_a = probability_from_log_odds(log_posterior_odds);
+    Ok(ModelComparison {
+        log_marginal_likelihood_a,
+        log_marginal_likelihood_b,
+        bayes_factor: log_bayes_factor.exp(),
+        log10_bayes_factor: log_bayes_factor / std::f64::consts::LN_10,
+        prior_a,
+        posterior_a,
+        posterior_b: probability_from_log_odds(-log_posterior_odds),
+    })
+}
+
+/// Explains the Bayes factor in words, e.g. `The data are 12 times more likely under A than under B: strong evidence for A over B on Jeffreys' scale, and positive on Kass and Raftery's`
+#[inline]
+#[must_use]
+pub fn explain_comparison(name_a: &str, name_b: &str, comparison: &ModelComparison) -> String {
+    let bayes_factor = comparison.bayes_factor;
+    let (favoured, other, times) = if bayes_factor < 1.0_f64 {
+        (name_b, name_a, bayes_factor.recip())
+    } else {
+        (name_a, name_b, bayes_factor)
+    };
+    let strength = evidence_strength(bayes_factor);
+    if strength == EvidenceStrength::Neutral {
+        return format!("The data are equally likely under {name_a} and under {name_b}");
+    }
+    format!(
+        "The data are {} times more likely under {favoured} than under {other}: {strength} evidence for {favoured} over {other} on Jeffreys' scale, and {} on Kass and Raftery's",
+        Rounded(times),
+        kass_raftery_strength(bayes_factor)
+    )
+}
+
+/// Reports the marginal likelihoods, Bayes factor, strength of evidence and posterior probabilities of the models
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_model_comparison(
+    name_a: &str,
+    name_b: &str,
+    comparison: &ModelComparison,
+    output_format: &OutputFormat,
+) {
+    let explanation = explain_comparison(name_a, name_b, comparison);
+    let strength = evidence_strength(comparison.bayes_factor);
+    let kass_raftery = kass_raftery_strength(comparison.bayes_factor);
+    match *output_format {
+        OutputFormat::Json => {
+            let json = json!({
+                "models": [name_a, name_b],
+                "log_marginal_likelihood_a": comparison.log_marginal_likelihood_a,
+                "log_marginal_likelihood_b": comparison.log_marginal_likelihood_b,
+                "bayes_factor": comparison.bayes_factor,
+                "log10_bayes_factor": comparison.log10_bayes_factor,
+                "jeffreys_strength": strength.to_string(),
+                "kass_raftery_strength": kass_raftery,
+                "prior_a": comparison.prior_a,
+                "posterior_a": comparison.posterior_a,
+                "posterior_b": comparison.posterior_b,
+                "explanation": explanation,
+            });
+            info!("{}", json.to_string());
+        }
+        OutputFormat::Simple => {
+            let output = format!(
+                "
+                P(D|{name_a}) = {}
+                P(D|{name_b}) = {}
+                BF = {}
+                log10 BF = {}
+                P({name_a}) = {}
+                P({name_a}|D) = {}
+                P({name_b}|D) = {}
+                {explanation}
+                ",
+                Rounded(comparison.marginal_likelihood_a()),
+                Rounded(comparison.marginal_likelihood_b()),
+                Rounded(comparison.bayes_factor),
+                Rounded(comparison.log10_bayes_factor),
+                Rounded(comparison.prior_a),
+                Rounded(comparison.posterior_a),
+                Rounded(comparison.posterior_b),
+            );
+            info!("{output}");
+        }
+        OutputFormat::Table | OutputFormat::Icons => {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(Row::new(vec![
+                Cell::new("Name"),
+                Cell::new("Quantity"),
+                Cell::new("Value"),
+            ]));
+            let rows = [
+                (
+                    "Marginal Likelihood",
+                    format!("P(D|{name_a})"),
+                    Rounded(comparison.marginal_likelihood_a()).to_string(),
+                ),
+                (
+                    "Marginal Likelihood",
+                    format!("P(D|{name_b})"),
+                    Rounded(comparison.marginal_likelihood_b()).to_string(),
+                ),
+                (
+                    "Bayes Factor",
+                    format!("P(D|{name_a}) / P(D|{name_b})"),
+                    Rounded(comparison.bayes_factor).to_string(),
+                ),
+                (
+                    "Log Bayes Factor",
+                    "log10 BF".to_owned(),
+                    Rounded(comparison.log10_bayes_factor).to_string(),
+                ),
+                ("Jeffreys", "Strength".to_owned(), strength.to_string()),
+                (
+                    "Kass & Raftery",
+                    "Strength".to_owned(),
+                    kass_raftery.to_owned(),
+                ),
+                (
+                    "Prior Probability",
+                    format!("P({name_a})"),
+                    Rounded(comparison.prior_a).to_string(),
+                ),
+                (
+                    "Posterior Probability",
+                    format!("P({name_a}|D)"),
+                    Rounded(comparison.posterior_a).to_string(),
+                ),
+                (
+                    "Posterior Probability",
+                    format!("P({name_b}|D)"),
+                    Rounded(comparison.posterior_b).to_string(),
+                ),
+            ];
+            for (label, quantity, value) in rows {
+                table.add_row(Row::new(vec![
+                    Cell::new(label),
+                    Cell::new(&quantity),
+                    Cell::new(&value),
+                ]));
+            }
+            table.printstd();
+            info!("{explanation}");
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_compares_models_by_their_bayes_factor() -> Result<()> {
+        let comparison = compare_models(&[0.3, 0.4], &[0.1, 0.1], 0.5)?;
+        assert!((comparison.bayes_factor - 12.0).abs() < 1e-9);
+        assert!((comparison.log10_bayes_factor - 12.0_f64.log10()).abs() < 1e-12);
+        assert!((comparison.posterior_a - 12.0 / 13.0).abs() < 1e-12);
+        assert!((comparison.posterior_a + comparison.posterior_b - 1.0).abs() < 1e-12);
+        assert!((comparison.marginal_likelihood_a() - 0.12).abs() < 1e-12);
+        assert!(explain_comparison("A", "B", &comparison).ends_with(
+            "times more likely under A than under B: strong evidence for A over B on Jeffreys' scale, and positive on Kass and Raftery's"
+        ));
+        let comparison = compare_models(&[0.1], &[0.2], 0.5)?;
+        assert!(explain_comparison("A", "B", &comparison)
+            .contains("more likely under B than under A: weak evidence for B over A"));
+        let comparison = compare_models(&[0.2], &[0.2], 0.5)?;
+        assert_eq!(
+            explain_comparison("A", "B", &comparison),
+            "The data are equally likely under A and under B"
+        );
+
+        // Prior odds of 1:3 against A offset a Bayes factor of 3 in its favour
+        let comparison = compare_models(&[3.0], &[1.0], 0.25)?;
+        assert!((comparison.posterior_a - 0.5).abs() < 1e-12);
+
+        // Long series of observations do not underflow
+        let comparison = compare_models(&[1e-5; 200], &[2e-5; 200], 0.5)?;
+        assert!(comparison.marginal_likelihood_a() == 0.0);
+        assert!((comparison.log10_bayes_factor + 200.0 * 2.0_f64.log10()).abs() < 1e-9);
+        assert!(comparison.posterior_a > 0.0 && comparison.posterior_a < 1e-60);
+        assert_eq!(
+            kass_raftery_strength(comparison.bayes_factor),
+            "very strong"
+        );
+
+        let comparison = compare_models(&[0.0], &[0.2], 0.5)?;
+        assert!(comparison.posterior_b > 1.0 - 1e-12);
+        assert!(compare_models(&[0.0], &[0.0], 0.5).is_err());
+        assert!(compare_models(&[0.0], &[0.2], 1.0).is_err());
+        assert!(compare_models(&[0.1, 0.2], &[0.2], 0.5).is_err());
+        assert!(compare_models(&[], &[], 0.5).is_err());
+        assert!(compare_models(&[-0.1], &[0.2], 0.5).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_likelihoods_and_densities() -> Result<()> {
+        assert_eq!(ModelLikelihood::from_str("1/50")?, ModelLikelihood(0.02));
+        assert_eq!(ModelLikelihood::from_str(" 2.5 ")?, ModelLikelihood(2.5));
+        assert_eq!(
+            ModelLikelihood::from_str("1.5e-8")?,
+            ModelLikelihood(1.5e-8)
+        );
+        assert!(ModelLikelihood::from_str("-1").is_err());
+        assert!(ModelLikelihood::from_str("likely").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+//! Shell completion scripts generated from the command-line definition, extended to complete the names of saved hypotheses.
+use crate::store::open_store;
+use crate::Args;
+use anyhow::Result;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::fmt::Write as _;
+use std::io::Write;
+
+/// The name of the binary the completions are generated for
+const BIN_NAME: &str = "ask-bayes";
+/// The options whose values are names of hypotheses
+const NAME_OPTIONS: [&str; 4] = ["-n", "--name", "--for", "--into"];
+/// The subcommands whose first argument is the name of a hypothesis
+const NAME_SUBCOMMANDS: [&str; 6] = ["plot", "tag", "undo", "revert", "rename", "merge"];
+/// The command the completion scripts run to list the names of the saved hypotheses
+const NAMES_COMMAND: &str = "ask-bayes names 2>/dev/null";
+/// The zsh function completing the names of the saved hypotheses
+const ZSH_NAMES_FUNCTION: &str = "_ask-bayes_hypotheses";
+
+/// Generates the completion script for the shell.
+///
+/// Besides the options and subcommands, the bash, zsh and fish scripts complete `--name`, `--for`, `--into` and the first argument of `plot`, `tag`, `undo`, `revert`, `rename` and `merge` with the names of the saved hypotheses, which they list by running `ask-bayes names` whenever one is completed.  Scripts for other shells complete only the options and subcommands.
+/// # Errors
+/// - If the script cannot be written
+#[inline]
+pub fn write_completions(shell: Shell, out: &mut dyn Write) -> Result<()> {
+    let mut generated = Vec::new();
+    clap_complete::generate(shell, &mut Args::command(), BIN_NAME, &mut generated);
+    let mut script = String::from_utf8(generated)?;
+    #[allow(clippy::wildcard_enum_match_arm)]
+    match shell {
+        Shell::Bash => script.push_str(&bash_names()),
+        Shell::Zsh => script = zsh_names(&script)?,
+        Shell::Fish => script.push_str(&fish_names()?),
+        _ => {}
+    }
+    out.write_all(script.as_bytes())?;
+    Ok(())
+}
+
+/// Bash functions that complete the names of hypotheses before falling back to the generated completions
+fn bash_names() -> String {
+    let options = NAME_OPTIONS.join("|");
+    let subcommands = NAME_SUBCOMMANDS.join("|");
+    format!(
+        r#"
+_ask-bayes_hypotheses() {{
+    local cur prev
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "${{prev}}" in
+        {options})
+            ;;
+        {subcommands})
+            if [[ "${{cur}}" == -* ]] ; then
+                _ask-bayes "$@"
+                return 0
+            fi
+            ;;
+        *)
+            _ask-bayes "$@"
+            return 0
+            ;;
+    esac
+    local IFS=$'\n'
+    COMPREPLY=( $(compgen -W "$({NAMES_COMMAND})" -- "${{cur}}") )
+    return 0
+}}
+
+complete -F _ask-bayes_hypotheses -o bashdefault -o default ask-bayes
+"#
+    )
+}
+
+/// Fish completions for the names of hypotheses, added to the generated completions
+fn fish_names() -> Result<String> {
+    let mut script = String::from("\n");
+    for option in NAME_OPTIONS {
+        let flag = option.strip_prefix("--").map_or_else(
+            || format!("-s {}", &option[1..]),
+            |long| format!("-l {long}"),
+        );
+        writeln!(
+            script,
+            "complete -c ask-bayes {flag} -x -a '({NAMES_COMMAND})'"
+        )?;
+    }
+    writeln!(
+        script,
+        "complete -c ask-bayes -n \"__fish_seen_subcommand_from {}\" -f -a '({NAMES_COMMAND})'",
+        NAME_SUBCOMMANDS.join(" ")
+    )?;
+    Ok(script)
+}
+
+/// Whether the line of a generated zsh script describes an option taking the name of a hypothesis
+fn is_zsh_name_option(line: &str) -> bool {
+    let spec = line
+        .trim_start()
+        .trim_start_matches('\'')
+        .trim_start_matches('*');
+    let spec = if spec.starts_with('(') {
+        spec.split_once(')').map_or(spec, |(_, rest)| rest)
+    } else {
+        spec
+    };
+    NAME_OPTIONS.iter().any(|option| {
+        let separator = if option.starts_with("--") { '=' } else { '+' };
+        spec.starts_with(&format!("{option}{separator}["))
+    })
+}
+
+/// Patches the generated zsh script so that the options and arguments taking names of hypotheses complete them
+fn zsh_names(script: &str) -> Result<String> {
+    let mut patched = String::with_capacity(script.len());
+    let mut subcommand = "";
+    for line in script.lines() {
+        let trimmed = line.trim();
+        if let Some(label) = trimmed
+            .strip_prefix('(')
+            .and_then(|label| label.strip_suffix(')'))
+        {
+            subcommand = label;
+        }
+        let is_name_argument =
+            NAME_SUBCOMMANDS.contains(&subcommand) && trimmed.starts_with("':name -- ");
+        let line = if is_name_argument {
+            line.strip_suffix(":' \\").map_or_else(
+                || line.to_owned(),
+                |spec| format!("{spec}:{ZSH_NAMES_FUNCTION}' \\"),
+            )
+        } else if is_zsh_name_option(line) {
+            line.strip_suffix(": ' \\").map_or_else(
+                || line.to_owned(),
+                |spec| format!("{spec}:{ZSH_NAMES_FUNCTION}' \\"),
+            )
+        } else {
+            line.to_owned()
+        };
+        if line == "_ask-bayes \"$@\"" {
+            write!(
+                patched,
+                r#"(( $+functions[{ZSH_NAMES_FUNCTION}] )) ||
+{ZSH_NAMES_FUNCTION}() {{
+    local -a hypotheses
+    hypotheses=(${{(f)"$({NAMES_COMMAND})"}})
+    _wanted hypotheses expl 'hypothesis' compadd -a hypotheses
+}}
+
+"#
+            )?;
+        }
+        patched.push_str(&line);
+        patched.push('\n');
+    }
+    Ok(patched)
+}
+
+/// Lists the names of the saved hypotheses
+/// # Errors
+/// - If the database cannot be opened
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn hypothesis_names() -> Result<Vec<String>> {
+    Ok(open_store()?
+        .list_priors()?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    fn completions(shell: Shell) -> Result<String> {
+        let mut script = Vec::new();
+        write_completions(shell, &mut script)?;
+        Ok(String::from_utf8(script)?)
+    }
+
+    #[test]
+    fn it_completes_hypothesis_names_in_every_shell() -> Result<()> {
+        let bash = completions(Shell::Bash)?;
+        assert!(bash.contains("-n|--name|--for|--into)"));
+        assert!(bash.contains("complete -F _ask-bayes_hypotheses"));
+
+        let zsh = completions(Shell::Zsh)?;
+        assert!(zsh
+            .contains("'--name=[Name of the Hypothesis to update]:NAME:_ask-bayes_hypotheses' \\"));
+        assert!(zsh.contains("':name -- Name of the hypothesis to plot:_ask-bayes_hypotheses' \\"));
+        assert!(zsh.contains("':name -- Name of the evidence:' \\"));
+        assert!(zsh.contains(":HYPOTHESIS:_ask-bayes_hypotheses' \\"));
+        assert!(!zsh.contains(":SET_PRIOR:_ask-bayes_hypotheses"));
+        assert!(zsh.ends_with("_ask-bayes_hypotheses() {\n    local -a hypotheses\n    hypotheses=(${(f)\"$(ask-bayes names 2>/dev/null)\"})\n    _wanted hypotheses expl 'hypothesis' compadd -a hypotheses\n}\n\n_ask-bayes \"$@\"\n"));
+
+        let fish = completions(Shell::Fish)?;
+        assert!(fish.contains("complete -c ask-bayes -s n -x -a '(ask-bayes names 2>/dev/null)'"));
+        assert!(fish.contains("complete -c ask-bayes -l for -x -a '(ask-bayes names 2>/dev/null)'"));
+
+        assert!(!completions(Shell::PowerShell)?.contains("ask-bayes names"));
+        Ok(())
+    }
+}
@@ -0,0 +1,558 @@
+//! User configuration: defaults for updates, output preferences and named evidence presets, read from a TOML file.
+//!
+//! Settings are resolved in order of precedence: command-line flags, then environment variables, then the configuration file, with the defaults of the namespace of a hypothesis before the global defaults, then the built-in defaults.
+use crate::namespace::enclosing_namespaces;
+use crate::store::StoreLocation;
+use crate::{Evidence, OutputFormat, Probability};
+use anyhow::{anyhow, Result};
+use dirs::home_dir;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The probability used for the prior and likelihoods when neither a flag nor the configuration sets one
+const BUILT_IN_PROBABILITY: &str = "0.5";
+
+/// (De)serializes optional values through their `Display` and `FromStr` implementations
+mod from_str_option {
+    use super::{Deserialize, Deserializer, Display, FromStr, Serializer};
+
+    /// Serializes the value as its `Display` text, or as nothing if there is no value
+    #[allow(clippy::ref_option)]
+    pub(super) fn serialize<S: Serializer, T: Display>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match *value {
+            Some(ref value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserializes the value by parsing its text with `FromStr`, if there is one
+    pub(super) fn deserialize<'de, D: Deserializer<'de>, T>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| value.parse().map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// A probability written in the configuration file, either as a number or as text in any supported notation, e.g. `"3:1"`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ProbabilityValue {
+    /// A number, e.g. `0.75`
+    Number(f64),
+    /// Text in any supported notation, e.g. `"75%"`
+    Text(String),
+}
+
+impl ProbabilityValue {
+    /// Parses the probability
+    fn parse(self) -> Result<Probability> {
+        match self {
+            Self::Number(value) => value.to_string().parse(),
+            Self::Text(text) => text.parse(),
+        }
+    }
+}
+
+/// (De)serializes probabilities, written as numbers or text and saved as numbers
+mod probability {
+    use super::{Deserialize, Deserializer, Probability, ProbabilityValue, Serializer};
+
+    /// Serializes the probability as a number
+    pub(super) fn serialize<S: Serializer>(
+        value: &Probability,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(value.value())
+    }
+
+    /// Deserializes the probability from a number or from text in any supported notation
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Probability, D::Error> {
+        ProbabilityValue::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// (De)serializes optional probabilities, written as numbers or text and saved as numbers
+mod probability_option {
+    use super::{Deserialize, Deserializer, Probability, ProbabilityValue, Serializer};
+
+    /// Serializes the probability as a number, or as nothing if there is no probability
+    #[allow(clippy::ref_option)]
+    pub(super) fn serialize<S: Serializer>(
+        value: &Option<Probability>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match *value {
+            Some(ref value) => serializer.serialize_f64(value.value()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserializes the probability, if there is one, from a number or from text in any supported notation
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Probability>, D::Error> {
+        Option::<ProbabilityValue>::deserialize(deserializer)?
+            .map(|value| value.parse().map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Defaults for updating a hypothesis from the command line
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Defaults {
+    /// The prior probability of the hypothesis P(H)
+    #[serde(with = "probability_option", skip_serializing_if = "Option::is_none")]
+    pub prior: Option<Probability>,
+    /// The likelihood of the evidence P(E|H)
+    #[serde(with = "probability_option", skip_serializing_if = "Option::is_none")]
+    pub likelihood: Option<Probability>,
+    /// The likelihood of the evidence P(E|¬H)
+    #[serde(with = "probability_option", skip_serializing_if = "Option::is_none")]
+    pub likelihood_null: Option<Probability>,
+    /// Whether supporting evidence is observed
+    #[serde(with = "from_str_option", skip_serializing_if = "Option::is_none")]
+    pub evidence: Option<Evidence>,
+    /// The store holding saved hypotheses, e.g. `sqlite` or `dir:./beliefs`
+    #[serde(with = "from_str_option", skip_serializing_if = "Option::is_none")]
+    pub store: Option<StoreLocation>,
+}
+
+/// Defaults for updating the hypotheses in a namespace, taking precedence over the defaults of the namespaces enclosing it and the global defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct NamespaceDefaults {
+    /// The prior probability of the hypothesis P(H)
+    #[serde(with = "probability_option", skip_serializing_if = "Option::is_none")]
+    pub prior: Option<Probability>,
+    /// The likelihood of the evidence P(E|H)
+    #[serde(with = "probability_option", skip_serializing_if = "Option::is_none")]
+    pub likelihood: Option<Probability>,
+    /// The likelihood of the evidence P(E|¬H)
+    #[serde(with = "probability_option", skip_serializing_if = "Option::is_none")]
+    pub likelihood_null: Option<Probability>,
+    /// Whether supporting evidence is observed
+    #[serde(with = "from_str_option", skip_serializing_if = "Option::is_none")]
+    pub evidence: Option<Evidence>,
+}
+
+/// Output preferences
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct OutputSettings {
+    /// The output format of commands that report probabilities
+    #[serde(with = "from_str_option", skip_serializing_if = "Option::is_none")]
+    pub format: Option<OutputFormat>,
+    /// The number of decimal places to show numbers with in all output other than JSON.  Numbers are shown in full if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<usize>,
+}
+
+/// A named pair of likelihoods for evidence that is applied often, e.g. a flaky test failing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct EvidencePreset {
+    /// The likelihood of the evidence P(E|H)
+    #[serde(with = "probability")]
+    pub likelihood: Probability,
+    /// The likelihood of the evidence P(E|¬H)
+    #[serde(with = "probability")]
+    pub likelihood_null: Probability,
+    /// Whether the evidence is observed, if the preset always describes one outcome
+    #[serde(
+        default,
+        with = "from_str_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub evidence: Option<Evidence>,
+    /// What the evidence is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// The prior, likelihoods and evidence of an update, resolved from flags, a preset, the configuration and the built-in defaults
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ResolvedUpdate {
+    /// The prior probability of the hypothesis P(H)
+    pub prior: Probability,
+    /// The likelihood of the evidence P(E|H)
+    pub likelihood: Probability,
+    /// The likelihood of the evidence P(E|¬H)
+    pub likelihood_null: Probability,
+    /// Whether supporting evidence is observed
+    pub evidence: Evidence,
+}
+
+/// The user configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Config {
+    /// Defaults for updating a hypothesis
+    pub defaults: Defaults,
+    /// Output preferences
+    pub output: OutputSettings,
+    /// Named evidence presets, applied with `--preset`
+    pub evidence: BTreeMap<String, EvidencePreset>,
+    /// Defaults for updating the hypotheses in each namespace, e.g. `infra/db`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, NamespaceDefaults>,
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(toml::from_str(s)?)
+    }
+}
+
+impl Config {
+    /// The configuration file used when `--config` is not given: `$XDG_CONFIG_HOME/ask-bayes/config.toml` if `XDG_CONFIG_HOME` is set, otherwise `~/.config/ask-bayes/config.toml`
+    /// # Errors
+    /// - If the home directory cannot be found
+    #[inline]
+    pub fn default_path() -> Result<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(
+                || {
+                    home_dir()
+                        .map(|hd| hd.join(".config"))
+                        .ok_or_else(|| anyhow!("Could not find home directory"))
+                },
+                |dir| Ok(PathBuf::from(dir)),
+            )?;
+        Ok(config_dir.join("ask-bayes").join("config.toml"))
+    }
+
+    /// Loads the configuration from the file, or from the default file if none is given.  A missing default file is treated as an empty configuration.
+    /// # Errors
+    /// - If a file given explicitly does not exist
+    /// - If the file cannot be read
+    /// - If the file is not a valid configuration
+    #[inline]
+    #[cfg(not(tarpaulin_include))]
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = if let Some(path) = path {
+            path.to_path_buf()
+        } else {
+            let path = Self::default_path()?;
+            if !path.exists() {
+                return Ok(Self::default());
+            }
+            path
+        };
+        fs::read_to_string(&path)
+            .map_err(|error| {
+                anyhow!(
+                    "Could not read the configuration {}: {error}",
+                    path.display()
+                )
+            })?
+            .parse()
+            .map_err(|error| anyhow!("Invalid configuration {}: {error}", path.display()))
+    }
+
+    /// Gets a named evidence preset
+    /// # Errors
+    /// - If there is no preset with the name
+    #[inline]
+    pub fn preset(&self, name: &str) -> Result<&EvidencePreset> {
+        self.evidence.get(name).ok_or_else(|| {
+            let known = self.evidence.keys().cloned().collect::<Vec<_>>();
+            if known.is_empty() {
+                anyhow!("Unknown evidence preset {name}.  No presets are configured")
+            } else {
+                anyhow!(
+                    "Unknown evidence preset {name}.  Configured presets: {}",
+                    known.join(", ")
+                )
+            }
+        })
+    }
+
+    /// The configuration for updating the hypothesis: the defaults of each namespace enclosing it replace the global defaults, the innermost namespace taking precedence
+    #[inline]
+    #[must_use]
+    pub fn for_hypothesis(&self, name: &str) -> Self {
+        let mut config = self.clone();
+        for namespace in enclosing_namespaces(name).into_iter().rev() {
+            if let Some(defaults) = self.namespaces.get(namespace) {
+                let overlay = |configured: &mut Option<Probability>,
+                               from_namespace: &Option<Probability>| {
+                    if from_namespace.is_some() {
+                        configured.clone_from(from_namespace);
+                    }
+                };
+                overlay(&mut config.defaults.prior, &defaults.prior);
+                overlay(&mut config.defaults.likelihood, &defaults.likelihood);
+                overlay(
+                    &mut config.defaults.likelihood_null,
+                    &defaults.likelihood_null,
+                );
+                if defaults.evidence.is_some() {
+                    config.defaults.evidence.clone_from(&defaults.evidence);
+                }
+            }
+        }
+        config
+    }
+
+    /// Resolves the prior, likelihoods and evidence of an update.  Each comes from its flag if given, otherwise from the preset, otherwise from the configured defaults, otherwise from the built-in defaults: 0.5 for probabilities and observed evidence.
+    /// # Errors
+    /// - If the preset does not exist
+    #[inline]
+    pub fn resolve_update(
+        &self,
+        prior: Option<Probability>,
+        likelihood: Option<Probability>,
+        likelihood_null: Option<Probability>,
+        evidence: Option<Evidence>,
+        preset: Option<&str>,
+    ) -> Result<ResolvedUpdate> {
+        let preset = preset.map(|name| self.preset(name)).transpose()?;
+        let resolve = |flag: Option<Probability>,
+                       from_preset: Option<&Probability>,
+                       configured: &Option<Probability>|
+         -> Result<Probability> {
+            flag.or_else(|| from_preset.cloned())
+                .or_else(|| configured.clone())
+                .map_or_else(|| BUILT_IN_PROBABILITY.parse(), Ok)
+        };
+        Ok(ResolvedUpdate {
+            prior: resolve(prior, None, &self.defaults.prior)?,
+            likelihood: resolve(
+                likelihood,
+                preset.map(|preset| &preset.likelihood),
+                &self.defaults.likelihood,
+            )?,
+            likelihood_null: resolve(
+                likelihood_null,
+                preset.map(|preset| &preset.likelihood_null),
+                &self.defaults.likelihood_null,
+            )?,
+            evidence: evidence
+                .or_else(|| preset.and_then(|preset| preset.evidence.clone()))
+                .or_else(|| self.defaults.evidence.clone())
+                .unwrap_or(Evidence::Observed),
+        })
+    }
+
+    /// The output format: the flag if given, otherwise the configured format, otherwise a table
+    #[inline]
+    #[must_use]
+    pub fn output_format(&self, flag: Option<OutputFormat>) -> OutputFormat {
+        flag.or_else(|| self.output.format.clone())
+            .unwrap_or(OutputFormat::Table)
+    }
+
+    /// The store to use: the flag if given, otherwise `ASK_BAYES_STORE` if it is set, otherwise the configured store, otherwise the sled database in `~/.ask-bayes`
+    /// # Errors
+    /// - If `ASK_BAYES_STORE` does not name a valid store
+    /// - If the home directory cannot be found
+    #[inline]
+    pub fn store_location(&self, flag: Option<StoreLocation>) -> Result<StoreLocation> {
+        if let Some(location) = flag {
+            return Ok(location);
+        }
+        if let Ok(location) = std::env::var("ASK_BAYES_STORE") {
+            return location.parse();
+        }
+        self.defaults
+            .store
+            .clone()
+            .map_or_else(StoreLocation::default_location, Ok)
+    }
+
+    /// The effective configuration, with every default resolved, as TOML
+    /// # Errors
+    /// - If the store cannot be resolved
+    /// - If the configuration cannot be serialized
+    #[inline]
+    pub fn effective(&self, store: Option<StoreLocation>) -> Result<String> {
+        let update = self.resolve_update(None, None, None, None, None)?;
+        let effective = Self {
+            defaults: Defaults {
+                prior: Some(update.prior),
+                likelihood: Some(update.likelihood),
+                likelihood_null: Some(update.likelihood_null),
+                evidence: Some(update.evidence),
+                store: Some(self.store_location(store)?),
+            },
+            output: OutputSettings {
+                format: Some(self.output_format(None)),
+                precision: self.output.precision,
+            },
+            evidence: self.evidence.clone(),
+            namespaces: self.namespaces.clone(),
+        };
+        Ok(toml::to_string(&effective)?)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[defaults]
+likelihood = "3:1"
+evidence = "n"
+store = "dir:./beliefs"
+
+[output]
+format = "simple"
+precision = 3
+
+[evidence.ci-flake]
+likelihood = 0.3
+likelihood_null = 0.1
+description = "A flaky CI test failed"
+"#;
+
+    #[test]
+    fn it_parses_a_configuration() -> Result<()> {
+        let config: Config = CONFIG.parse()?;
+        assert_eq!(
+            config.defaults.likelihood.as_ref().map(Probability::value),
+            Some(0.75)
+        );
+        assert_eq!(config.defaults.evidence, Some(Evidence::NotObserved));
+        assert_eq!(
+            config.defaults.store,
+            Some(StoreLocation::Dir(PathBuf::from("./beliefs")))
+        );
+        assert_eq!(config.output.format, Some(OutputFormat::Simple));
+        assert_eq!(config.output.precision, Some(3));
+        assert_eq!(config.preset("ci-flake")?.likelihood.value(), 0.3);
+        assert!(config.preset("ci-fail").is_err());
+        assert!("[defaults]\npriro = 0.5".parse::<Config>().is_err());
+        assert!("[defaults]\nprior = 1.5".parse::<Config>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_resolves_flags_over_presets_over_defaults() -> Result<()> {
+        let config: Config = CONFIG.parse()?;
+        let update = config.resolve_update(None, None, None, None, None)?;
+        assert_eq!(update.prior.value(), 0.5);
+        assert_eq!(update.likelihood.value(), 0.75);
+        assert_eq!(update.likelihood_null.value(), 0.5);
+        assert_eq!(update.evidence, Evidence::NotObserved);
+
+        let update = config.resolve_update(None, None, None, None, Some("ci-flake"))?;
+        assert_eq!(update.likelihood.value(), 0.3);
+        assert_eq!(update.likelihood_null.value(), 0.1);
+        assert_eq!(update.evidence, Evidence::NotObserved);
+
+        let update = config.resolve_update(
+            Some("0.2".parse()?),
+            Some("0.9".parse()?),
+            None,
+            Some(Evidence::Observed),
+            Some("ci-flake"),
+        )?;
+        assert_eq!(update.prior.value(), 0.2);
+        assert_eq!(update.likelihood.value(), 0.9);
+        assert_eq!(update.likelihood_null.value(), 0.1);
+        assert_eq!(update.evidence, Evidence::Observed);
+
+        assert_eq!(config.output_format(None), OutputFormat::Simple);
+        assert_eq!(
+            config.output_format(Some(OutputFormat::Json)),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            config.store_location(Some(StoreLocation::Sled(PathBuf::from("db"))))?,
+            StoreLocation::Sled(PathBuf::from("db"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_applies_the_defaults_of_enclosing_namespaces() -> Result<()> {
+        let config: Config = r#"
+[defaults]
+prior = 0.5
+likelihood = 0.6
+
+[namespaces.infra]
+prior = 0.1
+likelihood_null = 0.2
+
+[namespaces."infra/db"]
+prior = "1 in 50"
+evidence = "n"
+"#
+        .parse()?;
+        let update = config
+            .for_hypothesis("infra/db/overloaded")
+            .resolve_update(None, None, None, None, None)?;
+        assert_eq!(update.prior.value(), 0.02);
+        assert_eq!(update.likelihood.value(), 0.6);
+        assert_eq!(update.likelihood_null.value(), 0.2);
+        assert_eq!(update.evidence, Evidence::NotObserved);
+
+        let update = config.for_hypothesis("infra/cache").resolve_update(
+            Some("0.3".parse()?),
+            None,
+            None,
+            None,
+            None,
+        )?;
+        assert_eq!(update.prior.value(), 0.3);
+        assert_eq!(update.evidence, Evidence::Observed);
+
+        let update = config
+            .for_hypothesis("infrastructure")
+            .resolve_update(None, None, None, None, None)?;
+        assert_eq!(update.prior.value(), 0.5);
+        assert!("[namespaces.infra]\nstore = \"sqlite\""
+            .parse::<Config>()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_round_trips_the_effective_configuration() -> Result<()> {
+        let config: Config = CONFIG.parse()?;
+        let effective: Config = config
+            .effective(Some(StoreLocation::Sled(PathBuf::from("db"))))?
+            .parse()?;
+        assert_eq!(
+            effective.defaults.prior.as_ref().map(Probability::value),
+            Some(0.5)
+        );
+        assert_eq!(
+            effective.defaults.store,
+            Some(StoreLocation::Sled(PathBuf::from("db")))
+        );
+        assert_eq!(effective.evidence, config.evidence);
+        Ok(())
+    }
+}
@@ -0,0 +1,643 @@
+//! A full-screen terminal dashboard of the saved hypotheses, for browsing their probabilities and histories and for applying evidence or setting priors with the keyboard.
+use crate::history::{first_entry_step, trajectory, Change, HistoryEntry};
+use crate::store::{open_store, Store};
+use crate::{
+    calculate_posterior_probability, parse_probability, record_change, record_observations,
+    Evidence, Observation, Rounded,
+};
+use anyhow::Result;
+use num_traits::ToPrimitive;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Axis, Block, Borders, Chart, Clear, Dataset, GraphType, List, ListItem, ListState, Paragraph,
+    Row, Table, TableState,
+};
+use ratatui::Frame;
+
+/// The width of the probability bars in the list of hypotheses
+const BAR_WIDTH: usize = 20;
+/// The keyboard shortcuts shown at the bottom of the dashboard
+const SHORTCUTS: &str = "\u{2191}/\u{2193} select  e apply evidence  p set prior  r reload  q quit";
+
+/// What the keyboard is currently being used for
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    /// Moving between the hypotheses
+    Browse,
+    /// Entering a new prior for the selected hypothesis
+    SetPrior {
+        /// The prior entered so far
+        input: String,
+    },
+    /// Entering evidence to apply to the selected hypothesis
+    ApplyEvidence(EvidenceForm),
+}
+
+/// The evidence being entered for the selected hypothesis
+#[derive(Debug, Clone, PartialEq)]
+struct EvidenceForm {
+    /// The likelihood P(E|H) entered so far
+    likelihood: String,
+    /// The likelihood P(E|¬H) entered so far
+    likelihood_null: String,
+    /// Whether the evidence was observed
+    evidence: Evidence,
+    /// The field being edited: the likelihood, the likelihood null or the evidence
+    focus: usize,
+}
+
+impl EvidenceForm {
+    /// The number of fields in the form
+    const FIELDS: usize = 3;
+
+    /// The text field being edited, if the focus is not on the evidence
+    const fn focused_input(&mut self) -> Option<&mut String> {
+        match self.focus {
+            0 => Some(&mut self.likelihood),
+            1 => Some(&mut self.likelihood_null),
+            _ => None,
+        }
+    }
+
+    /// The observation described by the form
+    /// # Errors
+    /// - If either likelihood is not a probability
+    fn observation(&self) -> Result<Observation> {
+        Ok(Observation::new(
+            parse_probability(&self.likelihood)?.value(),
+            parse_probability(&self.likelihood_null)?.value(),
+            self.evidence.clone(),
+        ))
+    }
+}
+
+/// The state of the dashboard: the saved hypotheses, the one selected and its history, and what the keyboard is being used for
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Dashboard {
+    /// The saved hypotheses with their prior probabilities, in alphabetical order
+    hypotheses: Vec<(String, f64)>,
+    /// The index of the selected hypothesis
+    selected: usize,
+    /// The history of the selected hypothesis, oldest first
+    history: Vec<HistoryEntry>,
+    /// What the keyboard is being used for
+    mode: Mode,
+    /// The outcome of the last action, shown in place of the shortcuts
+    status: Option<String>,
+}
+
+impl Dashboard {
+    /// Loads the saved hypotheses from the store, selecting the first
+    /// # Errors
+    /// - If the store cannot be read
+    #[inline]
+    pub fn load(store: &dyn Store) -> Result<Self> {
+        let mut dashboard = Self {
+            hypotheses: Vec::new(),
+            selected: 0,
+            history: Vec::new(),
+            mode: Mode::Browse,
+            status: None,
+        };
+        dashboard.reload(store)?;
+        Ok(dashboard)
+    }
+
+    /// The name and prior probability of the selected hypothesis, if any have been saved
+    #[inline]
+    #[must_use]
+    pub fn selected(&self) -> Option<&(String, f64)> {
+        self.hypotheses.get(self.selected)
+    }
+
+    /// Reads the hypotheses and the history of the selected hypothesis from the store again, keeping the same hypothesis selected where it still exists
+    fn reload(&mut self, store: &dyn Store) -> Result<()> {
+        let selected = self.selected().map(|(name, _)| name.clone());
+        self.hypotheses = store.list_priors()?;
+        self.selected = selected
+            .and_then(|selected| {
+                self.hypotheses
+                    .iter()
+                    .position(|(name, _)| *name == selected)
+            })
+            .unwrap_or_else(|| self.selected.min(self.hypotheses.len().saturating_sub(1)));
+        self.load_history(store)
+    }
+
+    /// Reads the history of the selected hypothesis from the store
+    fn load_history(&mut self, store: &dyn Store) -> Result<()> {
+        self.history = match self.selected() {
+            Some((name, _)) => store.get_history(name)?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        Ok(())
+    }
+
+    /// Selects the hypothesis `offset` places from the selected one, stopping at the first and last
+    fn select_by(&mut self, store: &dyn Store, offset: isize) -> Result<()> {
+        let last = self.hypotheses.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(offset).min(last);
+        self.load_history(store)
+    }
+
+    /// Handles a key pressed while the dashboard is shown.  Returns whether the dashboard should keep running.
+    ///
+    /// Evidence applied and priors set are saved to the store, and recorded in the history of the hypothesis, as soon as they are entered.
+    /// # Errors
+    /// - If the store cannot be read or written
+    #[inline]
+    #[allow(clippy::wildcard_enum_match_arm)]
+    pub fn handle_key(&mut self, store: &dyn Store, key: KeyEvent) -> Result<bool> {
+        match self.mode.clone() {
+            Mode::Browse => return self.browse(store, key.code),
+            Mode::SetPrior { mut input } => match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Enter => self.set_prior(store, &input)?,
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.mode = Mode::SetPrior { input };
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    self.mode = Mode::SetPrior { input };
+                }
+                _ => {}
+            },
+            Mode::ApplyEvidence(mut form) => match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Enter => self.apply_evidence(store, &form)?,
+                KeyCode::Tab | KeyCode::Down => {
+                    form.focus = (form.focus + 1) % EvidenceForm::FIELDS;
+                    self.mode = Mode::ApplyEvidence(form);
+                }
+                KeyCode::BackTab | KeyCode::Up => {
+                    form.focus = (form.focus + EvidenceForm::FIELDS - 1) % EvidenceForm::FIELDS;
+                    self.mode = Mode::ApplyEvidence(form);
+                }
+                KeyCode::Backspace => {
+                    if let Some(input) = form.focused_input() {
+                        input.pop();
+                    }
+                    self.mode = Mode::ApplyEvidence(form);
+                }
+                KeyCode::Char(c) => {
+                    match form.focused_input() {
+                        Some(input) => input.push(c),
+                        None => {
+                            form.evidence = match form.evidence {
+                                Evidence::Observed => Evidence::NotObserved,
+                                Evidence::NotObserved => Evidence::Observed,
+                            };
+                        }
+                    }
+                    self.mode = Mode::ApplyEvidence(form);
+                }
+                _ => {}
+            },
+        }
+        Ok(true)
+    }
+
+    /// Handles a key pressed while moving between the hypotheses
+    #[allow(clippy::wildcard_enum_match_arm)]
+    fn browse(&mut self, store: &dyn Store, code: KeyCode) -> Result<bool> {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Up | KeyCode::Char('k') => self.select_by(store, -1)?,
+            KeyCode::Down | KeyCode::Char('j') => self.select_by(store, 1)?,
+            KeyCode::Home | KeyCode::Char('g') => self.select_by(store, isize::MIN)?,
+            KeyCode::End | KeyCode::Char('G') => self.select_by(store, isize::MAX)?,
+            KeyCode::Char('r') => {
+                self.reload(store)?;
+                self.status = Some("Reloaded the hypotheses".to_owned());
+            }
+            KeyCode::Char('e' | 'p') if self.hypotheses.is_empty() => {
+                self.status = Some(
+                    "Save a hypothesis with --set-prior before using the dashboard".to_owned(),
+                );
+            }
+            KeyCode::Char('e') => {
+                self.status = None;
+                self.mode = Mode::ApplyEvidence(EvidenceForm {
+                    likelihood: String::new(),
+                    likelihood_null: String::new(),
+                    evidence: Evidence::Observed,
+                    focus: 0,
+                });
+            }
+            KeyCode::Char('p') => {
+                self.status = None;
+                self.mode = Mode::SetPrior {
+                    input: String::new(),
+                };
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /// Sets the prior of the selected hypothesis to the probability entered, or reports why it cannot be
+    fn set_prior(&mut self, store: &dyn Store, input: &str) -> Result<()> {
+        let Some((name, _)) = self.selected().cloned() else {
+            return Ok(());
+        };
+        match parse_probability(input) {
+            Ok(prior) => {
+                record_change(store, &name, &HistoryEntry::new(prior.value(), Change::Set))?;
+                self.status = Some(format!("Set P({name}) to {}", prior.value()));
+                self.mode = Mode::Browse;
+                self.reload(store)?;
+            }
+            Err(err) => self.status = Some(err.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Applies the evidence entered to the selected hypothesis, or reports why it cannot be
+    fn apply_evidence(&mut self, store: &dyn Store, form: &EvidenceForm) -> Result<()> {
+        let Some((name, prior)) = self.selected().cloned() else {
+            return Ok(());
+        };
+        let posterior_probability = form
+            .observation()
+            .and_then(|observation| record_observations(store, &name, prior, &[observation], None));
+        match posterior_probability {
+            Ok(posterior_probability) => {
+                self.status = Some(format!(
+                    "Updated P({name}) from {} to {}",
+                    Rounded(prior),
+                    Rounded(posterior_probability)
+                ));
+                self.mode = Mode::Browse;
+                self.reload(store)?;
+            }
+            Err(err) => self.status = Some(err.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Draws the dashboard: the hypotheses with probability bars, the chart and history of the selected hypothesis, the shortcuts or the outcome of the last action, and the form being filled in, if any
+    #[inline]
+    pub fn draw(&self, frame: &mut Frame<'_>) {
+        let [main, footer] = split(
+            Direction::Vertical,
+            frame.area(),
+            [Constraint::Min(0), Constraint::Length(1)],
+        );
+        let [list, details] = split(
+            Direction::Horizontal,
+            main,
+            [Constraint::Percentage(40), Constraint::Min(0)],
+        );
+        self.draw_hypotheses(frame, list);
+        self.draw_details(frame, details);
+        let footer_text = self.status.as_deref().unwrap_or(SHORTCUTS);
+        frame.render_widget(
+            Paragraph::new(footer_text).style(Style::default().add_modifier(Modifier::DIM)),
+            footer,
+        );
+        match self.mode {
+            Mode::Browse => {}
+            Mode::SetPrior { ref input } => self.draw_prior_form(frame, input),
+            Mode::ApplyEvidence(ref form) => self.draw_evidence_form(frame, form),
+        }
+    }
+
+    /// Draws the list of hypotheses with a bar showing each prior probability
+    fn draw_hypotheses(&self, frame: &mut Frame<'_>, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title(" Hypotheses ");
+        if self.hypotheses.is_empty() {
+            frame.render_widget(
+                Paragraph::new("No hypotheses have been saved").block(block),
+                area,
+            );
+            return;
+        }
+        let items: Vec<ListItem<'_>> = self
+            .hypotheses
+            .iter()
+            .map(|(name, probability)| {
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        probability_bar(*probability),
+                        Style::default().fg(Color::Cyan),
+                    ),
+                    Span::raw(format!(" {}  {name}", Rounded(*probability))),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    /// Draws the chart and history of the selected hypothesis
+    fn draw_details(&self, frame: &mut Frame<'_>, area: Rect) {
+        let Some((name, _)) = self.selected() else {
+            frame.render_widget(Block::default().borders(Borders::ALL), area);
+            return;
+        };
+        let [chart_area, history_area] = split(
+            Direction::Vertical,
+            area,
+            [Constraint::Percentage(50), Constraint::Min(0)],
+        );
+        let points: Vec<(f64, f64)> = trajectory(&self.history)
+            .iter()
+            .zip(0_u32..)
+            .map(|(point, step)| (f64::from(step), point.probability))
+            .collect();
+        let last_step = points
+            .last()
+            .map_or(0.0_f64, |&(step, _)| step)
+            .max(1.0_f64);
+        let dataset = Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&points);
+        let chart = Chart::new(vec![dataset])
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" P({name}) over {} steps ", points.len())),
+            )
+            .x_axis(Axis::default().bounds([0.0_f64, last_step]))
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0_f64, 1.0_f64])
+                    .labels(["0", "0.5", "1"]),
+            );
+        frame.render_widget(chart, chart_area);
+
+        let first_step = first_entry_step(&self.history);
+        let probabilities: Vec<String> = self
+            .history
+            .iter()
+            .map(|entry| Rounded(entry.probability).to_string())
+            .collect();
+        let probability_width = probabilities
+            .iter()
+            .map(|probability| probability.chars().count())
+            .fold("P(H)".len(), usize::max);
+        let rows = self.history.iter().zip(probabilities).enumerate().map(
+            |(index, (entry, probability))| {
+                Row::new(vec![
+                    (first_step + index).to_string(),
+                    entry.timestamp.format("%Y-%m-%d %H:%M").to_string(),
+                    probability,
+                    entry.description(),
+                ])
+            },
+        );
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(4),
+                Constraint::Length(16),
+                Constraint::Length(u16::try_from(probability_width).unwrap_or(u16::MAX)),
+                Constraint::Min(10),
+            ],
+        )
+        .header(
+            Row::new(vec!["Step", "Time (UTC)", "P(H)", "Change"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title(" History "));
+        // Selecting the latest step scrolls the table so that the most recent changes are shown
+        let mut state = TableState::default().with_selected(self.history.len().checked_sub(1));
+        frame.render_stateful_widget(table, history_area, &mut state);
+    }
+
+    /// Draws the form for setting the prior of the selected hypothesis
+    fn draw_prior_form(&self, frame: &mut Frame<'_>, input: &str) {
+        let Some((name, prior)) = self.selected() else {
+            return;
+        };
+        let lines = vec![
+            Line::from(format!("Currently P({name}) = {}", Rounded(*prior))),
+            Line::from(format!("New P({name}): {input}_")),
+            Line::from(""),
+            Line::from("Enter to save, Esc to cancel"),
+        ];
+        draw_popup(frame, &format!(" Set the prior of {name} "), lines);
+    }
+
+    /// Draws the form for applying evidence to the selected hypothesis, previewing the posterior once the likelihoods are valid
+    fn draw_evidence_form(&self, frame: &mut Frame<'_>, form: &EvidenceForm) {
+        let Some((name, prior)) = self.selected() else {
+            return;
+        };
+        let marker = |field: usize| if form.focus == field { "> " } else { "  " };
+        let cursor = |field: usize| if form.focus == field { "_" } else { "" };
+        let preview = form
+            .observation()
+            .and_then(|observation| {
+                calculate_posterior_probability(
+                    *prior,
+                    observation.likelihood,
+                    observation.likelihood_null,
+                    &observation.evidence,
+                    name,
+                )
+            })
+            .map_or_else(
+                |_| String::new(),
+                |posterior| {
+                    format!(
+                        "P({name}) would change from {} to {}",
+                        Rounded(*prior),
+                        Rounded(posterior)
+                    )
+                },
+            );
+        let lines = vec![
+            Line::from(format!(
+                "{}P(E|{name}): {}{}",
+                marker(0),
+                form.likelihood,
+                cursor(0)
+            )),
+            Line::from(format!(
+                "{}P(E|\u{ac}{name}): {}{}",
+                marker(1),
+                form.likelihood_null,
+                cursor(1)
+            )),
+            Line::from(format!("{}Evidence: {}", marker(2), form.evidence)),
+            Line::from(""),
+            Line::from(preview),
+            Line::from("Tab to move, Space toggles the evidence, Enter to apply, Esc to cancel"),
+        ];
+        draw_popup(frame, &format!(" Apply evidence to {name} "), lines);
+    }
+}
+
+/// Splits the area in two along the direction
+fn split(direction: Direction, area: Rect, constraints: [Constraint; 2]) -> [Rect; 2] {
+    Layout::default()
+        .direction(direction)
+        .constraints(constraints)
+        .areas(area)
+}
+
+/// Draws the lines in a bordered box over the middle of the dashboard
+fn draw_popup(frame: &mut Frame<'_>, title: &str, lines: Vec<Line<'_>>) {
+    let area = frame.area();
+    let height = u16::try_from(lines.len() + 2)
+        .unwrap_or(u16::MAX)
+        .min(area.height);
+    let width = area.width.saturating_mul(3) / 4;
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        popup,
+    );
+}
+
+/// A bar `BAR_WIDTH` characters wide, filled in proportion to the probability
+fn probability_bar(probability: f64) -> String {
+    let filled = (probability.clamp(0.0_f64, 1.0_f64) * BAR_WIDTH.to_f64().unwrap_or_default())
+        .round()
+        .to_usize()
+        .unwrap_or_default()
+        .min(BAR_WIDTH);
+    format!(
+        "{}{}",
+        "\u{2588}".repeat(filled),
+        "\u{2591}".repeat(BAR_WIDTH - filled)
+    )
+}
+
+/// Opens a full-screen dashboard of the saved hypotheses.
+///
+/// The hypotheses are listed with bars showing their prior probabilities, and the chart and history of the selected hypothesis are shown beside them.  Evidence can be applied with `e` and the prior set with `p`, and the dashboard is closed with `q`.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the terminal cannot be drawn on or read from
+/// - If the store cannot be read or written
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn run_dashboard() -> Result<()> {
+    let store = open_store()?;
+    let mut dashboard = Dashboard::load(&*store)?;
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<()> {
+        loop {
+            terminal.draw(|frame| dashboard.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !dashboard.handle_key(&*store, key)? {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temporary_path;
+    use crate::store::SledStore;
+    use ratatui::backend::TestBackend;
+    use ratatui::crossterm::event::KeyModifiers;
+    use ratatui::Terminal;
+
+    fn press(dashboard: &mut Dashboard, store: &dyn Store, keys: &str) -> Result<bool> {
+        let mut running = true;
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\t' => KeyCode::Tab,
+                '\u{1b}' => KeyCode::Esc,
+                '\u{8}' => KeyCode::Backspace,
+                c => KeyCode::Char(c),
+            };
+            running = dashboard.handle_key(store, KeyEvent::new(code, KeyModifiers::NONE))?;
+        }
+        Ok(running)
+    }
+
+    fn screen(dashboard: &Dashboard) -> Result<String> {
+        let mut terminal = Terminal::new(TestBackend::new(100, 24))?;
+        terminal.draw(|frame| dashboard.draw(frame))?;
+        Ok(terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(100)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    #[test]
+    fn it_applies_evidence_and_sets_priors_from_the_keyboard() -> Result<()> {
+        let store = SledStore::open(&temporary_path("dashboard"))?;
+        let mut dashboard = Dashboard::load(&store)?;
+        assert!(screen(&dashboard)?.contains("No hypotheses have been saved"));
+        assert!(press(&mut dashboard, &store, "e")?);
+        assert_eq!(dashboard.mode, Mode::Browse);
+
+        record_change(&store, "rain", &HistoryEntry::new(0.5, Change::Set))?;
+        record_change(&store, "snow", &HistoryEntry::new(0.1, Change::Set))?;
+        press(&mut dashboard, &store, "r")?;
+        assert!(screen(&dashboard)?.contains(&format!(
+            "{}{} 0.1  snow",
+            "\u{2588}".repeat(2),
+            "\u{2591}".repeat(18)
+        )));
+
+        press(&mut dashboard, &store, "e0.8\t0.2\n")?;
+        assert!((store.get_prior("rain")?.unwrap_or_default() - 0.8).abs() < 1e-9);
+        assert_eq!(dashboard.history.len(), 2);
+        assert!(screen(&dashboard)?.contains("Updated P(rain) from 0.5 to 0.8"));
+
+        press(&mut dashboard, &store, "jp2\n")?;
+        assert!(matches!(dashboard.mode, Mode::SetPrior { .. }));
+        assert_eq!(store.get_prior("snow")?, Some(0.1));
+        press(&mut dashboard, &store, "\u{8}\u{1b}")?;
+        press(&mut dashboard, &store, "p1/4\n")?;
+        assert_eq!(store.get_prior("snow")?, Some(0.25));
+        assert_eq!(dashboard.selected(), Some(&("snow".to_owned(), 0.25)));
+
+        press(&mut dashboard, &store, "ke0.8\t0.2\t \n")?;
+        assert!((store.get_prior("rain")?.unwrap_or_default() - 0.5).abs() < 1e-9);
+        assert!(!press(&mut dashboard, &store, "q")?);
+        Ok(())
+    }
+
+    #[test]
+    fn it_numbers_the_history_from_the_prior_that_was_updated() -> Result<()> {
+        let store = SledStore::open(&temporary_path("dashboard-steps"))?;
+        record_observations(
+            &store,
+            "flood",
+            0.5_f64,
+            &[Observation::new(0.8_f64, 0.2_f64, Evidence::Observed)],
+            None,
+        )?;
+        let dashboard = Dashboard::load(&store)?;
+        let timestamp = dashboard.history[0].timestamp.format("%Y-%m-%d %H:%M");
+        let screen = screen(&dashboard)?;
+        assert!(screen.contains(&format!("1    {timestamp}")));
+        assert!(!screen.contains(&format!("0    {timestamp}")));
+        Ok(())
+    }
+}
@@ -0,0 +1,468 @@
+//! Decision analysis for choosing between actions given the probability of a hypothesis.
+use crate::store::{find_record, open_store, remove_record, set_record};
+use crate::{negate, OutputFormat, Rounded};
+use anyhow::{anyhow, Error, Result};
+use log::info;
+use prettytable::{format, Cell, Row, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The kind of metadata holding the actions attached to each hypothesis
+pub(crate) const ACTIONS_METADATA: &str = "actions";
+
+/// An action that can be taken, along with its utility when the hypothesis is true or false
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Action {
+    /// The name of the action
+    pub name: String,
+    /// The utility of taking the action if the hypothesis is true U(A|H)
+    pub utility_if_true: f64,
+    /// The utility of taking the action if the hypothesis is false U(A|¬H)
+    pub utility_if_false: f64,
+}
+
+impl Action {
+    /// Creates a new action
+    #[inline]
+    #[must_use]
+    pub const fn new(name: String, utility_if_true: f64, utility_if_false: f64) -> Self {
+        Self {
+            name,
+            utility_if_true,
+            utility_if_false,
+        }
+    }
+
+    /// The expected utility of the action given the probability of the hypothesis.  Ex. P(H) * U(A|H) + P(¬H) * U(A|¬H)
+    #[inline]
+    #[must_use]
+    pub fn expected_utility(&self, probability: f64) -> f64 {
+        self.utility_if_true
+            .mul_add(probability, self.utility_if_false * negate(probability))
+    }
+
+    /// How much the expected utility grows as the probability of the hypothesis grows.  Ex. U(A|H) - U(A|¬H)
+    fn slope(&self) -> f64 {
+        self.utility_if_true - self.utility_if_false
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    /// Parses an action in the form `name=utility_if_true,utility_if_false`
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, utilities) = s.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "Invalid action: {}.  Expected name=utility_if_true,utility_if_false",
+                s
+            )
+        })?;
+        let (utility_if_true, utility_if_false) = utilities.split_once(',').ok_or_else(|| {
+            anyhow!(
+                "Invalid action: {}.  Expected name=utility_if_true,utility_if_false",
+                s
+            )
+        })?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!(
+                "Invalid action: {}.  The action must have a name",
+                s
+            ));
+        }
+        let utility_if_true = utility_if_true.trim().parse::<f64>()?;
+        let utility_if_false = utility_if_false.trim().parse::<f64>()?;
+        if !utility_if_true.is_finite() || !utility_if_false.is_finite() {
+            return Err(anyhow!("Invalid action: {}.  Utilities must be finite", s));
+        }
+        Ok(Self::new(
+            name.to_owned(),
+            utility_if_true,
+            utility_if_false,
+        ))
+    }
+}
+
+impl Display for Action {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}={},{}",
+            self.name, self.utility_if_true, self.utility_if_false
+        )
+    }
+}
+
+/// A probability at which the best action changes
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct DecisionThreshold {
+    /// The probability of the hypothesis at which both actions have the same expected utility
+    pub probability: f64,
+    /// The best action just below the threshold
+    pub below: String,
+    /// The best action just above the threshold
+    pub above: String,
+}
+
+/// The expected utility of each action at a given probability of the hypothesis
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct DecisionAnalysis {
+    /// The probability of the hypothesis the actions were evaluated at
+    pub probability: f64,
+    /// The expected utility of each action, in the order the actions were given
+    pub expected_utilities: Vec<(String, f64)>,
+    /// The action with the highest expected utility
+    pub best_action: String,
+    /// The expected utility of the best action
+    pub best_expected_utility: f64,
+    /// The probabilities at which the best action changes, in increasing order
+    pub thresholds: Vec<DecisionThreshold>,
+}
+
+/// Evaluates the expected utility of each action at the given probability of the hypothesis
+/// # Errors
+/// - If no actions are given
+/// - If the probability is not between 0 and 1
+#[inline]
+pub fn analyze_decision(probability: f64, actions: &[Action]) -> Result<DecisionAnalysis> {
+    crate::validate_probability(probability)?;
+    let expected_utilities = actions
+        .iter()
+        .map(|action| (action.name.clone(), action.expected_utility(probability)))
+        .collect::<Vec<_>>();
+    let best_action = best_action(probability, actions)
+        .ok_or_else(|| anyhow!("At least one action is required"))?;
+    Ok(DecisionAnalysis {
+        probability,
+        expected_utilities,
+        best_action: best_action.name.clone(),
+        best_expected_utility: best_action.expected_utility(probability),
+        thresholds: decision_thresholds(actions),
+    })
+}
+
+/// The action with the highest expected utility at the given probability.  Ties are broken in favour of the action given first.
+fn best_action(probability: f64, actions: &[Action]) -> Option<&Action> {
+    actions
+        .iter()
+        .fold(None, |best: Option<&Action>, action| match best {
+            Some(best)
+                if best.expected_utility(probability) >= action.expected_utility(probability) =>
+            {
+                Some(best)
+            }
+            _ => Some(action),
+        })
+}
+
+/// The probabilities between 0 and 1 at which the best action changes
+fn decision_thresholds(actions: &[Action]) -> Vec<DecisionThreshold> {
+    let mut thresholds = Vec::new();
+    let mut probability = 0.0_f64;
+    // When several actions are best at P(H) = 0, the one that grows fastest stays best the longest
+    let mut current = match actions
+        .iter()
+        .fold(None, |best: Option<&Action>, action| match best {
+            Some(best)
+                if best.utility_if_false > action.utility_if_false
+                    || (best.utility_if_false - action.utility_if_false).abs() < f64::EPSILON
+                        && best.slope() >= action.slope() =>
+            {
+                Some(best)
+            }
+            _ => Some(action),
+        }) {
+        Some(action) => action,
+        None => return thresholds,
+    };
+    loop {
+        // The next action to take over is the one whose expected utility first overtakes the current best as P(H) grows
+        let next = actions
+            .iter()
+            .filter(|action| action.slope() > current.slope())
+            .map(|action| {
+                let crossing = (current.utility_if_false - action.utility_if_false)
+                    / (action.slope() - current.slope());
+                (crossing, action)
+            })
+            .filter(|&(crossing, _)| crossing > probability && crossing < 1.0_f64)
+            .fold(
+                None,
+                |next: Option<(f64, &Action)>, (crossing, action)| match next {
+                    Some((next_crossing, next_action))
+                        if next_crossing < crossing
+                            || (next_crossing - crossing).abs() < f64::EPSILON
+                                && next_action.slope() >= action.slope() =>
+                    {
+                        Some((next_crossing, next_action))
+                    }
+                    _ => Some((crossing, action)),
+                },
+            );
+        match next {
+            Some((crossing, action)) => {
+                thresholds.push(DecisionThreshold {
+                    probability: crossing,
+                    below: current.name.clone(),
+                    above: action.name.clone(),
+                });
+                probability = crossing;
+                current = action;
+            }
+            None => return thresholds,
+        }
+    }
+}
+
+/// Gets the actions attached to the hypothesis from the database.
+/// # Errors
+/// - If the database cannot be opened
+/// - If no actions are attached to the hypothesis
+/// - If the saved actions cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn get_actions(name: &str) -> Result<Vec<Action>> {
+    find_actions(name)?.ok_or_else(|| anyhow!("Could not find actions for hypothesis {name}"))
+}
+
+/// Gets the actions attached to the hypothesis from the database, or `None` if no actions are attached to it.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved actions cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn find_actions(name: &str) -> Result<Option<Vec<Action>>> {
+    find_record(&*open_store()?, ACTIONS_METADATA, name)
+}
+
+/// Attaches the actions to the hypothesis, saving them to the database.  Replaces any previously attached actions.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the actions cannot be inserted into the database
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn set_actions(name: &str, actions: &[Action]) -> Result<()> {
+    open_store()?.apply(&[set_record(ACTIONS_METADATA, name, &actions)?])
+}
+
+/// Removes the actions attached to the hypothesis from the database
+/// # Errors
+/// - If the database cannot be opened
+/// - If the actions cannot be removed from the database
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn remove_actions(name: &str) -> Result<()> {
+    open_store()?.apply(&[remove_record(ACTIONS_METADATA, name)])
+}
+
+/// Reports the expected utility of each action, the best action, and the thresholds at which the best action changes.
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_decision_analysis(
+    name: &str,
+    analysis: &DecisionAnalysis,
+    output_format: &OutputFormat,
+) {
+    match *output_format {
+        OutputFormat::Table | OutputFormat::Icons => {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(Row::new(vec![
+                Cell::new("Action"),
+                Cell::new("Expected Utility"),
+                Cell::new("Best"),
+            ]));
+            for (action, expected_utility) in &analysis.expected_utilities {
+                table.add_row(Row::new(vec![
+                    Cell::new(action),
+                    Cell::new(&Rounded(*expected_utility).to_string()),
+                    Cell::new(if *action == analysis.best_action {
+                        "*"
+                    } else {
+                        ""
+                    }),
+                ]));
+            }
+            info!(
+                "Expected utilities at P({name}) = {}",
+                Rounded(analysis.probability)
+            );
+            table.printstd();
+
+            if !analysis.thresholds.is_empty() {
+                let mut thresholds = Table::new();
+                thresholds.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+                thresholds.set_titles(Row::new(vec![
+                    Cell::new(&format!("P({name})")),
+                    Cell::new("Best Below"),
+                    Cell::new("Best Above"),
+                ]));
+                for threshold in &analysis.thresholds {
+                    thresholds.add_row(Row::new(vec![
+                        Cell::new(&Rounded(threshold.probability).to_string()),
+                        Cell::new(&threshold.below),
+                        Cell::new(&threshold.above),
+                    ]));
+                }
+                thresholds.printstd();
+            }
+        }
+        OutputFormat::Json => {
+            let json = json!({
+                "name": name,
+                "probability": analysis.probability,
+                "expected_utilities": analysis.expected_utilities.iter().map(|(action, expected_utility)| json!({
+                    "action": action,
+                    "expected_utility": expected_utility,
+                })).collect::<Vec<_>>(),
+                "best_action": analysis.best_action,
+                "thresholds": analysis.thresholds.iter().map(|threshold| json!({
+                    "probability": threshold.probability,
+                    "below": threshold.below,
+                    "above": threshold.above,
+                })).collect::<Vec<_>>(),
+            });
+
+            info!("{}", json.to_string());
+        }
+        OutputFormat::Simple => {
+            let mut lines = analysis
+                .expected_utilities
+                .iter()
+                .map(|(action, expected_utility)| {
+                    format!(
+                        "EU({action}|P({name}) = {}) = {}",
+                        Rounded(analysis.probability),
+                        Rounded(*expected_utility)
+                    )
+                })
+                .collect::<Vec<_>>();
+            lines.push(format!("Best action: {}", analysis.best_action));
+            lines.extend(analysis.thresholds.iter().map(|threshold| {
+                format!(
+                    "P({name}) = {}: {} -> {}",
+                    Rounded(threshold.probability),
+                    threshold.below,
+                    threshold.above
+                )
+            }));
+            let output = format!(
+                "
+                {}
+                ",
+                lines.join("\n                ")
+            );
+            info!("{output}");
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    fn epsilon_compare(a: f64, b: f64) -> bool {
+        (a - b).abs() < f64::EPSILON
+    }
+
+    fn actions() -> Vec<Action> {
+        vec![
+            Action::new("wait".to_owned(), 0.0_f64, 0.0_f64),
+            Action::new("treat".to_owned(), 100.0_f64, -20.0_f64),
+            Action::new("operate".to_owned(), 150.0_f64, -100.0_f64),
+        ]
+    }
+
+    #[test]
+    fn it_parses_a_valid_action() -> Result<()> {
+        let result = Action::from_str("treat=100,-20")?;
+        assert_eq!(
+            result,
+            Action::new("treat".to_owned(), 100.0_f64, -20.0_f64)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_parse_an_invalid_action() {
+        assert!(Action::from_str("treat").is_err());
+        assert!(Action::from_str("treat=100").is_err());
+        assert!(Action::from_str("=100,-20").is_err());
+        assert!(Action::from_str("treat=a,b").is_err());
+        assert!(Action::from_str("treat=inf,0").is_err());
+    }
+
+    #[test]
+    fn it_displays_an_action() {
+        let action = Action::new("treat".to_owned(), 100.0_f64, -20.5_f64);
+        assert_eq!(action.to_string(), "treat=100,-20.5");
+    }
+
+    #[test]
+    fn it_calculates_the_expected_utility_of_each_action() -> Result<()> {
+        let result = analyze_decision(0.25_f64, &actions())?;
+        assert!(epsilon_compare(result.expected_utilities[0].1, 0.0_f64));
+        assert!(epsilon_compare(result.expected_utilities[1].1, 10.0_f64));
+        assert!(epsilon_compare(result.expected_utilities[2].1, -37.5_f64));
+        assert_eq!(result.best_action, "treat");
+        Ok(())
+    }
+
+    #[test]
+    fn it_calculates_the_thresholds_at_which_the_best_action_changes() -> Result<()> {
+        let result = analyze_decision(0.5_f64, &actions())?;
+        assert_eq!(result.thresholds.len(), 2);
+        assert!(epsilon_compare(
+            result.thresholds[0].probability,
+            1.0_f64 / 6.0_f64
+        ));
+        assert_eq!(result.thresholds[0].below, "wait");
+        assert_eq!(result.thresholds[0].above, "treat");
+        assert!(epsilon_compare(
+            result.thresholds[1].probability,
+            80.0_f64 / 130.0_f64
+        ));
+        assert_eq!(result.thresholds[1].below, "treat");
+        assert_eq!(result.thresholds[1].above, "operate");
+        Ok(())
+    }
+
+    #[test]
+    fn it_skips_actions_that_are_never_best() -> Result<()> {
+        let mut actions = actions();
+        actions.push(Action::new("ignore".to_owned(), -10.0_f64, -10.0_f64));
+        let result = analyze_decision(0.9_f64, &actions)?;
+        assert_eq!(result.best_action, "operate");
+        assert!(result
+            .thresholds
+            .iter()
+            .all(|threshold| threshold.below != "ignore" && threshold.above != "ignore"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_has_no_thresholds_when_one_action_dominates() -> Result<()> {
+        let actions = vec![
+            Action::new("good".to_owned(), 10.0_f64, 10.0_f64),
+            Action::new("bad".to_owned(), 0.0_f64, 0.0_f64),
+        ];
+        let result = analyze_decision(0.5_f64, &actions)?;
+        assert_eq!(result.best_action, "good");
+        assert!(result.thresholds.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_analyze_a_decision_without_actions() {
+        assert!(analyze_decision(0.5_f64, &[]).is_err());
+    }
+}
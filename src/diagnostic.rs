@@ -0,0 +1,456 @@
+//! Evaluation of diagnostic tests from their sensitivity, specificity and the prevalence of the condition.
+use crate::icon_array::render_icon_array;
+use crate::{calculate_posterior_probability, negate, Evidence, OutputFormat, Rounded};
+use anyhow::Result;
+use console::colors_enabled;
+use log::info;
+use prettytable::{format, Cell, Row, Table};
+use serde_json::json;
+
+/// The performance of a diagnostic test for a condition with a given prevalence
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct DiagnosticAnalysis {
+    /// The probability that a case with the condition tests positive, i.e. P(E|H)
+    pub sensitivity: f64,
+    /// The probability that a case without the condition tests negative, i.e. P(¬E|¬H)
+    pub specificity: f64,
+    /// The base rate of the condition, i.e. P(H)
+    pub prevalence: f64,
+    /// The positive predictive value P(H|E), if a positive test is possible
+    pub positive_predictive_value: Option<f64>,
+    /// The negative predictive value P(¬H|¬E), if a negative test is possible
+    pub negative_predictive_value: Option<f64>,
+    /// The probability that a case without the condition tests positive, i.e. P(E|¬H)
+    pub false_positive_rate: f64,
+    /// The probability that a case with the condition tests negative, i.e. P(¬E|H)
+    pub false_negative_rate: f64,
+    /// How much more likely a positive test is with the condition than without, i.e. P(E|H) / P(E|¬H)
+    pub positive_likelihood_ratio: Option<f64>,
+    /// How much more likely a negative test is with the condition than without, i.e. P(¬E|H) / P(¬E|¬H)
+    pub negative_likelihood_ratio: Option<f64>,
+}
+
+/// The expected number of cases in each cell of the confusion matrix for a population
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct NaturalFrequencies {
+    /// The number of cases in the population
+    pub population: f64,
+    /// The number of cases with the condition that test positive
+    pub true_positives: f64,
+    /// The number of cases with the condition that test negative
+    pub false_negatives: f64,
+    /// The number of cases without the condition that test positive
+    pub false_positives: f64,
+    /// The number of cases without the condition that test negative
+    pub true_negatives: f64,
+}
+
+impl NaturalFrequencies {
+    /// The expected number of cases in each cell when evidence with the given likelihoods is looked for in the population
+    #[inline]
+    #[must_use]
+    pub fn new(prior: f64, likelihood: f64, likelihood_null: f64, population: f64) -> Self {
+        let with_condition = population * prior;
+        let without_condition = population - with_condition;
+        Self {
+            population,
+            true_positives: with_condition * likelihood,
+            false_negatives: with_condition * negate(likelihood),
+            false_positives: without_condition * likelihood_null,
+            true_negatives: without_condition * negate(likelihood_null),
+        }
+    }
+
+    /// The number of cases with the condition
+    #[inline]
+    #[must_use]
+    pub fn with_condition(&self) -> f64 {
+        self.true_positives + self.false_negatives
+    }
+
+    /// The number of cases without the condition
+    #[inline]
+    #[must_use]
+    pub fn without_condition(&self) -> f64 {
+        self.false_positives + self.true_negatives
+    }
+
+    /// The number of cases that test positive
+    #[inline]
+    #[must_use]
+    pub fn positives(&self) -> f64 {
+        self.true_positives + self.false_positives
+    }
+
+    /// The number of cases that test negative
+    #[inline]
+    #[must_use]
+    pub fn negatives(&self) -> f64 {
+        self.false_negatives + self.true_negatives
+    }
+}
+
+/// Evaluates a diagnostic test.  The prevalence, sensitivity and specificity are mapped onto the prior P(H), likelihood P(E|H) and likelihood null P(E|¬H) = 1 - specificity.
+/// # Errors
+/// - If any of the values are not valid probabilities
+#[inline]
+pub fn analyze_diagnostic_test(
+    sensitivity: f64,
+    specificity: f64,
+    prevalence: f64,
+    name: &str,
+) -> Result<DiagnosticAnalysis> {
+    crate::validate_probability(sensitivity)?;
+    crate::validate_probability(specificity)?;
+    crate::validate_probability(prevalence)?;
+    let false_positive_rate = negate(specificity);
+    let false_negative_rate = negate(sensitivity);
+    // A predictive value is undefined when the test result it conditions on cannot happen
+    let positive_predictive_value = calculate_posterior_probability(
+        prevalence,
+        sensitivity,
+        false_positive_rate,
+        &Evidence::Observed,
+        name,
+    )
+    .ok();
+    let negative_predictive_value = calculate_posterior_probability(
+        prevalence,
+        sensitivity,
+        false_positive_rate,
+        &Evidence::NotObserved,
+        name,
+    )
+    .ok()
+    .map(negate);
+    Ok(DiagnosticAnalysis {
+        sensitivity,
+        specificity,
+        prevalence,
+        positive_predictive_value,
+        negative_predictive_value,
+        false_positive_rate,
+        false_negative_rate,
+        positive_likelihood_ratio: likelihood_ratio(sensitivity, false_positive_rate),
+        negative_likelihood_ratio: likelihood_ratio(false_negative_rate, specificity),
+    })
+}
+
+/// The expected number of cases in each cell of the confusion matrix when the test is applied to the population
+#[inline]
+#[must_use]
+pub fn natural_frequencies(analysis: &DiagnosticAnalysis, population: f64) -> NaturalFrequencies {
+    NaturalFrequencies::new(
+        analysis.prevalence,
+        analysis.sensitivity,
+        analysis.false_positive_rate,
+        population,
+    )
+}
+
+/// The ratio of two likelihoods, which is infinite when only the denominator is 0 and undefined when both are
+fn likelihood_ratio(likelihood: f64, likelihood_null: f64) -> Option<f64> {
+    if likelihood <= 0.0_f64 && likelihood_null <= 0.0_f64 {
+        return None;
+    }
+    Some(likelihood / likelihood_null)
+}
+
+/// Formats a number of cases with thousands separators, keeping one decimal place for fractional counts
+#[must_use]
+pub(crate) fn format_count(count: f64) -> String {
+    let rounded = format!("{count:.1}");
+    let (integer, fraction) = rounded.split_once('.').unwrap_or((&rounded, "0"));
+    let mut grouped = String::new();
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if fraction == "0" {
+        grouped
+    } else {
+        format!("{grouped}.{fraction}")
+    }
+}
+
+/// Formats a value that may be undefined
+fn format_optional(value: Option<f64>) -> String {
+    value.map_or_else(
+        || "undefined".to_owned(),
+        |value| Rounded(value).to_string(),
+    )
+}
+
+/// Explains the result of the test in terms of natural frequencies, e.g. "Out of 10,000 cases…"
+#[inline]
+#[must_use]
+pub fn explain_natural_frequencies(name: &str, frequencies: &NaturalFrequencies) -> String {
+    let mut explanation = format!(
+        "Out of {} cases, {} have {name}, and {} of them test positive.  Of the {} without {name}, {} also test positive.",
+        format_count(frequencies.population),
+        format_count(frequencies.with_condition()),
+        format_count(frequencies.true_positives),
+        format_count(frequencies.without_condition()),
+        format_count(frequencies.false_positives),
+    );
+    if frequencies.positives() > 0.0_f64 {
+        explanation.push_str(&format!(
+            "  So of the {} positive tests, {} ({:.1}%) actually have {name}.",
+            format_count(frequencies.positives()),
+            format_count(frequencies.true_positives),
+            frequencies.true_positives / frequencies.positives() * 100.0_f64,
+        ));
+    }
+    explanation
+}
+
+/// Reports the predictive values, error rates and likelihood ratios of the test, explained with natural frequencies
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_diagnostic_analysis(
+    name: &str,
+    analysis: &DiagnosticAnalysis,
+    frequencies: &NaturalFrequencies,
+    output_format: &OutputFormat,
+) {
+    let explanation = explain_natural_frequencies(name, frequencies);
+    match *output_format {
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(Row::new(vec![
+                Cell::new("Name"),
+                Cell::new("Probability"),
+                Cell::new("Value"),
+            ]));
+            let rows = [
+                (
+                    "Prevalence",
+                    format!("P({name})"),
+                    Rounded(analysis.prevalence).to_string(),
+                ),
+                (
+                    "Sensitivity",
+                    format!("P(E|{name})"),
+                    Rounded(analysis.sensitivity).to_string(),
+                ),
+                (
+                    "Specificity",
+                    format!("P(\u{ac}E|\u{ac}{name})"),
+                    Rounded(analysis.specificity).to_string(),
+                ),
+                (
+                    "False Positive Rate",
+                    format!("P(E|\u{ac}{name})"),
+                    Rounded(analysis.false_positive_rate).to_string(),
+                ),
+                (
+                    "False Negative Rate",
+                    format!("P(\u{ac}E|{name})"),
+                    Rounded(analysis.false_negative_rate).to_string(),
+                ),
+                (
+                    "Positive Predictive Value",
+                    format!("P({name}|E)"),
+                    format_optional(analysis.positive_predictive_value),
+                ),
+                (
+                    "Negative Predictive Value",
+                    format!("P(\u{ac}{name}|\u{ac}E)"),
+                    format_optional(analysis.negative_predictive_value),
+                ),
+                (
+                    "Positive Likelihood Ratio",
+                    "LR+".to_owned(),
+                    format_optional(analysis.positive_likelihood_ratio),
+                ),
+                (
+                    "Negative Likelihood Ratio",
+                    "LR-".to_owned(),
+                    format_optional(analysis.negative_likelihood_ratio),
+                ),
+            ];
+            for (label, probability, value) in rows {
+                table.add_row(Row::new(vec![
+                    Cell::new(label),
+                    Cell::new(&probability),
+                    Cell::new(&value),
+                ]));
+            }
+            table.printstd();
+
+            let mut frequency_table = Table::new();
+            frequency_table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            frequency_table.set_titles(Row::new(vec![
+                Cell::new(&format!("Out of {}", format_count(frequencies.population))),
+                Cell::new("Test Positive"),
+                Cell::new("Test Negative"),
+                Cell::new("Total"),
+            ]));
+            frequency_table.add_row(Row::new(vec![
+                Cell::new(name),
+                Cell::new(&format_count(frequencies.true_positives)),
+                Cell::new(&format_count(frequencies.false_negatives)),
+                Cell::new(&format_count(frequencies.with_condition())),
+            ]));
+            frequency_table.add_row(Row::new(vec![
+                Cell::new(&format!("\u{ac}{name}")),
+                Cell::new(&format_count(frequencies.false_positives)),
+                Cell::new(&format_count(frequencies.true_negatives)),
+                Cell::new(&format_count(frequencies.without_condition())),
+            ]));
+            frequency_table.add_row(Row::new(vec![
+                Cell::new("Total"),
+                Cell::new(&format_count(frequencies.positives())),
+                Cell::new(&format_count(frequencies.negatives())),
+                Cell::new(&format_count(frequencies.population)),
+            ]));
+            frequency_table.printstd();
+            info!("{explanation}");
+        }
+        OutputFormat::Json => {
+            let json = json!({
+                "name": name,
+                "prevalence": analysis.prevalence,
+                "sensitivity": analysis.sensitivity,
+                "specificity": analysis.specificity,
+                "false_positive_rate": analysis.false_positive_rate,
+                "false_negative_rate": analysis.false_negative_rate,
+                "positive_predictive_value": analysis.positive_predictive_value,
+                "negative_predictive_value": analysis.negative_predictive_value,
+                "positive_likelihood_ratio": analysis.positive_likelihood_ratio,
+                "negative_likelihood_ratio": analysis.negative_likelihood_ratio,
+                "natural_frequencies": {
+                    "population": frequencies.population,
+                    "true_positives": frequencies.true_positives,
+                    "false_negatives": frequencies.false_negatives,
+                    "false_positives": frequencies.false_positives,
+                    "true_negatives": frequencies.true_negatives,
+                },
+                "explanation": explanation,
+            });
+
+            info!("{}", json.to_string());
+        }
+        OutputFormat::Simple => {
+            let output = format!(
+                "
+                P({name}) = {}
+                P(E|{name}) = {}
+                P(\u{ac}E|\u{ac}{name}) = {}
+                P(E|\u{ac}{name}) = {}
+                P(\u{ac}E|{name}) = {}
+                P({name}|E) = {}
+                P(\u{ac}{name}|\u{ac}E) = {}
+                LR+ = {}
+                LR- = {}
+                {explanation}
+                ",
+                Rounded(analysis.prevalence),
+                Rounded(analysis.sensitivity),
+                Rounded(analysis.specificity),
+                Rounded(analysis.false_positive_rate),
+                Rounded(analysis.false_negative_rate),
+                format_optional(analysis.positive_predictive_value),
+                format_optional(analysis.negative_predictive_value),
+                format_optional(analysis.positive_likelihood_ratio),
+                format_optional(analysis.negative_likelihood_ratio),
+            );
+            info!("{output}");
+        }
+        OutputFormat::Icons => {
+            info!(
+                "\n{}\n\n                {explanation}",
+                render_icon_array(name, frequencies, colors_enabled()),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    fn epsilon_compare(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9_f64
+    }
+
+    #[test]
+    fn it_analyzes_a_diagnostic_test() -> Result<()> {
+        let result = analyze_diagnostic_test(0.9_f64, 0.91_f64, 0.01_f64, "test")?;
+        // P(H|E) = 0.009 / (0.009 + 0.0891)
+        assert!(epsilon_compare(
+            result.positive_predictive_value.unwrap_or_default(),
+            0.009_f64 / 0.0981_f64
+        ));
+        // P(¬H|¬E) = 0.9009 / (0.001 + 0.9009)
+        assert!(epsilon_compare(
+            result.negative_predictive_value.unwrap_or_default(),
+            0.9009_f64 / 0.9019_f64
+        ));
+        assert!(epsilon_compare(result.false_positive_rate, 0.09_f64));
+        assert!(epsilon_compare(result.false_negative_rate, 0.1_f64));
+        assert!(epsilon_compare(
+            result.positive_likelihood_ratio.unwrap_or_default(),
+            10.0_f64
+        ));
+        assert!(epsilon_compare(
+            result.negative_likelihood_ratio.unwrap_or_default(),
+            0.1_f64 / 0.91_f64
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_leaves_predictive_values_undefined_when_a_result_is_impossible() -> Result<()> {
+        let result = analyze_diagnostic_test(0.0_f64, 1.0_f64, 0.5_f64, "test")?;
+        assert_eq!(result.positive_predictive_value, None);
+        assert!(result.negative_predictive_value.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_analyze_an_invalid_diagnostic_test() {
+        assert!(analyze_diagnostic_test(1.1_f64, 0.9_f64, 0.5_f64, "test").is_err());
+        assert!(analyze_diagnostic_test(0.9_f64, -0.1_f64, 0.5_f64, "test").is_err());
+        assert!(analyze_diagnostic_test(0.9_f64, 0.9_f64, 2.0_f64, "test").is_err());
+    }
+
+    #[test]
+    fn it_calculates_natural_frequencies() -> Result<()> {
+        let analysis = analyze_diagnostic_test(0.9_f64, 0.91_f64, 0.01_f64, "test")?;
+        let result = natural_frequencies(&analysis, 10_000.0_f64);
+        assert!(epsilon_compare(result.true_positives, 90.0_f64));
+        assert!(epsilon_compare(result.false_negatives, 10.0_f64));
+        assert!(epsilon_compare(result.false_positives, 891.0_f64));
+        assert!(epsilon_compare(result.true_negatives, 9009.0_f64));
+        assert!(epsilon_compare(result.positives(), 981.0_f64));
+        Ok(())
+    }
+
+    #[test]
+    fn it_explains_natural_frequencies() -> Result<()> {
+        let analysis = analyze_diagnostic_test(0.9_f64, 0.91_f64, 0.01_f64, "flu")?;
+        let result =
+            explain_natural_frequencies("flu", &natural_frequencies(&analysis, 10_000.0_f64));
+        assert_eq!(
+            result,
+            "Out of 10,000 cases, 100 have flu, and 90 of them test positive.  Of the 9,900 without flu, 891 also test positive.  So of the 981 positive tests, 90 (9.2%) actually have flu."
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_formats_counts() {
+        assert_eq!(format_count(0.0_f64), "0");
+        assert_eq!(format_count(999.0_f64), "999");
+        assert_eq!(format_count(1_000.0_f64), "1,000");
+        assert_eq!(format_count(1_234_567.0_f64), "1,234,567");
+        assert_eq!(format_count(12.34_f64), "12.3");
+    }
+}
@@ -0,0 +1,519 @@
+//! A store kept as plain-text files in a directory, one per hypothesis, so that beliefs can be versioned and reviewed in a git repository.
+use crate::history::HistoryEntry;
+use crate::store::{lock_timeout, Operation, Store, LOCK_RETRY_INTERVAL};
+use anyhow::{anyhow, Result};
+use fs2::{lock_contended_error, FileExt};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+
+/// The extension of the file holding each hypothesis
+const EXTENSION: &str = "json";
+/// The file locked while the store is written, so that only one process changes it at a time
+const LOCK_FILE: &str = ".lock";
+
+/// Everything recorded about one hypothesis, as it is written to its file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Record {
+    /// The name of the hypothesis
+    name: String,
+    /// The prior probability of the hypothesis, if one has been saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    probability: Option<f64>,
+    /// The metadata of the hypothesis by kind, e.g. its actions and tags
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, Value>,
+    /// The history of the hypothesis, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<HistoryEntry>,
+}
+
+impl Record {
+    /// A record with nothing in it
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether nothing is recorded, in which case the file is removed
+    fn is_empty(&self) -> bool {
+        self.probability.is_none() && self.metadata.is_empty() && self.history.is_empty()
+    }
+}
+
+/// The hexadecimal digits used to percent-encode file names
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// The name of the file holding the hypothesis.
+///
+/// Characters other than lowercase ASCII letters, digits, `-`, `_` and `.` are percent-encoded, uppercase letters included, so that every name maps to a single, portable file name that no other name shares, even on case-insensitive filesystems.
+fn file_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_lowercase() || byte.is_ascii_digit() || matches!(byte, b'-' | b'_' | b'.')
+        {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push('%');
+            encoded.push(char::from(HEX_DIGITS[usize::from(byte >> 4_u8)]));
+            encoded.push(char::from(HEX_DIGITS[usize::from(byte & 0x0F_u8)]));
+        }
+    }
+    format!("{encoded}.{EXTENSION}")
+}
+
+/// A store kept as JSON files in a directory, one per hypothesis.
+///
+/// Each file holds the name, prior probability, metadata and history of the hypothesis.  Writers lock a `.lock` file in the directory so that only one process changes the store at a time.  Files are pretty-printed with their keys in a fixed order and a trailing newline, so saving the same beliefs always produces the same bytes and diffs show only what changed.
+///
+/// A change to several hypotheses replaces their files one rename at a time, keeping a `.bak` link to each original until all of them have been replaced.  If a rename fails partway, the files renamed before it are restored, so the change is saved whole or not at all unless the process itself is killed in between.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct DirStore {
+    /// The directory holding the files
+    dir: PathBuf,
+}
+
+impl DirStore {
+    /// Opens the store in the directory, creating the directory if needed
+    /// # Errors
+    /// - If the directory cannot be created
+    #[inline]
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Locks the store for writing, waiting up to the lock timeout for other processes writing to it.  The lock is released when the returned file is closed.
+    /// # Errors
+    /// - If the lock file cannot be opened
+    /// - If another process still holds the lock once the timeout has passed
+    fn lock(&self) -> Result<File> {
+        let path = self.dir.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        let timeout = lock_timeout()?;
+        let started = Instant::now();
+        while let Err(error) = file.try_lock_exclusive() {
+            if error.kind() != lock_contended_error().kind() {
+                return Err(anyhow!("Could not lock {}: {error}", path.display()));
+            }
+            if started.elapsed() >= timeout {
+                return Err(anyhow!(
+                    "The store in {} is busy: another ask-bayes process is writing to it.  \
+                     Gave up after waiting {:.1}s.  Try again once it has finished, or set ASK_BAYES_LOCK_TIMEOUT to wait longer",
+                    self.dir.display(),
+                    timeout.as_secs_f64()
+                ));
+            }
+            thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+        Ok(file)
+    }
+
+    /// Applies the operations to the records they change and writes them out.  The store must be locked.
+    /// # Errors
+    /// - If a record cannot be read, parsed or written
+    fn write_operations(&self, operations: &[Operation]) -> Result<()> {
+        let mut records: BTreeMap<String, Record> = BTreeMap::new();
+        for operation in operations {
+            let name = match *operation {
+                Operation::SetPrior { ref name, .. }
+                | Operation::RemovePrior { ref name }
+                | Operation::SetHistory { ref name, .. }
+                | Operation::RemoveHistory { ref name }
+                | Operation::SetMetadata { ref name, .. }
+                | Operation::RemoveMetadata { ref name, .. } => name,
+            };
+            if !records.contains_key(name) {
+                let record = self.read(name)?.unwrap_or_else(|| Record::new(name));
+                records.insert(name.clone(), record);
+            }
+            let record = records
+                .get_mut(name)
+                .ok_or_else(|| anyhow!("Could not load hypothesis {name}"))?;
+            match *operation {
+                Operation::SetPrior { prior, .. } => record.probability = Some(prior),
+                Operation::RemovePrior { .. } => record.probability = None,
+                Operation::SetHistory { ref history, .. } => record.history.clone_from(history),
+                Operation::RemoveHistory { .. } => record.history.clear(),
+                Operation::SetMetadata {
+                    ref kind,
+                    ref value,
+                    ..
+                } => {
+                    record
+                        .metadata
+                        .insert(kind.clone(), serde_json::from_str(value)?);
+                }
+                Operation::RemoveMetadata { ref kind, .. } => {
+                    record.metadata.remove(kind);
+                }
+            }
+        }
+        self.write_all(records.values())
+    }
+
+    /// The path of the file holding the hypothesis
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(file_name(name))
+    }
+
+    /// Reads the record of the hypothesis, if it has a file
+    /// # Errors
+    /// - If the file cannot be read or parsed
+    fn read(&self, name: &str) -> Result<Option<Record>> {
+        let path = self.path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::read_file(&path).map(Some)
+    }
+
+    /// Reads and parses a record file
+    /// # Errors
+    /// - If the file cannot be read or parsed
+    fn read_file(path: &Path) -> Result<Record> {
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| anyhow!("Could not parse {}: {error}", path.display()))
+    }
+
+    /// Reads every record in the directory, in alphabetical order of name
+    /// # Errors
+    /// - If the directory or a file in it cannot be read or parsed
+    fn read_all(&self) -> Result<Vec<Record>> {
+        let mut records = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == EXTENSION)
+            {
+                records.push(Self::read_file(&path)?);
+            }
+        }
+        records.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(records)
+    }
+
+    /// Writes the record to a temporary file next to its file, returning the temporary file, or `None` if the record is empty and its file should be removed
+    /// # Errors
+    /// - If the temporary file cannot be written
+    fn stage(&self, record: &Record) -> Result<Option<PathBuf>> {
+        if record.is_empty() {
+            return Ok(None);
+        }
+        let mut contents = serde_json::to_string_pretty(record)?;
+        contents.push('\n');
+        let temporary = self
+            .path(&record.name)
+            .with_extension(format!("{EXTENSION}.tmp"));
+        fs::write(&temporary, contents)?;
+        Ok(Some(temporary))
+    }
+
+    /// Writes the records to their files, removing the files of empty records.
+    ///
+    /// Every record is first written to a temporary file, and only once all of them have been written are they renamed over the originals, so a record that cannot be written changes nothing.  Each original is kept as a backup until every rename has succeeded, and if one fails the files already replaced are restored from their backups.
+    /// # Errors
+    /// - If a file cannot be written, renamed or removed
+    fn write_all<'a>(&self, records: impl Iterator<Item = &'a Record>) -> Result<()> {
+        let mut staged = Vec::new();
+        for record in records {
+            match self.stage(record) {
+                Ok(temporary) => staged.push((self.path(&record.name), temporary)),
+                Err(error) => {
+                    remove_temporaries(staged.into_iter().filter_map(|(_, temporary)| temporary));
+                    return Err(error);
+                }
+            }
+        }
+        let mut replaced = Vec::new();
+        let mut pending = staged.into_iter();
+        while let Some((path, temporary)) = pending.next() {
+            match replace(&path, temporary.as_deref()) {
+                Ok(backup) => replaced.push((path, backup)),
+                Err(error) => {
+                    restore(replaced);
+                    remove_temporaries(
+                        temporary
+                            .into_iter()
+                            .chain(pending.filter_map(|(_, temporary)| temporary)),
+                    );
+                    return Err(error);
+                }
+            }
+        }
+        remove_temporaries(replaced.into_iter().filter_map(|(_, backup)| backup));
+        Ok(())
+    }
+}
+
+/// Replaces the file with the temporary file, or removes it if there is no temporary file, first linking the original to a backup.  Returns the backup, or `None` if there was no original.
+/// # Errors
+/// - If the backup cannot be made
+/// - If the file cannot be renamed or removed
+fn replace(path: &Path, temporary: Option<&Path>) -> Result<Option<PathBuf>> {
+    let backup = if path.exists() {
+        let backup = path.with_extension(format!("{EXTENSION}.bak"));
+        if backup.exists() {
+            fs::remove_file(&backup)?;
+        }
+        fs::hard_link(path, &backup)?;
+        Some(backup)
+    } else {
+        None
+    };
+    let replaced = match (temporary, backup.is_some()) {
+        (Some(temporary), _) => fs::rename(temporary, path),
+        (None, true) => fs::remove_file(path),
+        (None, false) => Ok(()),
+    };
+    if let Err(error) = replaced {
+        remove_temporaries(backup);
+        return Err(error.into());
+    }
+    Ok(backup)
+}
+
+/// Puts back the originals of the replaced files, newest first, and removes the files that had none
+fn restore(replaced: Vec<(PathBuf, Option<PathBuf>)>) {
+    for (path, backup) in replaced.into_iter().rev() {
+        let restored = match backup {
+            Some(backup) => fs::rename(backup, &path),
+            None if path.exists() => fs::remove_file(&path),
+            None => Ok(()),
+        };
+        if let Err(error) = restored {
+            warn!("Could not restore {}: {error}", path.display());
+        }
+    }
+}
+
+/// Removes temporary and backup files, warning about any that cannot be removed
+fn remove_temporaries(paths: impl IntoIterator<Item = PathBuf>) {
+    for path in paths {
+        if let Err(error) = fs::remove_file(&path) {
+            warn!("Could not remove {}: {error}", path.display());
+        }
+    }
+}
+
+impl Store for DirStore {
+    #[inline]
+    fn get_prior(&self, name: &str) -> Result<Option<f64>> {
+        Ok(self.read(name)?.and_then(|record| record.probability))
+    }
+
+    #[inline]
+    fn list_priors(&self) -> Result<Vec<(String, f64)>> {
+        Ok(self
+            .read_all()?
+            .into_iter()
+            .filter_map(|record| record.probability.map(|prior| (record.name, prior)))
+            .collect())
+    }
+
+    #[inline]
+    fn get_history(&self, name: &str) -> Result<Option<Vec<HistoryEntry>>> {
+        Ok(self
+            .read(name)?
+            .map(|record| record.history)
+            .filter(|history| !history.is_empty()))
+    }
+
+    #[inline]
+    fn history_names(&self) -> Result<Vec<String>> {
+        Ok(self
+            .read_all()?
+            .into_iter()
+            .filter(|record| !record.history.is_empty())
+            .map(|record| record.name)
+            .collect())
+    }
+
+    #[inline]
+    fn get_metadata(&self, kind: &str, name: &str) -> Result<Option<String>> {
+        match self
+            .read(name)?
+            .and_then(|mut record| record.metadata.remove(kind))
+        {
+            Some(value) => Ok(Some(serde_json::to_string(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn list_metadata(&self, kind: &str) -> Result<Vec<(String, String)>> {
+        let mut records = Vec::new();
+        for mut record in self.read_all()? {
+            if let Some(value) = record.metadata.remove(kind) {
+                records.push((record.name, serde_json::to_string(&value)?));
+            }
+        }
+        Ok(records)
+    }
+
+    #[inline]
+    fn metadata_kinds(&self) -> Result<Vec<String>> {
+        let mut kinds: Vec<String> = self
+            .read_all()?
+            .into_iter()
+            .flat_map(|record| record.metadata.into_keys())
+            .collect();
+        kinds.sort();
+        kinds.dedup();
+        Ok(kinds)
+    }
+
+    #[inline]
+    fn apply(&self, operations: &[Operation]) -> Result<()> {
+        let _lock = self.lock()?;
+        self.write_operations(operations)
+    }
+
+    #[inline]
+    fn transact(&self, change: &mut dyn FnMut(&dyn Store) -> Result<Vec<Operation>>) -> Result<()> {
+        let _lock = self.lock()?;
+        let operations = change(self)?;
+        self.write_operations(&operations)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::history::Change;
+    use crate::store::tests::{
+        it_behaves_like_a_store, it_keeps_concurrent_changes, temporary_path,
+    };
+
+    #[test]
+    fn it_stores_hypotheses_in_a_directory() -> Result<()> {
+        let store = DirStore::open(&temporary_path("dir-store"))?;
+        it_behaves_like_a_store(&store)
+    }
+
+    #[test]
+    fn it_keeps_changes_made_by_concurrent_writers() -> Result<()> {
+        it_keeps_concurrent_changes(
+            |path| Ok(Box::new(DirStore::open(path)?)),
+            &temporary_path("dir-store-concurrent"),
+        )
+    }
+
+    #[test]
+    fn it_restores_the_replaced_files_when_a_rename_fails() -> Result<()> {
+        let dir = temporary_path("dir-store-rollback");
+        let store = DirStore::open(&dir)?;
+        store.apply(&[Operation::SetPrior {
+            name: "cloud".to_owned(),
+            prior: 0.1,
+        }])?;
+        fs::create_dir_all(store.path("snow").join("blocked"))?;
+        let records: Vec<Record> = [("cloud", 0.9), ("rain", 0.5), ("snow", 0.2)]
+            .into_iter()
+            .map(|(name, prior)| Record {
+                probability: Some(prior),
+                ..Record::new(name)
+            })
+            .collect();
+        assert!(store.write_all(records.iter()).is_err());
+        assert_eq!(store.get_prior("cloud")?, Some(0.1));
+        assert_eq!(store.get_prior("rain")?, None);
+        let mut files: Vec<String> = fs::read_dir(&dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_>>()?;
+        files.sort();
+        assert_eq!(files, vec![".lock", "cloud.json", "snow.json"]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_encodes_names_as_portable_file_names() {
+        assert_eq!(file_name("rain"), "rain.json");
+        assert_eq!(file_name("infra/db load"), "infra%2Fdb%20load.json");
+        assert_eq!(file_name("café"), "caf%C3%A9.json");
+        assert_eq!(file_name("Rain"), "%52ain.json");
+        assert_ne!(
+            file_name("Rain").to_lowercase(),
+            file_name("rain").to_lowercase()
+        );
+    }
+
+    #[test]
+    fn it_writes_deterministic_human_readable_files() -> Result<()> {
+        let dir = temporary_path("dir-store-format");
+        let store = DirStore::open(&dir)?;
+        let mut entry = HistoryEntry::new(0.25, Change::Set);
+        entry.timestamp = "2022-03-01T12:00:00Z".parse()?;
+        let operations = [
+            Operation::SetMetadata {
+                kind: "tags".to_owned(),
+                name: "infra/db".to_owned(),
+                value: r#"["incident-42","database"]"#.to_owned(),
+            },
+            Operation::SetPrior {
+                name: "infra/db".to_owned(),
+                prior: 0.25,
+            },
+            Operation::SetHistory {
+                name: "infra/db".to_owned(),
+                history: vec![entry],
+            },
+        ];
+        store.apply(&operations)?;
+        let written = fs::read_to_string(dir.join("infra%2Fdb.json"))?;
+        assert_eq!(
+            written,
+            r#"{
+  "name": "infra/db",
+  "probability": 0.25,
+  "metadata": {
+    "tags": [
+      "incident-42",
+      "database"
+    ]
+  },
+  "history": [
+    {
+      "timestamp": "2022-03-01T12:00:00Z",
+      "probability": 0.25,
+      "change": "Set"
+    }
+  ]
+}
+"#
+        );
+        store.apply(&operations)?;
+        assert_eq!(fs::read_to_string(dir.join("infra%2Fdb.json"))?, written);
+
+        store.apply(&[
+            Operation::RemovePrior {
+                name: "infra/db".to_owned(),
+            },
+            Operation::RemoveHistory {
+                name: "infra/db".to_owned(),
+            },
+            Operation::RemoveMetadata {
+                kind: "tags".to_owned(),
+                name: "infra/db".to_owned(),
+            },
+        ])?;
+        assert!(!dir.join("infra%2Fdb.json").exists());
+        Ok(())
+    }
+}
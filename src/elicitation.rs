@@ -0,0 +1,373 @@
+//! Guided elicitation of the likelihoods of evidence, from comparative questions or reference classes of similar cases, for when P(E|H) and P(E|¬H) are hard to estimate directly.
+use crate::{parse_rational, report_probability_interpretation, Probability, Rounded};
+use anyhow::{anyhow, Result};
+use dialoguer::{Confirm, Input, Select};
+use log::{error, info};
+use num_traits::ToPrimitive;
+use prettytable::{format, Cell, Row, Table};
+use std::fmt::{Display, Formatter};
+
+/// The number of cases in each reference class the user is asked to imagine
+pub const REFERENCE_CLASS_SIZE: u32 = 100;
+
+/// A pair of likelihoods of the evidence, one if the hypothesis is true and one if it is false
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Likelihoods {
+    /// The likelihood of the evidence if the hypothesis is true, i.e. P(E|H)
+    pub likelihood: f64,
+    /// The likelihood of the evidence if the hypothesis is false, i.e. P(E|¬H)
+    pub likelihood_null: f64,
+}
+
+impl Likelihoods {
+    /// How many times more likely the evidence is if the hypothesis is true than if it is false, i.e. P(E|H) / P(E|¬H).  Infinite if the evidence is impossible when the hypothesis is false.
+    #[inline]
+    #[must_use]
+    pub fn likelihood_ratio(&self) -> f64 {
+        self.likelihood / self.likelihood_null
+    }
+}
+
+/// Finds the likelihoods implied by the likelihood of the evidence if the hypothesis is true and how many times more likely the evidence is then than if it is false
+/// # Errors
+/// - If the likelihood is not a probability above 0
+/// - If the ratio is not a finite number above 0
+/// - If the ratio is so small that P(E|¬H) would be above 1
+#[inline]
+pub fn likelihoods_from_ratio(likelihood: f64, ratio: f64) -> Result<Likelihoods> {
+    if !(likelihood > 0.0_f64 && likelihood <= 1.0_f64) {
+        return Err(anyhow!(
+            "The likelihood P(E|H) must be above 0 and at most 1"
+        ));
+    }
+    if !(ratio.is_finite() && ratio > 0.0_f64) {
+        return Err(anyhow!("The likelihood ratio must be a number above 0"));
+    }
+    let likelihood_null = likelihood / ratio;
+    if likelihood_null > 1.0_f64 {
+        return Err(anyhow!(
+            "With P(E|H) = {}, the evidence can be at most {} times less likely if the hypothesis is false",
+            likelihood,
+            Rounded(1.0_f64 / likelihood)
+        ));
+    }
+    Ok(Likelihoods {
+        likelihood,
+        likelihood_null,
+    })
+}
+
+/// Finds the likelihoods implied by how often the evidence would be seen in a reference class of cases where the hypothesis is true and one where it is false
+/// # Errors
+/// - If either reference class is empty
+/// - If more cases show the evidence than are in the reference class
+/// - If no case in either reference class shows the evidence
+#[inline]
+pub fn likelihoods_from_frequencies(
+    with_evidence_if_true: f64,
+    cases_if_true: f64,
+    with_evidence_if_false: f64,
+    cases_if_false: f64,
+) -> Result<Likelihoods> {
+    for (with_evidence, cases) in [
+        (with_evidence_if_true, cases_if_true),
+        (with_evidence_if_false, cases_if_false),
+    ] {
+        if cases.is_nan() || cases <= 0.0_f64 {
+            return Err(anyhow!("A reference class must have at least one case"));
+        }
+        if !(0.0_f64..=cases).contains(&with_evidence) {
+            return Err(anyhow!(
+                "Between 0 and {} cases can show the evidence",
+                cases
+            ));
+        }
+    }
+    if with_evidence_if_true == 0.0_f64 && with_evidence_if_false == 0.0_f64 {
+        return Err(anyhow!(
+            "The evidence must be possible in at least one of the reference classes"
+        ));
+    }
+    Ok(Likelihoods {
+        likelihood: with_evidence_if_true / cases_if_true,
+        likelihood_null: with_evidence_if_false / cases_if_false,
+    })
+}
+
+/// Parses a likelihood ratio written as a decimal (`3`, `2.5`) or a fraction (`1/3`)
+/// # Errors
+/// - If the value is not a decimal or a fraction
+/// - If the value is not above 0
+#[inline]
+pub fn parse_likelihood_ratio(value: &str) -> Result<f64> {
+    let input = value.trim();
+    let ratio = parse_rational(input)?
+        .to_f64()
+        .ok_or_else(|| anyhow!("Invalid likelihood ratio: {}", input))?;
+    if !(ratio.is_finite() && ratio > 0.0_f64) {
+        return Err(anyhow!("The likelihood ratio must be a number above 0"));
+    }
+    Ok(ratio)
+}
+
+/// The strength of evidence on Jeffreys' scale
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum EvidenceStrength {
+    /// A ratio of 1, which is no evidence either way
+    Neutral,
+    /// A ratio below 3
+    Weak,
+    /// A ratio below 10
+    Moderate,
+    /// A ratio below 30
+    Strong,
+    /// A ratio below 100
+    VeryStrong,
+    /// A ratio of 100 or more
+    Decisive,
+}
+
+impl Display for EvidenceStrength {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Neutral => write!(f, "no"),
+            Self::Weak => write!(f, "weak"),
+            Self::Moderate => write!(f, "moderate"),
+            Self::Strong => write!(f, "strong"),
+            Self::VeryStrong => write!(f, "very strong"),
+            Self::Decisive => write!(f, "decisive"),
+        }
+    }
+}
+
+/// The strength of the evidence given by a likelihood ratio, or Bayes factor, on Jeffreys' scale: weak below 3, moderate below 10, strong below 30, very strong below 100, and decisive above.
+///
+/// A ratio below 1 is as strong as its reciprocal, and a ratio of 1 is neutral.
+#[inline]
+#[must_use]
+pub fn evidence_strength(ratio: f64) -> EvidenceStrength {
+    let strength = if ratio < 1.0_f64 {
+        ratio.recip()
+    } else {
+        ratio
+    };
+    match strength {
+        s if s.is_nan() || s <= 1.0_f64 => EvidenceStrength::Neutral,
+        s if s < 3.0_f64 => EvidenceStrength::Weak,
+        s if s < 10.0_f64 => EvidenceStrength::Moderate,
+        s if s < 30.0_f64 => EvidenceStrength::Strong,
+        s if s < 100.0_f64 => EvidenceStrength::VeryStrong,
+        _ => EvidenceStrength::Decisive,
+    }
+}
+
+/// Describes the strength of evidence with the likelihood ratio on Jeffreys' scale, e.g. `strong evidence for` for 20 or `weak evidence against` for 1/2
+#[inline]
+#[must_use]
+pub fn describe_likelihood_ratio(ratio: f64) -> &'static str {
+    let for_hypothesis = ratio > 1.0_f64;
+    match (evidence_strength(ratio), for_hypothesis) {
+        (EvidenceStrength::Neutral, _) => "no evidence either way about",
+        (EvidenceStrength::Weak, true) => "weak evidence for",
+        (EvidenceStrength::Weak, false) => "weak evidence against",
+        (EvidenceStrength::Moderate, true) => "moderate evidence for",
+        (EvidenceStrength::Moderate, false) => "moderate evidence against",
+        (EvidenceStrength::Strong, true) => "strong evidence for",
+        (EvidenceStrength::Strong, false) => "strong evidence against",
+        (EvidenceStrength::VeryStrong, true) => "very strong evidence for",
+        (EvidenceStrength::VeryStrong, false) => "very strong evidence against",
+        (EvidenceStrength::Decisive, true) => "decisive evidence for",
+        (EvidenceStrength::Decisive, false) => "decisive evidence against",
+    }
+}
+
+/// Reports the likelihoods with the likelihood ratio they imply, how strong that evidence is, and how often the evidence would be seen in reference classes of cases, so that they can be checked before they are used
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_likelihoods(name: &str, likelihoods: &Likelihoods) {
+    let ratio = likelihoods.likelihood_ratio();
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.add_row(Row::new(vec![
+        Cell::new("Likelihood"),
+        Cell::new(&format!("P(E|{name})")),
+        Cell::new(&Rounded(likelihoods.likelihood).to_string()),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new("Likelihood Null"),
+        Cell::new(&format!("P(E|\u{ac}{name})")),
+        Cell::new(&Rounded(likelihoods.likelihood_null).to_string()),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new("Likelihood Ratio"),
+        Cell::new(&format!("P(E|{name}) / P(E|\u{ac}{name})")),
+        Cell::new(&Rounded(ratio).to_string()),
+    ]));
+    table.printstd();
+    let size = f64::from(REFERENCE_CLASS_SIZE);
+    info!(
+        "Out of {} cases where {name} is true, about {} would show the evidence, against about {} out of {} where it is false",
+        REFERENCE_CLASS_SIZE,
+        Rounded(likelihoods.likelihood * size),
+        Rounded(likelihoods.likelihood_null * size),
+        REFERENCE_CLASS_SIZE
+    );
+    info!(
+        "Observing the evidence would be {} {name}",
+        describe_likelihood_ratio(ratio)
+    );
+}
+
+/// Asks how many of the cases in a reference class would show the evidence
+/// # Errors
+/// - If the prompt cannot be displayed
+#[cfg(not(tarpaulin_include))]
+fn ask_reference_class(name: &str, truth: &str) -> Result<f64> {
+    Ok(Input::<f64>::new()
+        .with_prompt(format!(
+            "Out of {REFERENCE_CLASS_SIZE} such cases where {name} is {truth}, in how many would you expect to see the evidence?"
+        ))
+        .validate_with(|count: &f64| -> Result<(), String> {
+            if (0.0_f64..=f64::from(REFERENCE_CLASS_SIZE)).contains(count) {
+                Ok(())
+            } else {
+                Err(format!("Enter a number between 0 and {REFERENCE_CLASS_SIZE}"))
+            }
+        })
+        .interact_text()?)
+}
+
+/// Elicits the likelihoods from how often the evidence would be seen in reference classes of similar cases
+/// # Errors
+/// - If the prompt cannot be displayed
+/// - If the answers do not imply valid likelihoods
+#[cfg(not(tarpaulin_include))]
+fn elicit_from_reference_classes(name: &str) -> Result<Likelihoods> {
+    info!(
+        "Think of cases like this one where {name} turned out to be true: past incidents, similar situations, or published rates"
+    );
+    let with_evidence_if_true = ask_reference_class(name, "true")?;
+    info!("Now think of cases like this one where {name} turned out to be false");
+    let with_evidence_if_false = ask_reference_class(name, "false")?;
+    let size = f64::from(REFERENCE_CLASS_SIZE);
+    likelihoods_from_frequencies(with_evidence_if_true, size, with_evidence_if_false, size)
+}
+
+/// Elicits the likelihoods from the likelihood of the evidence if the hypothesis is true and how many times more likely it is then than if the hypothesis is false
+/// # Errors
+/// - If the prompt cannot be displayed
+/// - If the answers do not imply valid likelihoods
+#[cfg(not(tarpaulin_include))]
+fn elicit_from_comparison(name: &str) -> Result<Likelihoods> {
+    let likelihood = Input::<Probability>::new()
+        .with_prompt(format!(
+            "How likely is the evidence if {name} is true? P(E|{name})"
+        ))
+        .interact_text()?;
+    report_probability_interpretation(&format!("P(E|{name})"), &likelihood);
+    let ratio = Input::<String>::new()
+        .with_prompt(format!(
+            "How many times more likely is the evidence if {name} is true than if it is false? Use a fraction such as 1/3 if it is less likely"
+        ))
+        .validate_with(|ratio: &String| -> Result<(), String> {
+            parse_likelihood_ratio(ratio)
+                .map(|_| ())
+                .map_err(|error| error.to_string())
+        })
+        .interact_text()?;
+    likelihoods_from_ratio(likelihood.value(), parse_likelihood_ratio(&ratio)?)
+}
+
+/// Guides the user through estimating the likelihoods of the evidence.
+///
+/// The likelihoods are estimated either by comparing how likely the evidence is if the hypothesis is true and if it is false, or by imagining reference classes of similar cases.  The implied likelihoods and likelihood ratio are shown for confirmation, and the user is asked again until they accept them.
+/// # Errors
+/// - If the prompt cannot be displayed
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn elicit_likelihoods(name: &str) -> Result<Likelihoods> {
+    let methods = [
+        format!(
+            "Compare how much more likely the evidence is if {name} is true than if it is false"
+        ),
+        format!("Imagine cases like this one where {name} is true and where it is false"),
+    ];
+    loop {
+        let method = Select::new()
+            .with_prompt("How would you like to estimate the likelihoods?")
+            .items(&methods)
+            .default(0)
+            .interact()?;
+        let elicited = if method == 0 {
+            elicit_from_comparison(name)
+        } else {
+            elicit_from_reference_classes(name)
+        };
+        match elicited {
+            Ok(likelihoods) => {
+                report_likelihoods(name, &likelihoods);
+                if Confirm::new()
+                    .with_prompt("Use these likelihoods?")
+                    .default(true)
+                    .interact()?
+                {
+                    return Ok(likelihoods);
+                }
+            }
+            Err(err) => error!("{}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_comparisons_and_reference_classes_to_likelihoods() -> Result<()> {
+        let likelihoods = likelihoods_from_ratio(0.8, parse_likelihood_ratio("4")?)?;
+        assert!((likelihoods.likelihood_null - 0.2).abs() < 1e-12);
+        assert!((likelihoods.likelihood_ratio() - 4.0).abs() < 1e-12);
+        let likelihoods = likelihoods_from_ratio(0.2, parse_likelihood_ratio(" 1/3 ")?)?;
+        assert!((likelihoods.likelihood_null - 0.6).abs() < 1e-12);
+        assert!(likelihoods_from_ratio(0.5, 0.25).is_err());
+        assert!(likelihoods_from_ratio(0.0, 2.0).is_err());
+        assert!(parse_likelihood_ratio("0").is_err());
+        assert!(parse_likelihood_ratio("-2").is_err());
+        assert!(parse_likelihood_ratio("many").is_err());
+
+        let likelihoods = likelihoods_from_frequencies(30.0, 100.0, 0.0, 100.0)?;
+        assert!((likelihoods.likelihood - 0.3).abs() < 1e-12);
+        assert!(likelihoods.likelihood_ratio().is_infinite());
+        assert!(likelihoods_from_frequencies(0.0, 100.0, 0.0, 100.0).is_err());
+        assert!(likelihoods_from_frequencies(120.0, 100.0, 10.0, 100.0).is_err());
+        assert!(likelihoods_from_frequencies(1.0, 0.0, 1.0, 100.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_describes_the_strength_of_evidence() {
+        assert_eq!(
+            describe_likelihood_ratio(1.0),
+            "no evidence either way about"
+        );
+        assert_eq!(describe_likelihood_ratio(2.0), "weak evidence for");
+        assert_eq!(describe_likelihood_ratio(0.2), "moderate evidence against");
+        assert_eq!(describe_likelihood_ratio(20.0), "strong evidence for");
+        assert_eq!(
+            describe_likelihood_ratio(1.0 / 50.0),
+            "very strong evidence against"
+        );
+        assert_eq!(
+            describe_likelihood_ratio(f64::INFINITY),
+            "decisive evidence for"
+        );
+        assert_eq!(evidence_strength(f64::NAN), EvidenceStrength::Neutral);
+        assert_eq!(evidence_strength(0.5_f64), EvidenceStrength::Weak);
+        assert_eq!(evidence_strength(50.0_f64).to_string(), "very strong");
+    }
+}
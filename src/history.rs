@@ -0,0 +1,470 @@
+//! The recorded history of changes to the prior probability of each hypothesis.
+use crate::store::{open_store, Store};
+use crate::{append_to_history, Evidence, Observation};
+use anyhow::{anyhow, Error, Result};
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How the prior probability of a hypothesis was changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Change {
+    /// The prior probability was set directly
+    Set,
+    /// The prior probability was updated to the posterior probability after an observation
+    Update {
+        /// The prior probability before the update
+        prior: f64,
+        /// The observation the hypothesis was updated with
+        observation: Observation,
+    },
+    /// The prior probability was restored to an earlier step of the history
+    Revert {
+        /// The step that was restored
+        step: usize,
+    },
+    /// Another hypothesis was merged into the hypothesis, along with its history
+    Merge {
+        /// The name of the hypothesis merged in
+        from: String,
+    },
+}
+
+impl Display for Change {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Set => write!(f, "set"),
+            Self::Update {
+                ref observation, ..
+            } => {
+                let evidence = match observation.evidence {
+                    Evidence::Observed => "observed",
+                    Evidence::NotObserved => "not observed",
+                };
+                write!(
+                    f,
+                    "evidence {evidence} with P(E|H) = {}, P(E|\u{ac}H) = {}",
+                    observation.likelihood, observation.likelihood_null
+                )
+            }
+            Self::Revert { step } => write!(f, "reverted to step {step}"),
+            Self::Merge { ref from } => write!(f, "merged {from} in"),
+        }
+    }
+}
+
+/// A recorded change to the prior probability of a hypothesis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct HistoryEntry {
+    /// When the change was made
+    pub timestamp: DateTime<Utc>,
+    /// The prior probability of the hypothesis after the change
+    pub probability: f64,
+    /// How the prior probability was changed
+    pub change: Change,
+    /// A note explaining the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Creates a new history entry timestamped with the current time
+    #[inline]
+    #[must_use]
+    pub fn new(probability: f64, change: Change) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            probability,
+            change,
+            note: None,
+        }
+    }
+
+    /// Attaches a note explaining the change
+    #[inline]
+    #[must_use]
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    /// Describes the change, followed by its note if it has one
+    #[inline]
+    #[must_use]
+    pub fn description(&self) -> String {
+        self.note.as_ref().map_or_else(
+            || self.change.to_string(),
+            |note| format!("{}: {note}", self.change),
+        )
+    }
+}
+
+/// A point on the trajectory of a belief
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct TrajectoryPoint {
+    /// When the belief took this value, if known
+    pub timestamp: Option<DateTime<Utc>>,
+    /// The probability of the hypothesis
+    pub probability: f64,
+    /// What caused the belief to take this value
+    pub annotation: String,
+}
+
+/// The step of the first entry of the history.  If the first change was an update, the prior it was updated from is step 0 and the update is step 1, otherwise the first change is step 0.
+#[inline]
+#[must_use]
+pub fn first_entry_step(history: &[HistoryEntry]) -> usize {
+    usize::from(matches!(
+        history.first(),
+        Some(&HistoryEntry {
+            change: Change::Update { .. },
+            ..
+        })
+    ))
+}
+
+/// The probabilities a belief has taken over its history, in order, indexed by step.
+///
+/// If the first change was an update, the prior it was updated from is included as the starting point.
+#[inline]
+#[must_use]
+pub fn trajectory(history: &[HistoryEntry]) -> Vec<TrajectoryPoint> {
+    let mut points = Vec::with_capacity(history.len() + 1);
+    if let Some(&HistoryEntry {
+        change: Change::Update { prior, .. },
+        ..
+    }) = history.first()
+    {
+        points.push(TrajectoryPoint {
+            timestamp: None,
+            probability: prior,
+            annotation: "initial prior".to_owned(),
+        });
+    }
+    points.extend(history.iter().map(|entry| TrajectoryPoint {
+        timestamp: Some(entry.timestamp),
+        probability: entry.probability,
+        annotation: entry.description(),
+    }));
+    points
+}
+
+/// Gets the history of the hypothesis from the database, oldest first.
+/// # Errors
+/// - If the database cannot be opened
+/// - If no history has been recorded for the hypothesis
+/// - If the saved history cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn get_history(name: &str) -> Result<Vec<HistoryEntry>> {
+    find_history(name)?.ok_or_else(|| anyhow!("No history has been recorded for hypothesis {name}"))
+}
+
+/// Gets the history of the hypothesis from the database, oldest first, or `None` if no history has been recorded for it.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved history cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn find_history(name: &str) -> Result<Option<Vec<HistoryEntry>>> {
+    open_store()?.get_history(name)
+}
+
+/// The earlier state of a hypothesis to revert to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RevertTarget {
+    /// The step of the history, as numbered by `plot`
+    Step(usize),
+    /// The latest step recorded at or before the time
+    Time(DateTime<Utc>),
+}
+
+impl FromStr for RevertTarget {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(step) = s.parse() {
+            return Ok(Self::Step(step));
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Time(time.with_timezone(&Utc)));
+        }
+        NaiveDateTime::parse_from_str(s.trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S")
+            .map(|time| Self::Time(DateTime::from_utc(time, Utc)))
+            .map_err(|error| {
+                anyhow!("Invalid step or timestamp: {s}.  Expected a step number, an RFC 3339 timestamp, or a time such as `2022-03-01 12:00:00 UTC`: {error}")
+            })
+    }
+}
+
+impl Display for RevertTarget {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Step(step) => write!(f, "step {step}"),
+            Self::Time(time) => write!(f, "{}", time.format("%Y-%m-%d %H:%M:%S UTC")),
+        }
+    }
+}
+
+/// The step whose state is in effect at the step: the step itself, or for a revert, the state it restored
+fn effective_step(history: &[HistoryEntry], step: usize) -> usize {
+    let first = first_entry_step(history);
+    match step.checked_sub(first).and_then(|index| history.get(index)) {
+        Some(&HistoryEntry {
+            change: Change::Revert { step: restored },
+            ..
+        }) if restored < step => effective_step(history, restored),
+        Some(_) | None => step,
+    }
+}
+
+/// The step an undo restores: the one before the state currently in effect, so that repeated undos walk back through the history
+/// # Errors
+/// - If there is no earlier state to restore
+#[inline]
+pub fn undo_step(history: &[HistoryEntry]) -> Result<usize> {
+    let last = (history.len() + first_entry_step(history))
+        .checked_sub(1)
+        .ok_or_else(|| anyhow!("There is no history to undo"))?;
+    effective_step(history, last)
+        .checked_sub(1)
+        .ok_or_else(|| anyhow!("There is no earlier state to undo to"))
+}
+
+/// The step and probability of the earlier state
+/// # Errors
+/// - If the step is not in the history
+/// - If no step was recorded at or before the time
+#[inline]
+pub fn resolve_revert(history: &[HistoryEntry], target: &RevertTarget) -> Result<(usize, f64)> {
+    let points = trajectory(history);
+    let step = match *target {
+        RevertTarget::Step(step) => step,
+        RevertTarget::Time(time) => points
+            .iter()
+            .rposition(|point| {
+                point
+                    .timestamp
+                    .is_some_and(|timestamp| timestamp.trunc_subsecs(0) <= time)
+            })
+            .ok_or_else(|| anyhow!("No state was recorded at or before {target}"))?,
+    };
+    points
+        .get(step)
+        .map(|point| (step, point.probability))
+        .ok_or_else(|| {
+            anyhow!(
+                "There is no step {step}.  The history has steps 0 to {}",
+                points.len().saturating_sub(1)
+            )
+        })
+}
+
+/// Restores the hypothesis to the target state, or undoes the last change if there is no target, recording the revert in its history.
+///
+/// The history is read and the revert saved with the store locked, so the state is never chosen from a history another process has since changed.
+/// # Errors
+/// - If no history has been recorded for the hypothesis
+/// - If the target is not in the history, or there is no earlier state to undo to
+/// - If the store cannot be locked, read or written
+fn revert_to(store: &dyn Store, name: &str, target: Option<&RevertTarget>) -> Result<(usize, f64)> {
+    let mut restored = None;
+    store.transact(&mut |store| {
+        let history = store
+            .get_history(name)?
+            .ok_or_else(|| anyhow!("No history has been recorded for hypothesis {name}"))?;
+        let (step, probability) = if let Some(target) = target {
+            resolve_revert(&history, target)?
+        } else {
+            let step = undo_step(&history)
+                .map_err(|error| anyhow!("Cannot undo the last change to {name}: {error}"))?;
+            resolve_revert(&history, &RevertTarget::Step(step))?
+        };
+        restored = Some((step, probability));
+        append_to_history(
+            store,
+            name,
+            &[HistoryEntry::new(probability, Change::Revert { step })],
+        )
+    })?;
+    restored.ok_or_else(|| anyhow!("{name} could not be reverted"))
+}
+
+/// Rolls back the last change to the prior probability of the hypothesis, recording the rollback in its history.  Returns the step restored and its probability.
+/// # Errors
+/// - If the database cannot be opened
+/// - If no history has been recorded for the hypothesis
+/// - If there is no earlier state to restore
+/// - If the change cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn undo_prior(name: &str) -> Result<(usize, f64)> {
+    revert_to(&*open_store()?, name, None)
+}
+
+/// Restores the prior probability of the hypothesis to an earlier state, recording the revert in its history.  Returns the step restored and its probability.
+/// # Errors
+/// - If the database cannot be opened
+/// - If no history has been recorded for the hypothesis
+/// - If the target is not in the history
+/// - If the change cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn revert_prior(name: &str, target: &RevertTarget) -> Result<(usize, f64)> {
+    revert_to(&*open_store()?, name, Some(target))
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temporary_path;
+    use crate::store::SledStore;
+
+    fn update(prior: f64, probability: f64) -> HistoryEntry {
+        HistoryEntry::new(
+            probability,
+            Change::Update {
+                prior,
+                observation: Observation::new(0.8, 0.2, Evidence::Observed),
+            },
+        )
+    }
+
+    #[test]
+    fn it_round_trips_a_history_entry() -> Result<()> {
+        let entry = update(0.5, 0.8);
+        let serialized = serde_json::to_vec(&[entry.clone()])?;
+        let deserialized: Vec<HistoryEntry> = serde_json::from_slice(&serialized)?;
+        assert_eq!(deserialized, vec![entry]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_starts_the_trajectory_at_the_prior_of_the_first_update() {
+        let points = trajectory(&[update(0.5, 0.8), update(0.8, 0.94)]);
+        let probabilities = points.iter().map(|p| p.probability).collect::<Vec<_>>();
+        assert_eq!(probabilities, vec![0.5, 0.8, 0.94]);
+        assert_eq!(points[0].timestamp, None);
+        assert_eq!(
+            points[1].annotation,
+            "evidence observed with P(E|H) = 0.8, P(E|\u{ac}H) = 0.2"
+        );
+    }
+
+    #[test]
+    fn it_starts_the_trajectory_at_a_set_prior() {
+        let points = trajectory(&[HistoryEntry::new(0.3, Change::Set), update(0.3, 0.63)]);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].annotation, "set");
+    }
+
+    #[test]
+    fn it_annotates_the_trajectory_with_notes() -> Result<()> {
+        let entry = update(0.5, 0.8).with_note("Replica lag alert fired");
+        let points = trajectory(&[entry.clone()]);
+        assert_eq!(
+            points[1].annotation,
+            "evidence observed with P(E|H) = 0.8, P(E|\u{ac}H) = 0.2: Replica lag alert fired"
+        );
+        let serialized = serde_json::to_string(&entry)?;
+        assert!(serialized.contains(r#""note":"Replica lag alert fired""#));
+        assert!(!serde_json::to_string(&update(0.5, 0.8))?.contains("note"));
+        Ok(())
+    }
+
+    fn revert(step: usize, probability: f64) -> HistoryEntry {
+        HistoryEntry::new(probability, Change::Revert { step })
+    }
+
+    #[test]
+    fn it_walks_back_through_the_history_with_repeated_undos() -> Result<()> {
+        let mut history = vec![
+            HistoryEntry::new(0.5, Change::Set),
+            update(0.5, 0.8),
+            update(0.8, 0.94),
+        ];
+        assert_eq!(undo_step(&history)?, 1);
+        history.push(revert(1, 0.8));
+        assert_eq!(undo_step(&history)?, 0);
+        history.push(revert(0, 0.5));
+        assert!(undo_step(&history).is_err());
+        assert!(undo_step(&[]).is_err());
+        assert_eq!(undo_step(&[update(0.5, 0.8)])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reverts_to_a_step_chosen_from_the_saved_history() -> Result<()> {
+        let store = SledStore::open(&temporary_path("revert"))?;
+        assert!(revert_to(&store, "rain", None).is_err());
+        crate::record_change(&store, "rain", &HistoryEntry::new(0.5, Change::Set))?;
+        crate::record_change(&store, "rain", &update(0.5, 0.8))?;
+        crate::record_change(&store, "rain", &update(0.8, 0.94))?;
+        assert_eq!(revert_to(&store, "rain", None)?, (1, 0.8));
+        assert_eq!(revert_to(&store, "rain", None)?, (0, 0.5));
+        assert_eq!(store.get_prior("rain")?, Some(0.5));
+        assert!(revert_to(&store, "rain", None).is_err());
+        assert_eq!(
+            revert_to(&store, "rain", Some(&RevertTarget::Step(2)))?,
+            (2, 0.94)
+        );
+        assert!(revert_to(&store, "rain", Some(&RevertTarget::Step(9))).is_err());
+        let changes: Vec<Change> = store
+            .get_history("rain")?
+            .unwrap_or_default()
+            .into_iter()
+            .skip(3)
+            .map(|entry| entry.change)
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                Change::Revert { step: 1 },
+                Change::Revert { step: 0 },
+                Change::Revert { step: 2 }
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_resolves_a_revert_by_step_or_time() -> Result<()> {
+        let mut history = vec![update(0.5, 0.8), update(0.8, 0.94)];
+        history[0].timestamp = "2022-03-01T12:00:00.250Z".parse()?;
+        history[1].timestamp = "2022-03-02T12:00:00Z".parse()?;
+        assert_eq!(resolve_revert(&history, &RevertTarget::Step(0))?, (0, 0.5));
+        assert_eq!(resolve_revert(&history, &RevertTarget::Step(2))?, (2, 0.94));
+        assert!(resolve_revert(&history, &RevertTarget::Step(3)).is_err());
+        assert_eq!(
+            resolve_revert(&history, &"2022-03-01 12:00:00 UTC".parse()?)?,
+            (1, 0.8)
+        );
+        assert_eq!(
+            resolve_revert(&history, &"2022-03-05T00:00:00+01:00".parse()?)?,
+            (2, 0.94)
+        );
+        assert!(resolve_revert(&history, &"2022-02-01 00:00:00".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_a_revert_target() -> Result<()> {
+        assert_eq!(RevertTarget::from_str("3")?, RevertTarget::Step(3));
+        assert_eq!(
+            RevertTarget::from_str("2022-03-01T12:00:00Z")?,
+            RevertTarget::Time("2022-03-01T12:00:00Z".parse()?)
+        );
+        assert!(RevertTarget::from_str("yesterday").is_err());
+        assert_eq!(revert(2, 0.5).change.to_string(), "reverted to step 2");
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Self-contained HTML reports summarising hypotheses, their history and the calculations behind each update.
+use crate::history::{find_history, first_entry_step, trajectory, Change, HistoryEntry};
+use crate::plot::{escape_xml, render_svg};
+use crate::tags::get_tags;
+use crate::{get_prior, negate, Evidence, Observation};
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// The styles embedded in every report
+const STYLE: &str =
+    "body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+h1 { border-bottom: 2px solid #1f77b4; }
+section { margin-bottom: 3em; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f0f4f8; }
+.tag { background: #e8eef5; border-radius: 0.3em; padding: 0.1em 0.4em; margin-right: 0.3em; }
+.calculation { font-family: monospace; }
+";
+
+/// Everything known about a hypothesis that is included in a report
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct HypothesisReport {
+    /// The name of the hypothesis
+    pub name: String,
+    /// The current prior probability of the hypothesis
+    pub probability: f64,
+    /// The tags attached to the hypothesis
+    pub tags: BTreeSet<String>,
+    /// The recorded history of the hypothesis, oldest first
+    pub history: Vec<HistoryEntry>,
+}
+
+/// Writes out Bayes' theorem for an update with the values substituted in
+#[inline]
+#[must_use]
+pub fn worked_calculation(
+    name: &str,
+    prior: f64,
+    observation: &Observation,
+    posterior_probability: f64,
+) -> String {
+    let (evidence, likelihood, likelihood_null) = match observation.evidence {
+        Evidence::Observed => ("E", observation.likelihood, observation.likelihood_null),
+        Evidence::NotObserved => (
+            "\u{ac}E",
+            negate(observation.likelihood),
+            negate(observation.likelihood_null),
+        ),
+    };
+    let numerator = likelihood * prior;
+    let denominator = likelihood.mul_add(prior, likelihood_null * negate(prior));
+    format!(
+        "P({name}|{evidence}) = P({evidence}|{name}) P({name}) / (P({evidence}|{name}) P({name}) + P({evidence}|\u{ac}{name}) P(\u{ac}{name})) \
+         = {likelihood} \u{d7} {prior} / ({likelihood} \u{d7} {prior} + {likelihood_null} \u{d7} {}) \
+         = {numerator} / {denominator} \
+         = {posterior_probability}",
+        negate(prior),
+    )
+}
+
+/// Renders a standalone HTML document reporting each hypothesis with its current probability, tags, chart, history and worked calculations
+/// # Errors
+/// - If the document cannot be written
+#[inline]
+pub fn render_html_report(title: &str, hypotheses: &[HypothesisReport]) -> Result<String> {
+    let title = escape_xml(title);
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>"
+    )?;
+
+    writeln!(
+        html,
+        "<table>\n<tr><th>Hypothesis</th><th>Probability</th><th>Updates</th></tr>"
+    )?;
+    for hypothesis in hypotheses {
+        let name = escape_xml(&hypothesis.name);
+        let updates = hypothesis
+            .history
+            .iter()
+            .filter(|entry| matches!(entry.change, Change::Update { .. }))
+            .count();
+        writeln!(
+            html,
+            "<tr><td><a href=\"#{name}\">{name}</a></td><td>{}</td><td>{updates}</td></tr>",
+            hypothesis.probability
+        )?;
+    }
+    html.push_str("</table>\n");
+
+    for hypothesis in hypotheses {
+        let name = escape_xml(&hypothesis.name);
+        writeln!(
+            html,
+            "<section id=\"{name}\">\n<h2>{name}</h2>\n<p>P({name}) = {}</p>",
+            hypothesis.probability
+        )?;
+        if !hypothesis.tags.is_empty() {
+            html.push_str("<p>Tags: ");
+            for tag in &hypothesis.tags {
+                write!(html, "<span class=\"tag\">{}</span>", escape_xml(tag))?;
+            }
+            html.push_str("</p>\n");
+        }
+        if hypothesis.history.is_empty() {
+            html.push_str("<p>No history has been recorded for this hypothesis.</p>\n</section>\n");
+            continue;
+        }
+        html.push_str(&render_svg(
+            &hypothesis.name,
+            &trajectory(&hypothesis.history),
+        )?);
+        writeln!(
+            html,
+            "<table>\n<tr><th>Step</th><th>Time</th><th>Probability</th><th>Change</th><th>Calculation</th></tr>"
+        )?;
+        for (step, entry) in hypothesis.history.iter().enumerate() {
+            let calculation = match entry.change {
+                Change::Update {
+                    prior,
+                    ref observation,
+                } => escape_xml(&worked_calculation(
+                    &hypothesis.name,
+                    prior,
+                    observation,
+                    entry.probability,
+                )),
+                Change::Set | Change::Revert { .. } | Change::Merge { .. } => String::new(),
+            };
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"calculation\">{calculation}</td></tr>",
+                step + first_entry_step(&hypothesis.history),
+                entry.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+                entry.probability,
+                escape_xml(&entry.description()),
+            )?;
+        }
+        html.push_str("</table>\n</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Gathers the current probability, tags and history of the hypothesis from the database
+/// # Errors
+/// - If the database cannot be opened
+/// - If the prior probability of the hypothesis is not in the database
+/// - If the saved tags or history cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn collect_hypothesis_report(name: &str) -> Result<HypothesisReport> {
+    Ok(HypothesisReport {
+        name: name.to_owned(),
+        probability: get_prior(name)?,
+        tags: get_tags(name)?,
+        history: find_history(name)?.unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works_through_an_update_when_evidence_is_observed() {
+        let observation = Observation::new(0.8_f64, 0.25_f64, Evidence::Observed);
+        let calculation =
+            worked_calculation("rain", 0.5_f64, &observation, 0.761_904_761_904_762_f64);
+        assert!(calculation.starts_with("P(rain|E) = P(E|rain) P(rain) / "));
+        assert!(calculation.contains("= 0.8 \u{d7} 0.5 / (0.8 \u{d7} 0.5 + 0.25 \u{d7} 0.5)"));
+        assert!(calculation.contains("= 0.4 / 0.525"));
+        assert!(calculation.ends_with("= 0.761904761904762"));
+    }
+
+    #[test]
+    fn it_works_through_an_update_when_evidence_is_not_observed() {
+        let observation = Observation::new(0.75_f64, 0.25_f64, Evidence::NotObserved);
+        let calculation = worked_calculation("rain", 0.5_f64, &observation, 0.25_f64);
+        assert!(calculation.starts_with("P(rain|\u{ac}E) = P(\u{ac}E|rain) P(rain)"));
+        assert!(calculation.contains("= 0.25 \u{d7} 0.5 / (0.25 \u{d7} 0.5 + 0.75 \u{d7} 0.5)"));
+    }
+
+    #[test]
+    fn it_renders_a_self_contained_html_report() -> Result<()> {
+        let hypotheses = [
+            HypothesisReport {
+                name: "db<overload>".to_owned(),
+                probability: 0.8_f64,
+                tags: BTreeSet::from(["incident-42".to_owned()]),
+                history: vec![
+                    HistoryEntry::new(0.5_f64, Change::Set),
+                    HistoryEntry::new(
+                        0.8_f64,
+                        Change::Update {
+                            prior: 0.5_f64,
+                            observation: Observation::new(0.8_f64, 0.2_f64, Evidence::Observed),
+                        },
+                    ),
+                ],
+            },
+            HypothesisReport {
+                name: "cache".to_owned(),
+                probability: 0.1_f64,
+                tags: BTreeSet::new(),
+                history: Vec::new(),
+            },
+        ];
+        let html = render_html_report("incident-42", &hypotheses)?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>db&lt;overload&gt;</h2>"));
+        assert!(!html.contains("<overload>"));
+        assert!(html.contains("<span class=\"tag\">incident-42</span>"));
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert!(html.contains("= 0.8 \u{d7} 0.5 / (0.8 \u{d7} 0.5 + 0.2 \u{d7} 0.5)"));
+        assert!(html.contains("No history has been recorded for this hypothesis."));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("<link"));
+        Ok(())
+    }
+}
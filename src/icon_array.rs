@@ -0,0 +1,286 @@
+//! Natural-frequency and icon-array visualisations of an update.
+use crate::diagnostic::{format_count, NaturalFrequencies};
+use crate::{Evidence, Rounded};
+use console::{colors_enabled, style, Color};
+use log::info;
+use num_traits::ToPrimitive;
+
+/// The number of icons drawn in an icon array
+pub const ICON_COUNT: usize = 100;
+/// The number of icons drawn on each row of an icon array
+const ICONS_PER_ROW: usize = 10;
+
+/// The cell of the joint distribution of the hypothesis and the evidence that an icon stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IconKind {
+    /// The hypothesis is true and the evidence is observed
+    HypothesisAndEvidence,
+    /// The hypothesis is true and the evidence is not observed
+    HypothesisWithoutEvidence,
+    /// The hypothesis is false and the evidence is observed
+    EvidenceWithoutHypothesis,
+    /// The hypothesis is false and the evidence is not observed
+    Neither,
+}
+
+impl IconKind {
+    /// Every kind of icon, in the order they are drawn
+    pub const ALL: [Self; 4] = [
+        Self::HypothesisAndEvidence,
+        Self::HypothesisWithoutEvidence,
+        Self::EvidenceWithoutHypothesis,
+        Self::Neither,
+    ];
+
+    /// The glyph used for the icon, coloured if `colour` is set and plain ASCII otherwise
+    #[inline]
+    #[must_use]
+    pub fn glyph(self, colour: bool) -> String {
+        if colour {
+            let colour = match self {
+                Self::HypothesisAndEvidence => Color::Green,
+                Self::HypothesisWithoutEvidence => Color::Yellow,
+                Self::EvidenceWithoutHypothesis => Color::Red,
+                Self::Neither => Color::White,
+            };
+            style("\u{25a0}").fg(colour).force_styling(true).to_string()
+        } else {
+            match self {
+                Self::HypothesisAndEvidence => "#",
+                Self::HypothesisWithoutEvidence => "+",
+                Self::EvidenceWithoutHypothesis => "x",
+                Self::Neither => ".",
+            }
+            .to_owned()
+        }
+    }
+
+    /// The label of the cell the icon stands for
+    #[inline]
+    #[must_use]
+    pub fn label(self, name: &str) -> String {
+        match self {
+            Self::HypothesisAndEvidence => format!("{name} \u{2227} E"),
+            Self::HypothesisWithoutEvidence => format!("{name} \u{2227} \u{ac}E"),
+            Self::EvidenceWithoutHypothesis => format!("\u{ac}{name} \u{2227} E"),
+            Self::Neither => format!("\u{ac}{name} \u{2227} \u{ac}E"),
+        }
+    }
+
+    /// The expected number of cases in the cell the icon stands for
+    const fn count(self, frequencies: &NaturalFrequencies) -> f64 {
+        match self {
+            Self::HypothesisAndEvidence => frequencies.true_positives,
+            Self::HypothesisWithoutEvidence => frequencies.false_negatives,
+            Self::EvidenceWithoutHypothesis => frequencies.false_positives,
+            Self::Neither => frequencies.true_negatives,
+        }
+    }
+}
+
+/// Picks the smallest power of ten, starting at [`ICON_COUNT`], for which every cell of the update that can happen holds at least one whole case.
+#[inline]
+#[must_use]
+pub fn natural_population(prior: f64, likelihood: f64, likelihood_null: f64) -> f64 {
+    let smallest = [
+        prior * likelihood,
+        prior * (1.0_f64 - likelihood),
+        (1.0_f64 - prior) * likelihood_null,
+        (1.0_f64 - prior) * (1.0_f64 - likelihood_null),
+    ]
+    .into_iter()
+    .filter(|p| *p > 0.0_f64)
+    .fold(1.0_f64, f64::min);
+    let mut population = 100.0_f64;
+    while population * smallest < 1.0_f64 - 1e-9_f64 && population < 1e9_f64 {
+        population *= 10.0_f64;
+    }
+    population
+}
+
+/// Divides `icon_count` icons between the cells in proportion to their frequencies.
+///
+/// Uses the largest remainder method so the icons always add up to `icon_count`.
+#[inline]
+#[must_use]
+pub fn allocate_icons(frequencies: &NaturalFrequencies, icon_count: usize) -> [usize; 4] {
+    let total = icon_count.to_f64().unwrap_or_default();
+    let quotas = IconKind::ALL.map(|kind| {
+        if frequencies.population > 0.0_f64 {
+            kind.count(frequencies) / frequencies.population * total
+        } else {
+            0.0_f64
+        }
+    });
+    let mut icons = quotas.map(|quota| quota.floor().to_usize().unwrap_or_default());
+    let mut by_remainder = [0_usize, 1, 2, 3];
+    by_remainder.sort_by(|a, b| {
+        let remainder = |i: usize| quotas[i] - quotas[i].floor();
+        remainder(*b).total_cmp(&remainder(*a))
+    });
+    let allocated: usize = icons.iter().sum();
+    for index in by_remainder
+        .into_iter()
+        .cycle()
+        .take(icon_count.saturating_sub(allocated))
+    {
+        icons[index] += 1;
+    }
+    icons
+}
+
+/// Renders an icon array of [`ICON_COUNT`] icons followed by a legend of the cells they stand for
+#[inline]
+#[must_use]
+pub fn render_icon_array(name: &str, frequencies: &NaturalFrequencies, colour: bool) -> String {
+    let icons = allocate_icons(frequencies, ICON_COUNT);
+    let glyphs = IconKind::ALL
+        .iter()
+        .zip(icons)
+        .flat_map(|(kind, count)| std::iter::repeat_n(kind.glyph(colour), count))
+        .collect::<Vec<_>>();
+    let mut lines = glyphs
+        .chunks(ICONS_PER_ROW)
+        .map(|row| format!("                {}", row.join(" ")))
+        .collect::<Vec<_>>();
+    lines.push(String::new());
+    for (kind, count) in IconKind::ALL.iter().zip(icons) {
+        lines.push(format!(
+            "                {} {}: {} of {} ({count} icons)",
+            kind.glyph(colour),
+            kind.label(name),
+            format_count(kind.count(frequencies)),
+            format_count(frequencies.population),
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Explains an update in terms of natural frequencies, conditioned on whether the evidence was observed
+#[inline]
+#[must_use]
+pub fn explain_update(name: &str, frequencies: &NaturalFrequencies, evidence: &Evidence) -> String {
+    let (with_outcome, without_outcome, total, outcome) = match *evidence {
+        Evidence::Observed => (
+            frequencies.true_positives,
+            frequencies.false_positives,
+            frequencies.positives(),
+            "show the evidence",
+        ),
+        Evidence::NotObserved => (
+            frequencies.false_negatives,
+            frequencies.true_negatives,
+            frequencies.negatives(),
+            "do not show the evidence",
+        ),
+    };
+    let mut sentences = vec![
+        format!(
+            "Out of {} cases, {} have {name}, and {} of them {outcome}.",
+            format_count(frequencies.population),
+            format_count(frequencies.with_condition()),
+            format_count(with_outcome),
+        ),
+        format!(
+            "Of the {} without {name}, {} also {outcome}.",
+            format_count(frequencies.without_condition()),
+            format_count(without_outcome),
+        ),
+    ];
+    if total > 0.0_f64 {
+        sentences.push(format!(
+            "So of the {} cases that {outcome}, {} ({:.1}%) have {name}.",
+            format_count(total),
+            format_count(with_outcome),
+            with_outcome / total * 100.0_f64,
+        ));
+    }
+    sentences.join("  ")
+}
+
+/// Reports an update as natural frequencies and an icon array, falling back to plain ASCII glyphs when colour is unavailable
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_icon_array(
+    name: &str,
+    prior: f64,
+    likelihood: f64,
+    likelihood_null: f64,
+    evidence: &Evidence,
+    posterior_probability: f64,
+) {
+    let population = natural_population(prior, likelihood, likelihood_null);
+    let frequencies = NaturalFrequencies::new(prior, likelihood, likelihood_null, population);
+    let evidence_label = match *evidence {
+        Evidence::Observed => "E",
+        Evidence::NotObserved => "\u{ac}E",
+    };
+    info!(
+        "\n{}\n\n                {}\n\n                P({name}|{evidence_label}) = {}",
+        render_icon_array(name, &frequencies, colors_enabled()),
+        explain_update(name, &frequencies, evidence),
+        Rounded(posterior_probability),
+    );
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_allocates_every_icon() {
+        let frequencies = NaturalFrequencies::new(0.01_f64, 0.9_f64, 0.09_f64, 10_000.0_f64);
+        let icons = allocate_icons(&frequencies, ICON_COUNT);
+        assert_eq!(icons.iter().sum::<usize>(), ICON_COUNT);
+        assert_eq!(icons, [1, 0, 9, 90]);
+    }
+
+    #[test]
+    fn it_allocates_icons_by_largest_remainder() {
+        let frequencies = NaturalFrequencies::new(1.0_f64 / 3.0_f64, 0.5_f64, 0.5_f64, 300.0_f64);
+        let icons = allocate_icons(&frequencies, ICON_COUNT);
+        assert_eq!(icons.iter().sum::<usize>(), ICON_COUNT);
+        assert!(icons.iter().all(|count| (16..=34).contains(count)));
+    }
+
+    #[test]
+    fn it_picks_a_population_with_whole_cases_in_every_cell() {
+        assert!((natural_population(0.5_f64, 0.5_f64, 0.5_f64) - 100.0_f64).abs() < f64::EPSILON);
+        assert!(
+            (natural_population(0.01_f64, 0.9_f64, 0.09_f64) - 1000.0_f64).abs() < f64::EPSILON
+        );
+        assert!((natural_population(0.5_f64, 1.0_f64, 0.0_f64) - 100.0_f64).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn it_renders_an_ascii_icon_array() {
+        let frequencies = NaturalFrequencies::new(0.25_f64, 0.8_f64, 0.2_f64, 100.0_f64);
+        let output = render_icon_array("rain", &frequencies, false);
+        let grid = output.lines().take(ICONS_PER_ROW).collect::<Vec<_>>();
+        assert_eq!(grid[0].trim(), "# # # # # # # # # #");
+        assert_eq!(grid[2].trim(), "+ + + + + x x x x x");
+        assert_eq!(output.matches('#').count(), 21);
+        assert!(output.contains("rain \u{2227} E: 20 of 100 (20 icons)"));
+        assert!(!output.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn it_explains_an_update_when_evidence_is_observed() {
+        let frequencies = NaturalFrequencies::new(0.25_f64, 0.8_f64, 0.2_f64, 100.0_f64);
+        let explanation = explain_update("rain", &frequencies, &Evidence::Observed);
+        assert_eq!(
+            explanation,
+            "Out of 100 cases, 25 have rain, and 20 of them show the evidence.  Of the 75 without rain, 15 also show the evidence.  So of the 35 cases that show the evidence, 20 (57.1%) have rain."
+        );
+    }
+
+    #[test]
+    fn it_explains_an_update_when_evidence_is_not_observed() {
+        let frequencies = NaturalFrequencies::new(0.25_f64, 0.8_f64, 0.2_f64, 100.0_f64);
+        let explanation = explain_update("rain", &frequencies, &Evidence::NotObserved);
+        assert!(explanation
+            .contains("So of the 65 cases that do not show the evidence, 5 (7.7%) have rain."));
+    }
+}
@@ -20,26 +20,138 @@
 #![allow(clippy::struct_excessive_bools)]
 
 use anyhow::{anyhow, Error, Result};
-use clap::Parser;
-use dialoguer::Input;
-use dirs::home_dir;
+use categorical::{CategoryCount, CategoryPrior};
+use clap::{Parser, Subcommand};
+use clap_complete::Shell;
+use comparison::ModelLikelihood;
+use decision::{Action, ACTIONS_METADATA};
+use dialoguer::{Confirm, FuzzySelect, Input};
+use elicitation::{elicit_likelihoods, Likelihoods};
+use history::{Change, HistoryEntry, RevertTarget};
+use icon_array::report_icon_array;
 use log::info;
+use named_evidence::HYPOTHESIS_EVIDENCE_METADATA;
+use names::removal;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
 use prettytable::{format, Cell, Row, Table};
+use quantity::GaussianModel;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sled::Db;
 use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::OnceLock;
+use store::{open_store, Operation, Store, StoreLocation};
+use tags::TAGS_METADATA;
+use value_of_information::EvidenceTest;
+
+pub mod categorical;
+pub mod comparison;
+pub mod completions;
+pub mod config;
+pub mod dashboard;
+pub mod decision;
+pub mod diagnostic;
+pub mod dir_store;
+pub mod elicitation;
+pub mod history;
+pub mod html_report;
+pub mod icon_array;
+pub mod named_evidence;
+pub mod names;
+pub mod namespace;
+pub mod plot;
+pub mod quantity;
+pub mod server;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod store;
+pub mod tags;
+pub mod value_of_information;
 
 /// The prelude for the `ask-bayes` crate.
 pub mod prelude {
+    pub use crate::categorical::{
+        get_categorical, list_categoricals, remove_categorical, report_categorical,
+        report_categoricals, set_categorical, update_categorical, CategoricalBelief,
+        CategoricalEntry, Category, CategoryCount, CategoryEstimate, CategoryPrior,
+    };
+    pub use crate::comparison::{
+        compare_models, explain_comparison, kass_raftery_strength, report_model_comparison,
+        ModelComparison, ModelLikelihood,
+    };
+    pub use crate::completions::{hypothesis_names, write_completions};
+    pub use crate::config::{
+        Config, Defaults, EvidencePreset, NamespaceDefaults, OutputSettings, ResolvedUpdate,
+    };
+    pub use crate::dashboard::{run_dashboard, Dashboard};
+    pub use crate::decision::{
+        analyze_decision, find_actions, get_actions, remove_actions, report_decision_analysis,
+        set_actions, Action, DecisionAnalysis, DecisionThreshold,
+    };
+    pub use crate::diagnostic::{
+        analyze_diagnostic_test, explain_natural_frequencies, natural_frequencies,
+        report_diagnostic_analysis, DiagnosticAnalysis, NaturalFrequencies,
+    };
+    pub use crate::dir_store::DirStore;
+    pub use crate::elicitation::{
+        describe_likelihood_ratio, elicit_likelihoods, evidence_strength,
+        likelihoods_from_frequencies, likelihoods_from_ratio, parse_likelihood_ratio,
+        report_likelihoods, EvidenceStrength, Likelihoods,
+    };
+    pub use crate::history::{
+        find_history, first_entry_step, get_history, resolve_revert, revert_prior, trajectory,
+        undo_prior, undo_step, Change, HistoryEntry, RevertTarget, TrajectoryPoint,
+    };
+    pub use crate::html_report::{
+        collect_hypothesis_report, render_html_report, worked_calculation, HypothesisReport,
+    };
+    pub use crate::icon_array::{
+        allocate_icons, explain_update, natural_population, render_icon_array, report_icon_array,
+        IconKind,
+    };
+    pub use crate::named_evidence::{
+        get_evidence, list_evidence, remove_evidence, report_evidence, set_evidence,
+        EvidenceDefinition, NamedEvidence,
+    };
+    pub use crate::names::{check_name, edit_distance, merge_priors, rename_prior, similar_names};
+    pub use crate::namespace::{
+        enclosing_namespaces, export_namespace, list_namespace, move_namespace, namespace_prefix,
+        report_hypotheses, ExportedHypothesis,
+    };
+    pub use crate::plot::{render_chart, render_svg};
+    pub use crate::quantity::{
+        get_quantity, list_quantities, parse_measurements_csv, remove_quantity, report_quantities,
+        report_quantity, set_quantity, update_quantity, GaussianBelief, GaussianModel, Quantity,
+        QuantityEntry,
+    };
+    pub use crate::server::{handle_request, ApiResponse, ApiServer};
+    #[cfg(feature = "sqlite")]
+    pub use crate::sqlite_store::SqliteStore;
+    pub use crate::store::{
+        migrate, open_store, open_store_at, store_location, use_store, Operation, SledStore, Store,
+        StoreLocation,
+    };
+    pub use crate::tags::{add_tags, get_tags, hypotheses_with_tag, remove_tags};
+    pub use crate::value_of_information::{
+        calculate_expected_value_of_perfect_information, calculate_value_of_information,
+        report_value_of_information, EvidenceTest, TestOutcome, ValueOfInformation,
+    };
     pub use crate::{
-        calculate_posterior_probability, get_prior, remove_prior, report_posterior_probability,
-        set_prior, wizard, Args, Evidence, UpdateHypothesis,
+        calculate_chained_posterior_probability, calculate_exact_posterior_probability,
+        calculate_posterior_probability, get_prior, list_hypotheses, parse_batch,
+        parse_probability, remove_prior, report_exact_posterior_probability,
+        report_posterior_probability, report_probability_interpretation, set_prior, update_prior,
+        update_prior_with_observations, update_priors, use_precision, wizard, Args, BatchUpdate,
+        CategoriesCommand, Command, ConfigCommand, Evidence, EvidenceCommand, Observation,
+        OutputFormat, Probability, ProbabilityNotation, QuantityCommand, Rounded, UpdateHypothesis,
     };
 }
 
 /// Whether or not evidence supporting the hypothesis was observed
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum Evidence {
     /// Evidence supporting the hypothesis was observed
@@ -114,6 +226,8 @@ pub enum OutputFormat {
     Json,
     /// Output in a formatted string
     Simple,
+    /// Output as natural frequencies and an icon array
+    Icons,
 }
 
 impl FromStr for OutputFormat {
@@ -125,6 +239,7 @@ impl FromStr for OutputFormat {
             "table" | "Table" | "t" | "T" => Ok(Self::Table),
             "json" | "Json" | "j" | "J" => Ok(Self::Json),
             "simple" | "Simple" | "s" | "S" => Ok(Self::Simple),
+            "icons" | "Icons" | "i" | "I" => Ok(Self::Icons),
             _ => Err(anyhow!("Invalid output format: {}", s)),
         }
     }
@@ -137,6 +252,152 @@ impl Display for OutputFormat {
             Self::Table => write!(f, "Table"),
             Self::Json => write!(f, "Json"),
             Self::Simple => write!(f, "Simple"),
+            Self::Icons => write!(f, "Icons"),
+        }
+    }
+}
+
+/// The notation a probability was written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProbabilityNotation {
+    /// A decimal, e.g. `0.75`
+    Decimal,
+    /// A percentage, e.g. `75%`
+    Percentage,
+    /// A fraction, e.g. `3/4`
+    Fraction,
+    /// Odds in favour, e.g. `3:1`
+    Odds,
+    /// A natural frequency, e.g. `1 in 1000`
+    Frequency,
+}
+
+impl Display for ProbabilityNotation {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Decimal => write!(f, "decimal"),
+            Self::Percentage => write!(f, "percentage"),
+            Self::Fraction => write!(f, "fraction"),
+            Self::Odds => write!(f, "odds"),
+            Self::Frequency => write!(f, "frequency"),
+        }
+    }
+}
+
+/// A probability supplied by the user.  Retains the exact rational value that was entered alongside its floating point approximation.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Probability {
+    /// The floating point value of the probability
+    value: f64,
+    /// The exact rational value of the probability
+    exact: BigRational,
+    /// The notation the probability was written in
+    notation: ProbabilityNotation,
+    /// The text the probability was parsed from
+    input: String,
+}
+
+impl Probability {
+    /// A probability with a value that was not typed by the user, e.g. one saved in the store.  Its exact value is the shortest decimal that rounds to the value.
+    /// # Errors
+    /// - If the value is not between 0 and 1
+    #[inline]
+    pub fn from_value(value: f64) -> Result<Self> {
+        validate_probability(value)?;
+        Ok(Self {
+            value,
+            exact: parse_rational(&value.to_string())?,
+            notation: ProbabilityNotation::Decimal,
+            input: value.to_string(),
+        })
+    }
+
+    /// The floating point value of the probability
+    #[inline]
+    #[must_use]
+    pub const fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// The exact rational value of the probability
+    #[inline]
+    #[must_use]
+    pub const fn exact(&self) -> &BigRational {
+        &self.exact
+    }
+
+    /// The notation the probability was written in
+    #[inline]
+    #[must_use]
+    pub const fn notation(&self) -> ProbabilityNotation {
+        self.notation
+    }
+
+    /// Describes how the input was interpreted, e.g. `3:1 (odds) = 3/4 = 0.75`.  Returns `None` for plain decimals.
+    #[inline]
+    #[must_use]
+    pub fn interpretation(&self) -> Option<String> {
+        match self.notation {
+            ProbabilityNotation::Decimal => None,
+            ProbabilityNotation::Fraction => Some(format!(
+                "{} ({}) = {}",
+                self.input,
+                self.notation,
+                Rounded(self.value)
+            )),
+            ProbabilityNotation::Percentage
+            | ProbabilityNotation::Odds
+            | ProbabilityNotation::Frequency => Some(format!(
+                "{} ({}) = {} = {}",
+                self.input,
+                self.notation,
+                self.exact,
+                Rounded(self.value)
+            )),
+        }
+    }
+}
+
+impl FromStr for Probability {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_probability(s)
+    }
+}
+
+impl Display for Probability {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+/// A single observation used to update a hypothesis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Observation {
+    /// The likelihood of the evidence P(E|H)
+    pub likelihood: f64,
+    /// The likelihood of the evidence P(E|¬H)
+    pub likelihood_null: f64,
+    /// Whether or not the evidence was observed
+    pub evidence: Evidence,
+}
+
+impl Observation {
+    /// Creates a new observation
+    #[inline]
+    #[must_use]
+    pub const fn new(likelihood: f64, likelihood_null: f64, evidence: Evidence) -> Self {
+        Self {
+            likelihood,
+            likelihood_null,
+            evidence,
         }
     }
 }
@@ -144,8 +405,11 @@ impl Display for OutputFormat {
 /// Arguments for the `ask-bayes` command
 #[derive(Parser, Debug)]
 #[non_exhaustive]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 pub struct Args {
+    /// A command to run instead of updating a hypothesis
+    #[clap(subcommand)]
+    pub command: Option<Command>,
     /// Name of the Hypothesis to update
     #[clap(
         short,
@@ -154,42 +418,33 @@ pub struct Args {
         required_unless_present("wizard")
     )]
     pub name: Option<String>,
-    /// The prior probability of the hypothesis P(H)
-    #[clap(
-        short,
-        long,
-        default_value_if("name", None, Some("0.5")),
-        validator = parse_validate_probability,
-        forbid_empty_values = true,
-        required_unless_present("wizard")
-    )]
-    pub prior: Option<f64>,
-    /// The likelihood of the evidence P(E|H)
+    /// The prior probability of the hypothesis P(H).  Defaults to the configured prior, otherwise 0.5
+    #[clap(short, long, forbid_empty_values = true)]
+    pub prior: Option<Probability>,
+    /// The likelihood of the evidence P(E|H).  Defaults to the preset, otherwise the configured likelihood, otherwise 0.5
+    #[clap(short, long, forbid_empty_values = true)]
+    pub likelihood: Option<Probability>,
+    /// The likelihood of the evidence P(E|¬H).  Defaults to the preset, otherwise the configured likelihood, otherwise 0.5
+    #[clap(long, forbid_empty_values = true)]
+    pub likelihood_null: Option<Probability>,
+    /// Indicates whether supporting evidence is observed.  Defaults to the preset, otherwise the configured evidence, otherwise observed
     #[clap(
         short,
         long,
-        default_value_if("name", None, Some("0.5")),
-        validator = parse_validate_probability,
-        forbid_empty_values = true,
-        required_unless_present("wizard"))]
-    pub likelihood: Option<f64>,
-    /// The likelihood of the evidence P(E|¬H)
+        default_missing_value = "Observed",
+        possible_values = ["o", "observed", "Observed", "n", "not-observed", "NotObserved"])]
+    pub evidence: Option<Evidence>,
+    /// Applies a named evidence preset from the configuration file, supplying the likelihoods and evidence not given as flags
+    #[clap(long, value_name = "PRESET", forbid_empty_values = true)]
+    pub preset: Option<String>,
+    /// Applies evidence saved with `evidence set`, supplying the likelihoods not given as flags.  Likelihoods saved for the hypothesis take precedence over generic ones
     #[clap(
         long,
-        default_value_if("name", None, Some("0.5")),
-        validator = parse_validate_probability,
+        value_name = "EVIDENCE",
         forbid_empty_values = true,
-        required_unless_present("wizard"))]
-    pub likelihood_null: Option<f64>,
-    /// Indicates whether supporting evidence is observed
-    #[clap(
-        short,
-        long,
-        default_value_if("name", None, Some("Observed")),
-        default_missing_value = "Observed",
-        possible_values = ["o", "observed", "Observed", "n", "not-observed", "NotObserved"],
-        required_unless_present("wizard"))]
-    pub evidence: Option<Evidence>,
+        conflicts_with = "preset"
+    )]
+    pub with_evidence: Option<String>,
     /// Updates the prior probability of the hypothesis P(H) to the new posterior probability, saving it to the database
     #[clap(
         short,
@@ -207,6 +462,8 @@ pub struct Args {
         conflicts_with = "likelihood",
         conflicts_with = "likelihood-null",
         conflicts_with = "evidence",
+        conflicts_with = "preset",
+        conflicts_with = "with-evidence",
         conflicts_with = "update-prior"
     )]
     pub get_prior: bool,
@@ -215,15 +472,16 @@ pub struct Args {
     #[clap(
         short,
         long,
-        validator = parse_validate_probability,
         conflicts_with = "prior",
         conflicts_with = "likelihood",
         conflicts_with = "likelihood-null",
         conflicts_with = "evidence",
+        conflicts_with = "preset",
+        conflicts_with = "with-evidence",
         conflicts_with = "update-prior",
         conflicts_with = "get-prior"
     )]
-    pub set_prior: Option<f64>,
+    pub set_prior: Option<Probability>,
     /// Removes the prior probability of the hypothesis P(H) from the database.
     /// Incompatible with other flags aside from `--name`
     #[clap(
@@ -233,23 +491,531 @@ pub struct Args {
         conflicts_with = "likelihood",
         conflicts_with = "likelihood-null",
         conflicts_with = "evidence",
+        conflicts_with = "preset",
+        conflicts_with = "with-evidence",
         conflicts_with = "update-prior",
         conflicts_with = "set-prior",
         conflicts_with = "get-prior"
     )]
     pub remove_prior: bool,
     /// Runs the wizard to help guide you through the process of updating a hypothesis
-    #[clap(short, long, exclusive = true, takes_value = false)]
+    #[clap(
+        short,
+        long,
+        takes_value = false,
+        conflicts_with_all = &["name", "prior", "likelihood", "likelihood-null", "evidence", "preset", "with-evidence", "update-prior", "get-prior", "set-prior", "remove-prior", "output", "exact"]
+    )]
     pub wizard: bool,
-    /// The type of output to display
+    /// The type of output to display.  Defaults to the configured format, otherwise a table
     #[clap(
         short,
         long,
-        default_value_if("name", None, Some("Table")),
-        possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S"],
-        required_unless_present("wizard")
+        possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S", "i", "icons", "Icons", "I"]
     )]
     pub output: Option<OutputFormat>,
+    /// Performs the calculation using exact rational arithmetic.  Probabilities may be given as fractions, e.g. `1/3`
+    #[clap(
+        short = 'x',
+        long,
+        takes_value = false,
+        conflicts_with = "get-prior",
+        conflicts_with = "set-prior",
+        conflicts_with = "remove-prior"
+    )]
+    pub exact: bool,
+    /// The store holding saved hypotheses: `sled`, `sqlite` or `dir`, optionally followed by `:PATH`, e.g. `dir:./beliefs`.  Defaults to `ASK_BAYES_STORE` if it is set, otherwise the configured store, otherwise the sled database in `~/.ask-bayes`
+    #[clap(long, global = true, value_name = "STORE")]
+    pub store: Option<StoreLocation>,
+    /// The configuration file to read defaults, output preferences and evidence presets from.  Defaults to `~/.config/ask-bayes/config.toml`
+    #[clap(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+/// Commands that can be run instead of updating a hypothesis
+#[derive(Subcommand, Debug)]
+#[non_exhaustive]
+pub enum Command {
+    /// Reports the expected utility of each action at the current probability of the hypothesis, the best action, and the probabilities at which the best action changes
+    Decide {
+        /// Name of the hypothesis the actions depend on
+        #[clap(short, long, forbid_empty_values = true)]
+        name: String,
+        /// The probability of the hypothesis P(H).  Defaults to the saved value of the prior probability of the hypothesis
+        #[clap(short, long)]
+        prior: Option<Probability>,
+        /// An action and its utilities if the hypothesis is true or false, in the form `name=utility_if_true,utility_if_false`.
+        /// Defaults to the actions saved for the hypothesis
+        #[clap(short, long = "action", multiple_occurrences = true)]
+        actions: Vec<Action>,
+        /// Saves the given actions for the hypothesis, replacing any previously saved actions
+        #[clap(short, long, requires = "actions")]
+        save: bool,
+        /// Removes the saved actions for the hypothesis
+        #[clap(short, long, conflicts_with = "actions", conflicts_with = "save")]
+        remove: bool,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S", "i", "icons", "Icons", "I"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Reports how much running each test is expected to be worth before choosing between the actions saved for a hypothesis
+    Voi {
+        /// Name of the hypothesis the actions depend on
+        #[clap(short, long, forbid_empty_values = true)]
+        name: String,
+        /// The probability of the hypothesis P(H).  Defaults to the saved value of the prior probability of the hypothesis
+        #[clap(short, long)]
+        prior: Option<Probability>,
+        /// A test to evaluate, in the form `name=likelihood,likelihood_null` or `name=likelihood,likelihood_null,cost`.
+        /// Repeat to compare several tests
+        #[clap(short, long = "test", required = true, multiple_occurrences = true)]
+        tests: Vec<EvidenceTest>,
+        /// An action and its utilities if the hypothesis is true or false, in the form `name=utility_if_true,utility_if_false`.
+        /// Defaults to the actions saved for the hypothesis
+        #[clap(short, long = "action", multiple_occurrences = true)]
+        actions: Vec<Action>,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S", "i", "icons", "Icons", "I"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Compares two competing models of the same data by their Bayes factor, the ratio of the likelihood of the data under each.
+    /// Reports the strength of the evidence and the posterior probability of each model
+    Compare {
+        /// The likelihood of the data under model A P(D|A).  Repeat to give the likelihood of each of a series of independent observations instead
+        #[clap(
+            short = 'a',
+            long = "model-a",
+            value_name = "LIKELIHOOD",
+            required = true,
+            multiple_occurrences = true
+        )]
+        likelihoods_a: Vec<ModelLikelihood>,
+        /// The likelihood of the data under model B P(D|B).  Repeat to give the likelihood of each of the same observations
+        #[clap(
+            short = 'b',
+            long = "model-b",
+            value_name = "LIKELIHOOD",
+            required = true,
+            multiple_occurrences = true
+        )]
+        likelihoods_b: Vec<ModelLikelihood>,
+        /// Name of model A
+        #[clap(long, default_value = "A", forbid_empty_values = true)]
+        name_a: String,
+        /// Name of model B
+        #[clap(long, default_value = "B", forbid_empty_values = true)]
+        name_b: String,
+        /// The prior probability of model A P(A), e.g. `0.5`, or the prior odds of model A against model B, e.g. `1:3`.  Model B has the remaining probability
+        #[clap(short, long, default_value = "0.5")]
+        prior: Probability,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Evaluates a diagnostic test from its sensitivity, specificity and the prevalence of the condition.
+    /// Reports the predictive values, error rates and likelihood ratios of the test, explained with natural frequencies
+    Diagnose {
+        /// Name of the condition the test detects
+        #[clap(short, long, forbid_empty_values = true)]
+        name: String,
+        /// The probability that a case with the condition tests positive P(E|H)
+        #[clap(long, visible_alias = "sens")]
+        sensitivity: Probability,
+        /// The probability that a case without the condition tests negative P(¬E|¬H)
+        #[clap(long, visible_alias = "spec")]
+        specificity: Probability,
+        /// The base rate of the condition P(H).  Defaults to the saved value of the prior probability of the hypothesis
+        #[clap(short, long, visible_alias = "base-rate")]
+        prevalence: Option<Probability>,
+        /// The number of cases used to explain the results with natural frequencies
+        #[clap(long, default_value = "10000")]
+        population: u64,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S", "i", "icons", "Icons", "I"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Draws how the prior probability of a hypothesis has evolved across its recorded updates
+    Plot {
+        /// Name of the hypothesis to plot
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// Also writes the chart to a standalone SVG file
+        #[clap(long, value_name = "FILE")]
+        svg: Option<PathBuf>,
+        /// The width of the chart in columns
+        #[clap(long, default_value = "60")]
+        width: usize,
+        /// The height of the chart in rows
+        #[clap(long, default_value = "15")]
+        height: usize,
+        /// Draws the chart with plain ASCII characters instead of Unicode
+        #[clap(long)]
+        ascii: bool,
+    },
+    /// Attaches tags to a hypothesis, or lists its tags if none are given
+    Tag {
+        /// Name of the hypothesis to tag
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The tags to attach
+        tags: Vec<String>,
+        /// Detaches the given tags instead
+        #[clap(short, long, requires = "tags")]
+        remove: bool,
+    },
+    /// Writes a self-contained HTML report of hypotheses with their current probabilities, history, worked calculations and charts
+    Report {
+        /// Name of a hypothesis to include.  Repeat to include several hypotheses
+        #[clap(
+            short,
+            long = "name",
+            multiple_occurrences = true,
+            required_unless_present = "tag"
+        )]
+        names: Vec<String>,
+        /// Includes every hypothesis with the tag
+        #[clap(short, long)]
+        tag: Option<String>,
+        /// The file to write the report to
+        #[clap(long, value_name = "FILE")]
+        html: PathBuf,
+    },
+    /// Serves a JSON API on localhost for calculating posteriors and reading, updating and removing hypotheses
+    Serve {
+        /// The port to listen on.  Port 0 picks a free port
+        #[clap(short, long, default_value = "8000")]
+        port: u16,
+    },
+    /// Rolls back the last change to the prior probability of the hypothesis.  The rollback is recorded in its history, so repeated undos walk back through it
+    Undo {
+        /// Name of the hypothesis
+        #[clap(forbid_empty_values = true)]
+        name: String,
+    },
+    /// Restores the prior probability of the hypothesis to an earlier step of its history, as numbered by `plot`.  The revert is recorded in its history
+    Revert {
+        /// Name of the hypothesis
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The step to restore, or a time such as `2022-03-01 12:00:00 UTC` to restore the latest step recorded at or before it
+        #[clap(long, value_name = "STEP|TIMESTAMP")]
+        to: RevertTarget,
+    },
+    /// Renames a hypothesis, moving its prior probability, history, actions, tags and saved evidence to the new name
+    Rename {
+        /// Name of the hypothesis
+        #[clap(forbid_empty_values = true)]
+        from: String,
+        /// The new name of the hypothesis, which must not already be saved
+        #[clap(forbid_empty_values = true)]
+        to: String,
+    },
+    /// Merges a hypothesis into another, e.g. to consolidate a hypothesis saved under a mistyped name.
+    /// The hypothesis merged into keeps its prior probability and gains the history, tags and saved evidence of the other, which is removed
+    Merge {
+        /// Name of the hypothesis to merge and remove
+        #[clap(forbid_empty_values = true)]
+        from: String,
+        /// Name of the hypothesis to merge into
+        #[clap(long, forbid_empty_values = true, value_name = "HYPOTHESIS")]
+        into: String,
+    },
+    /// Saves, shows, removes and lists named evidence, applied to hypotheses with `--with-evidence`
+    Evidence {
+        /// What to do with the evidence
+        #[clap(subcommand)]
+        command: EvidenceCommand,
+    },
+    /// Saves, updates with measurements, shows, removes and lists beliefs about continuous quantities, such as latencies and costs
+    Quantity {
+        /// What to do with the quantity
+        #[clap(subcommand)]
+        command: QuantityCommand,
+    },
+    /// Saves, updates with counts, shows, removes and lists beliefs about how often each of several categories occurs, such as the root causes of incidents
+    Categories {
+        /// What to do with the categories
+        #[clap(subcommand)]
+        command: CategoriesCommand,
+    },
+    /// Prints a completion script for the shell, which also completes the names of saved hypotheses.
+    /// For example, `ask-bayes completions bash > /etc/bash_completion.d/ask-bayes`
+    Completions {
+        /// The shell to complete for.  Hypothesis names are completed in `bash`, `zsh` and `fish`
+        #[clap(arg_enum)]
+        shell: Shell,
+    },
+    /// Prints the names of the saved hypotheses, one per line
+    Names {
+        /// Prints only the hypotheses in this namespace and the namespaces nested in it, e.g. `infra/db`
+        namespace: Option<String>,
+    },
+    /// Lists the saved hypotheses with their prior probabilities
+    List {
+        /// Lists only the hypotheses in this namespace and the namespaces nested in it, e.g. `infra/db`
+        namespace: Option<String>,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Opens a full-screen dashboard of the saved hypotheses, showing their probabilities and histories, for applying evidence and setting priors with the keyboard
+    Dashboard,
+    /// Prints the saved hypotheses as JSON, with their prior probabilities, actions, tags, saved evidence and history
+    Export {
+        /// Exports only the hypotheses in this namespace and the namespaces nested in it, e.g. `infra/db`
+        namespace: Option<String>,
+    },
+    /// Moves every hypothesis in a namespace, and in the namespaces nested in it, into another namespace, e.g. `ask-bayes move infra/db storage/db`
+    Move {
+        /// The namespace to move
+        #[clap(forbid_empty_values = true)]
+        from: String,
+        /// The namespace to move it into
+        #[clap(forbid_empty_values = true)]
+        to: String,
+    },
+    /// Inspects the configuration
+    Config {
+        /// What to do with the configuration
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Applies a batch of observations to saved hypotheses as a single transaction: either every posterior is saved, or nothing is
+    Batch {
+        /// A JSON file holding an array of observations, each with `name`, `likelihood`, `likelihood_null` and optionally `evidence`.  Reads from standard input if the file is `-`
+        #[clap(value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Copies every hypothesis, with its history, actions and tags, from one store into another, e.g. from the sled database into SQLite
+    Migrate {
+        /// The store to copy from: `sled`, `sqlite` or `dir`, optionally followed by `:PATH`
+        #[clap(long, default_value = "sled", value_name = "STORE")]
+        from: StoreLocation,
+        /// The store to copy into: `sled`, `sqlite` or `dir`, optionally followed by `:PATH`
+        #[clap(long, value_name = "STORE")]
+        to: StoreLocation,
+    },
+}
+
+/// Commands for managing named evidence
+#[derive(Subcommand, Debug)]
+#[non_exhaustive]
+#[allow(clippy::large_enum_variant)]
+pub enum EvidenceCommand {
+    /// Saves the likelihoods of the evidence, replacing any saved before
+    Set {
+        /// Name of the evidence
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The likelihood of the evidence P(E|H)
+        #[clap(short, long)]
+        likelihood: Probability,
+        /// The likelihood of the evidence P(E|¬H)
+        #[clap(long)]
+        likelihood_null: Probability,
+        /// Saves the likelihoods for this hypothesis only, rather than for any hypothesis
+        #[clap(long = "for", value_name = "HYPOTHESIS")]
+        hypothesis: Option<String>,
+        /// What the evidence is
+        #[clap(short, long)]
+        description: Option<String>,
+    },
+    /// Shows the likelihoods of the evidence
+    Get {
+        /// Name of the evidence
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// Shows the likelihoods that apply to this hypothesis
+        #[clap(long = "for", value_name = "HYPOTHESIS")]
+        hypothesis: Option<String>,
+    },
+    /// Removes the evidence
+    Remove {
+        /// Name of the evidence
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// Removes the likelihoods saved for this hypothesis, rather than the generic ones
+        #[clap(long = "for", value_name = "HYPOTHESIS")]
+        hypothesis: Option<String>,
+    },
+    /// Lists the saved evidence
+    List {
+        /// Lists only the evidence that applies to this hypothesis
+        #[clap(long = "for", value_name = "HYPOTHESIS")]
+        hypothesis: Option<String>,
+    },
+}
+
+/// Commands for beliefs about continuous quantities
+#[derive(Subcommand, Debug)]
+#[non_exhaustive]
+pub enum QuantityCommand {
+    /// Sets the prior belief about the quantity, replacing any saved before and recording the change in its history
+    Set {
+        /// Name of the quantity
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The model of the measurements: `known-variance` if their standard deviation is known, or `normal-inverse-gamma` (`nig`) to learn it from them
+        #[clap(
+            long,
+            default_value = "known-variance",
+            possible_values = ["known-variance", "known", "normal", "normal-inverse-gamma", "nig"]
+        )]
+        model: GaussianModel,
+        /// The prior estimate of the quantity
+        #[clap(short, long, allow_hyphen_values = true)]
+        mean: f64,
+        /// How uncertain the prior estimate is, as a standard deviation
+        #[clap(short, long)]
+        sd: f64,
+        /// The standard deviation of the measurements: known exactly with `known-variance`, or a prior guess with `normal-inverse-gamma`
+        #[clap(long)]
+        noise_sd: f64,
+        /// How many measurements the guess of the standard deviation of the measurements is worth, with `normal-inverse-gamma`
+        #[clap(long, default_value = "1")]
+        noise_weight: f64,
+        /// The unit the quantity is measured in, e.g. `ms`.  Defaults to the saved unit
+        #[clap(short, long)]
+        unit: Option<String>,
+    },
+    /// Updates the belief about the quantity with measurements and reports the posterior
+    #[clap(allow_negative_numbers = true)]
+    Update {
+        /// Name of the quantity
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The measurements
+        #[clap(required_unless_present = "csv")]
+        measurements: Vec<f64>,
+        /// Also reads measurements from a CSV file, or from standard input if the file is `-`.  A header row is skipped
+        #[clap(long, value_name = "FILE")]
+        csv: Option<PathBuf>,
+        /// The column of the CSV file holding the measurements, named in its header row.  Defaults to the first column
+        #[clap(long, requires = "csv")]
+        column: Option<String>,
+        /// The probability held by the credible and predictive intervals
+        #[clap(short, long, default_value = "0.95")]
+        level: Probability,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Shows the belief about the quantity: its mean, standard deviation and credible interval, and the interval expected to hold the next measurement
+    Show {
+        /// Name of the quantity
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The probability held by the credible and predictive intervals
+        #[clap(short, long, default_value = "0.95")]
+        level: Probability,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Removes the quantity and its history
+    Remove {
+        /// Name of the quantity
+        #[clap(forbid_empty_values = true)]
+        name: String,
+    },
+    /// Lists the saved quantities
+    List,
+}
+
+/// Commands for beliefs about category frequencies
+#[derive(Subcommand, Debug)]
+#[non_exhaustive]
+pub enum CategoriesCommand {
+    /// Sets the prior belief about the categories, replacing any saved before along with its counts and history
+    Set {
+        /// Name of the belief
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The categories, each as `name` or as `name=weight` to give it its own prior weight
+        #[clap(required = true, min_values = 2, value_name = "CATEGORY[=WEIGHT]")]
+        categories: Vec<CategoryPrior>,
+        /// How many observations the prior belief in each category is worth, unless it is given its own weight
+        #[clap(short, long, default_value = "1")]
+        weight: f64,
+    },
+    /// Updates the belief with the number of times each category was observed and reports the posterior
+    Update {
+        /// Name of the belief
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The counts, each as `name=count`, or as `name` for a single observation
+        #[clap(required = true, value_name = "CATEGORY[=COUNT]")]
+        counts: Vec<CategoryCount>,
+        /// The probability held by the credible intervals
+        #[clap(short, long, default_value = "0.95")]
+        level: Probability,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Shows the expected proportion of each category and its credible interval, from the most to the least common
+    Show {
+        /// Name of the belief
+        #[clap(forbid_empty_values = true)]
+        name: String,
+        /// The probability held by the credible intervals
+        #[clap(short, long, default_value = "0.95")]
+        level: Probability,
+        /// The type of output to display
+        #[clap(
+            short,
+            long,
+            possible_values = ["t", "table", "Table", "T", "j", "json", "Json", "J", "s", "simple", "Simple", "S"]
+        )]
+        output: Option<OutputFormat>,
+    },
+    /// Removes the belief and its history
+    Remove {
+        /// Name of the belief
+        #[clap(forbid_empty_values = true)]
+        name: String,
+    },
+    /// Lists the saved beliefs about categories
+    List,
+}
+
+/// Commands for inspecting the configuration
+#[derive(Subcommand, Debug)]
+#[non_exhaustive]
+pub enum ConfigCommand {
+    /// Prints the effective configuration: the configuration file merged with the environment and the built-in defaults
+    Show,
 }
 
 /// The posterior probability of the hypothesis P(H|E) if the evidence is observed, or P(H|¬E) if the evidence is not observed
@@ -263,16 +1029,126 @@ pub fn calculate_posterior_probability(
     evidence: &Evidence,
     name: &str,
 ) -> Result<f64> {
-    validate_likelihoods_and_prior(prior, likelihood, likelihood_null, evidence, name)?;
-    let p_e = marginal_likelihood(prior, likelihood, likelihood_null);
+    calculate_chained_posterior_probability(
+        prior,
+        &[Observation::new(
+            likelihood,
+            likelihood_null,
+            evidence.clone(),
+        )],
+        name,
+    )
+}
+
+/// The posterior probability of the hypothesis after applying each observation in turn.
+///
+/// The calculation is performed in log-odds space so that extreme priors and long chains of updates do not underflow or round to 0 or 1.
+/// # Errors
+/// - If the P(E) is 0 for any of the observations
+#[inline]
+pub fn calculate_chained_posterior_probability(
+    prior: f64,
+    observations: &[Observation],
+    name: &str,
+) -> Result<f64> {
+    Ok(
+        calculate_posterior_probabilities(prior, observations, name)?
+            .last()
+            .copied()
+            .unwrap_or(prior),
+    )
+}
+
+/// The posterior probability of the hypothesis after each of the observations, carrying the log-odds from one step to the next rather than the rounded probability
+/// # Errors
+/// - If the P(E) is 0 for any of the observations
+pub(crate) fn calculate_posterior_probabilities(
+    prior: f64,
+    observations: &[Observation],
+    name: &str,
+) -> Result<Vec<f64>> {
+    let mut posterior_log_odds = log_odds(prior);
+    let mut posterior_probabilities = Vec::with_capacity(observations.len());
+    for observation in observations {
+        // ln(O(H|E)) = ln(O(H)) + ln(P(E|H) / P(E|¬H))
+        let updated_log_odds = posterior_log_odds
+            + log_likelihood_ratio(
+                observation.likelihood,
+                observation.likelihood_null,
+                &observation.evidence,
+            );
+        if updated_log_odds.is_nan() {
+            validate_likelihoods_and_prior(
+                probability_from_log_odds(posterior_log_odds),
+                observation.likelihood,
+                observation.likelihood_null,
+                &observation.evidence,
+                name,
+            )?;
+            return Err(anyhow!(
+                "The posterior probability of {name} is undefined for the given likelihoods"
+            ));
+        }
+        posterior_log_odds = updated_log_odds;
+        posterior_probabilities.push(probability_from_log_odds(posterior_log_odds));
+    }
+    Ok(posterior_probabilities)
+}
+
+/// The history entries recording each observation in turn, with the posterior probability of the hypothesis after it
+/// # Errors
+/// - If the P(E) is 0 for any of the observations
+fn update_entries(
+    prior: f64,
+    observations: &[Observation],
+    name: &str,
+) -> Result<Vec<HistoryEntry>> {
+    let posterior_probabilities = calculate_posterior_probabilities(prior, observations, name)?;
+    let mut step_prior = prior;
+    Ok(observations
+        .iter()
+        .zip(posterior_probabilities)
+        .map(|(observation, posterior_probability)| {
+            let entry = HistoryEntry::new(
+                posterior_probability,
+                Change::Update {
+                    prior: step_prior,
+                    observation: observation.clone(),
+                },
+            );
+            step_prior = posterior_probability;
+            entry
+        })
+        .collect())
+}
+
+/// The posterior probability of the hypothesis calculated using exact rational arithmetic
+/// # Errors
+/// - If the P(E) is 0
+#[inline]
+pub fn calculate_exact_posterior_probability(
+    prior: &BigRational,
+    likelihood: &BigRational,
+    likelihood_null: &BigRational,
+    evidence: &Evidence,
+    name: &str,
+) -> Result<BigRational> {
+    let p_e = exact_marginal_likelihood(prior, likelihood, likelihood_null);
     match *evidence {
         Evidence::Observed => {
+            if p_e.is_zero() {
+                return Err(anyhow!("The total probability of observing evidence P(E) must be greater than 0 if evidence is observed.  \r\nP(E) = P({name})[{prior}] * P(E|{name})[{likelihood}] + P(\u{ac}{name})[{}] * P(E|\u{ac}{name})[{likelihood_null}] = 0", exact_negate(prior)));
+            }
             // P(H|E) = P(H) * P(E|H) / P(E)
             Ok(likelihood * prior / p_e)
         }
         Evidence::NotObserved => {
+            let p_not_e = exact_negate(&p_e);
+            if p_not_e.is_zero() {
+                return Err(anyhow!("The total probability of not observing evidence P(\u{ac}E) must be greater than 0 if evidence is not observed.  \r\nP(\u{ac}E) = P(\u{ac}E|{name})[{}] * P({name})[{prior}] + P(\u{ac}{name})[{}] * P(\u{ac}E|\u{ac}{name})[{}] = 0", exact_negate(likelihood), exact_negate(prior), exact_negate(likelihood_null)));
+            }
             // P(H|¬E) = P(H) * P(¬E|H) / P(¬E)
-            Ok(negate(likelihood) * prior / negate(p_e))
+            Ok(exact_negate(likelihood) * prior / p_not_e)
         }
     }
 }
@@ -285,53 +1161,358 @@ pub fn calculate_posterior_probability(
 #[inline]
 #[cfg(not(tarpaulin_include))]
 pub fn get_prior(name: &str) -> Result<f64> {
-    let db = open_db()?;
-    let prior = db.get(&name)?;
-    match prior {
-        Some(prior_serialized) => {
-            let bytes = prior_serialized.as_ref();
-            Ok(f64::from_be_bytes(bytes.try_into()?))
-        }
+    let store = open_store()?;
+    match store.get_prior(name)? {
+        Some(prior) => Ok(prior),
         None => return Err(anyhow!("Could not find hypothesis {name}")),
     }
 }
 
-/// Sets the prior probability of the hypothesis P(H) to the new value, saving it to the database.
+/// Sets the prior probability of the hypothesis P(H) to the new value, saving it to the database and recording the change in its history.
 /// # Errors
 /// - If the database cannot be opened
 /// - If the prior cannot be inserted into the database
+/// - If the change cannot be recorded in the history of the hypothesis
 #[inline]
 #[cfg(not(tarpaulin_include))]
 pub fn set_prior(name: &str, prior: f64) -> Result<()> {
-    let db = open_db()?;
-    db.insert(name, &prior.to_be_bytes())?;
-    Ok(())
+    let store = open_store()?;
+    record_change(&*store, name, &HistoryEntry::new(prior, Change::Set))
 }
 
-/// Removes the prior probability of the hypothesis P(H) from the database
+/// Updates the prior probability of the hypothesis P(H) to the posterior probability after the observation, saving it to the database and recording the update in its history.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the posterior cannot be inserted into the database
+/// - If the update cannot be recorded in the history of the hypothesis
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn update_prior(
+    name: &str,
+    prior: f64,
+    observation: Observation,
+    posterior_probability: f64,
+) -> Result<()> {
+    let store = open_store()?;
+    record_change(
+        &*store,
+        name,
+        &HistoryEntry::new(posterior_probability, Change::Update { prior, observation }),
+    )
+}
+
+/// Updates the prior probability of the hypothesis P(H) to the posterior probability after a series of observations, each applied to the posterior of the one before, saving it to the database.
+///
+/// Each observation is recorded as a step of the history of the hypothesis, the note, if given, being attached to the last.  Returns the posterior probability.
+/// # Errors
+/// - If the database cannot be opened
+/// - If there are no observations
+/// - If any of the posterior probabilities is undefined
+/// - If the posterior cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn update_prior_with_observations(
+    name: &str,
+    prior: f64,
+    observations: &[Observation],
+    note: Option<&str>,
+) -> Result<f64> {
+    let store = open_store()?;
+    record_observations(&*store, name, prior, observations, note)
+}
+
+/// Records a series of observations of the hypothesis in the store, with the store locked so that changes made by other processes are not lost
+/// # Errors
+/// - If there are no observations
+/// - If any of the posterior probabilities is undefined
+/// - If the store cannot be locked, read or written
+pub(crate) fn record_observations(
+    store: &dyn Store,
+    name: &str,
+    prior: f64,
+    observations: &[Observation],
+    note: Option<&str>,
+) -> Result<f64> {
+    if observations.is_empty() {
+        return Err(anyhow!("No evidence was given for {name}"));
+    }
+    let mut entries = update_entries(prior, observations, name)?;
+    let posterior_probability = entries.last().map_or(prior, |entry| entry.probability);
+    if let (Some(note), Some(last)) = (note, entries.last_mut()) {
+        last.note = Some(note.to_owned());
+    }
+    store.transact(&mut |store| append_to_history(store, name, &entries))?;
+    Ok(posterior_probability)
+}
+
+/// The prior and posterior probability of a saved hypothesis after a batch of observations has been applied to it
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct BatchUpdate {
+    /// The name of the hypothesis
+    pub name: String,
+    /// The prior probability of the hypothesis before the batch
+    pub prior: f64,
+    /// The posterior probability of the hypothesis after every observation of it in the batch
+    pub posterior_probability: f64,
+}
+
+/// An observation of a saved hypothesis in a batch file
+#[derive(Debug, Deserialize)]
+struct BatchEntry {
+    /// The name of the hypothesis
+    name: String,
+    /// The likelihood of the evidence P(E|H)
+    likelihood: f64,
+    /// The likelihood of the evidence P(E|¬H)
+    likelihood_null: f64,
+    /// Whether or not the evidence was observed
+    #[serde(default = "BatchEntry::default_evidence")]
+    evidence: String,
+}
+
+impl BatchEntry {
+    /// Evidence is observed unless the entry says otherwise
+    fn default_evidence() -> String {
+        "observed".to_owned()
+    }
+}
+
+/// Parses a batch of observations of saved hypotheses from a JSON array of objects with `name`, `likelihood`, `likelihood_null` and optionally `evidence` (`observed` by default)
+/// # Errors
+/// - If the batch is not a JSON array of observations
+/// - If a likelihood is not between 0 and 1
+/// - If the evidence of an observation is invalid
+#[inline]
+pub fn parse_batch(batch: &str) -> Result<Vec<(String, Observation)>> {
+    let entries: Vec<BatchEntry> =
+        serde_json::from_str(batch).map_err(|error| anyhow!("Invalid batch: {error}"))?;
+    entries
+        .into_iter()
+        .map(|entry| {
+            for (label, likelihood) in [
+                ("P(E|H)", entry.likelihood),
+                ("P(E|\u{ac}H)", entry.likelihood_null),
+            ] {
+                if !(0.0_f64..=1.0_f64).contains(&likelihood) {
+                    return Err(anyhow!(
+                        "Invalid batch: {label} for {} must be between 0 and 1, got {likelihood}",
+                        entry.name
+                    ));
+                }
+            }
+            let evidence = entry.evidence.parse()?;
+            Ok((
+                entry.name,
+                Observation::new(entry.likelihood, entry.likelihood_null, evidence),
+            ))
+        })
+        .collect()
+}
+
+/// Applies the observations to the saved hypotheses as a single transaction: either every posterior is saved, or nothing is.
+///
+/// Observations of the same hypothesis are applied in order, each to the posterior of the one before.
+/// Returns the prior and posterior of each hypothesis, in the order they first appear.
+/// # Errors
+/// - If the database cannot be opened
+/// - If any of the hypotheses has not been saved
+/// - If any of the posterior probabilities is undefined
+/// - If the posteriors cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn update_priors(updates: &[(String, Observation)]) -> Result<Vec<BatchUpdate>> {
+    let store = open_store()?;
+    apply_observations(&*store, updates)
+}
+
+/// Applies the observations to the hypotheses in the store as a single transaction, with the store locked from reading the hypotheses to saving them
+/// # Errors
+/// - If any of the hypotheses is not in the store
+/// - If any of the posterior probabilities is undefined
+/// - If the store cannot be written
+fn apply_observations(
+    store: &dyn Store,
+    updates: &[(String, Observation)],
+) -> Result<Vec<BatchUpdate>> {
+    let mut applied = Vec::new();
+    store.transact(&mut |store| {
+        let mut results: Vec<BatchUpdate> = Vec::new();
+        let mut histories = Vec::new();
+        for (name, _) in updates {
+            if !results.iter().any(|result| result.name == *name) {
+                let prior = store
+                    .get_prior(name)?
+                    .ok_or_else(|| anyhow!("Could not find hypothesis {name}"))?;
+                results.push(BatchUpdate {
+                    name: name.clone(),
+                    prior,
+                    posterior_probability: prior,
+                });
+                histories.push(store.get_history(name)?.unwrap_or_default());
+            }
+        }
+        for (result, history) in results.iter_mut().zip(histories.iter_mut()) {
+            let observations: Vec<Observation> = updates
+                .iter()
+                .filter(|update| update.0 == result.name)
+                .map(|update| update.1.clone())
+                .collect();
+            let entries = update_entries(result.prior, &observations, &result.name)?;
+            if let Some(last) = entries.last() {
+                result.posterior_probability = last.probability;
+            }
+            history.extend(entries);
+        }
+        let mut operations = Vec::new();
+        for (result, history) in results.iter().zip(histories) {
+            operations.push(Operation::SetPrior {
+                name: result.name.clone(),
+                prior: result.posterior_probability,
+            });
+            operations.push(Operation::SetHistory {
+                name: result.name.clone(),
+                history,
+            });
+        }
+        applied = results;
+        Ok(operations)
+    })?;
+    Ok(applied)
+}
+
+/// The kinds of metadata saved for each hypothesis, which are removed, renamed and merged along with it
+pub(crate) const HYPOTHESIS_METADATA: [&str; 3] = [
+    ACTIONS_METADATA,
+    TAGS_METADATA,
+    HYPOTHESIS_EVIDENCE_METADATA,
+];
+
+/// Saves the probability of a history entry as the prior of the hypothesis and appends the entry to its history, with the store locked so that changes made by other processes are not lost
+/// # Errors
+/// - If the history cannot be read
+/// - If the store cannot be written
+pub(crate) fn record_change(store: &dyn Store, name: &str, entry: &HistoryEntry) -> Result<()> {
+    store.transact(&mut |store| append_to_history(store, name, std::slice::from_ref(entry)))
+}
+
+/// The operations that append the entries to the history of the hypothesis and save the probability of the last of them as its prior.
+///
+/// Meant to be returned from [`Store::transact`], so that the history is read under the same lock it is written with.
+/// # Errors
+/// - If the history cannot be read
+pub(crate) fn append_to_history(
+    store: &dyn Store,
+    name: &str,
+    entries: &[HistoryEntry],
+) -> Result<Vec<Operation>> {
+    let Some(last) = entries.last() else {
+        return Ok(Vec::new());
+    };
+    let mut history = store.get_history(name)?.unwrap_or_default();
+    history.extend(entries.iter().cloned());
+    Ok(vec![
+        Operation::SetPrior {
+            name: name.to_owned(),
+            prior: last.probability,
+        },
+        Operation::SetHistory {
+            name: name.to_owned(),
+            history,
+        },
+    ])
+}
+
+/// Removes the prior probability of the hypothesis P(H) from the database, along with its history, tags and any actions attached to it
 /// # Errors
 /// - If the database cannot be opened
 /// - If the prior cannot be removed from the database
 #[inline]
 #[cfg(not(tarpaulin_include))]
 pub fn remove_prior(name: &str) -> Result<()> {
-    let db = open_db()?;
-    db.remove(name)?;
-    Ok(())
+    open_store()?.apply(&removal(name))
 }
 
-/// Opens the hypotheses database
+/// Lists the saved hypotheses with their prior probabilities, in alphabetical order
 /// # Errors
 /// - If the database cannot be opened
+/// - If a saved name is not valid UTF-8
+/// - If a saved prior value is not a valid float
 #[inline]
 #[cfg(not(tarpaulin_include))]
-fn open_db() -> Result<Db> {
-    let hd = match home_dir() {
-        Some(hd) => hd,
-        None => return Err(anyhow!("Could not find home directory")),
+pub fn list_hypotheses() -> Result<Vec<(String, f64)>> {
+    open_store()?.list_priors()
+}
+
+/// Parses a probability written as a decimal (`0.75`), a percentage (`75%`), a fraction (`3/4`), odds in favour (`3:1`), or a natural frequency (`1 in 1000`).
+/// # Errors
+/// - If the value is not written in one of the supported notations
+/// - If the value is not between 0 and 1
+#[inline]
+pub fn parse_probability(value: &str) -> Result<Probability> {
+    let input = value.trim();
+    let lowercase = input.to_lowercase();
+    let (notation, exact) = if let Some(percentage) = input.strip_suffix('%') {
+        let hundred = BigRational::from_integer(BigInt::from(100_u8));
+        (
+            ProbabilityNotation::Percentage,
+            parse_decimal(percentage.trim())? / hundred,
+        )
+    } else if let Some((in_favour, against)) = input.split_once(':') {
+        let in_favour = parse_part(in_favour, input)?;
+        let total = parse_part(against, input)? + &in_favour;
+        if total.is_zero() {
+            return Err(anyhow!("Invalid odds: {}", input));
+        }
+        (ProbabilityNotation::Odds, in_favour / total)
+    } else if let Some((count, population)) = lowercase.split_once(" in ") {
+        let population = parse_part(&population.replace(',', ""), input)?;
+        if population.is_zero() {
+            return Err(anyhow!("Invalid frequency: {}", input));
+        }
+        (
+            ProbabilityNotation::Frequency,
+            parse_part(&count.replace(',', ""), input)? / population,
+        )
+    } else if let Some((numerator, denominator)) = input.split_once('/') {
+        let denominator = parse_part(denominator, input)?;
+        if denominator.is_zero() {
+            return Err(anyhow!("Invalid fraction: {}", input));
+        }
+        (
+            ProbabilityNotation::Fraction,
+            parse_part(numerator, input)? / denominator,
+        )
+    } else {
+        (ProbabilityNotation::Decimal, parse_rational(input)?)
     };
-    let db_path = hd.join(".ask-bayes").join("hypotheses.db");
-    Ok(sled::open(db_path)?)
+    validate_exact_probability(&exact)?;
+    // Decimal input is parsed directly so that its value is identical to the one the user typed
+    let value = if notation == ProbabilityNotation::Decimal {
+        parse_validate_probability(input)?
+    } else {
+        exact
+            .to_f64()
+            .ok_or_else(|| anyhow!("Invalid probability: {}", input))?
+    };
+    Ok(Probability {
+        value,
+        exact,
+        notation,
+        input: input.to_owned(),
+    })
+}
+
+/// Parses one part of odds, a natural frequency or a fraction, which cannot be negative, so that the signs of two negative parts cannot cancel out
+fn parse_part(part: &str, input: &str) -> Result<BigRational> {
+    let part = parse_decimal(part.trim())?;
+    if part.is_negative() {
+        return Err(anyhow!(
+            "Invalid probability: {}.  Its parts cannot be negative",
+            input
+        ));
+    }
+    Ok(part)
 }
 
 /// Parses and validates a probability
@@ -341,21 +1522,117 @@ fn parse_validate_probability(value: &str) -> Result<f64> {
     Ok(float)
 }
 
+/// Parses a decimal (e.g. `0.75`, `1e-12`) or a fraction (e.g. `1/3`) into an exact rational
+pub(crate) fn parse_rational(value: &str) -> Result<BigRational> {
+    match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator = parse_decimal(denominator.trim())?;
+            if denominator.is_zero() {
+                return Err(anyhow!("Invalid fraction: {}", value));
+            }
+            Ok(parse_decimal(numerator.trim())? / denominator)
+        }
+        None => parse_decimal(value),
+    }
+}
+
+/// The largest power of ten in scientific notation parsed exactly.  Beyond it, numbers are far outside the range of an `f64`, so they are parsed as one rather than spending unbounded time and memory on the exact value.
+const MAX_EXACT_EXPONENT: i32 = 1_000;
+
+/// Parses a decimal number, optionally in scientific notation, into an exact rational
+fn parse_decimal(value: &str) -> Result<BigRational> {
+    let (signed_mantissa, exponent) = value
+        .split_once(['e', 'E'])
+        .map_or((value, None), |(mantissa, exponent)| {
+            (mantissa, Some(exponent))
+        });
+    let negative = signed_mantissa.starts_with('-');
+    let mantissa = signed_mantissa.trim_start_matches(['-', '+']);
+    if mantissa.len() + 1 < signed_mantissa.len() {
+        return Err(anyhow!("Invalid number: {}", value));
+    }
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty()
+        || !integer
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(anyhow!("Invalid number: {}", value));
+    }
+    let exponent = match exponent {
+        Some(exponent) => match exponent.parse::<i32>() {
+            Ok(exponent) if (-MAX_EXACT_EXPONENT..=MAX_EXACT_EXPONENT).contains(&exponent) => {
+                exponent
+            }
+            _ => {
+                return BigRational::from_float(value.parse::<f64>()?)
+                    .ok_or_else(|| anyhow!("Invalid number: {}", value))
+            }
+        },
+        None => 0_i32,
+    };
+    let digits = BigInt::from_str(&format!("{integer}{fraction}"))?;
+    let scale = exponent
+        .checked_sub(i32::try_from(fraction.len())?)
+        .ok_or_else(|| anyhow!("Invalid number: {}", value))?;
+    let ten = BigRational::from_integer(BigInt::from(10_u8));
+    let magnitude = BigRational::from_integer(digits) * Pow::pow(ten, scale);
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
 /// Validates a probability.  Probabilities should be valid floats between 0 and 1.
-fn validate_probability(value: f64) -> Result<()> {
+pub(crate) fn validate_probability(value: f64) -> Result<()> {
     if !(0.0_f64..=1.0_f64).contains(&value) {
         return Err(anyhow!("Probability must be between 0 and 1"));
     }
     Ok(())
 }
 
+/// Validates an exact probability.  Probabilities should be between 0 and 1.
+fn validate_exact_probability(value: &BigRational) -> Result<()> {
+    if value.is_negative() || *value > BigRational::one() {
+        return Err(anyhow!("Probability must be between 0 and 1"));
+    }
+    Ok(())
+}
+
 /// Negates a probability.  Ex. P(H) -> P(¬H)
-fn negate(value: f64) -> f64 {
+pub(crate) fn negate(value: f64) -> f64 {
     1.0_f64 - value
 }
 
+/// Negates an exact probability.  Ex. P(H) -> P(¬H)
+fn exact_negate(value: &BigRational) -> BigRational {
+    BigRational::one() - value
+}
+
+/// Converts a probability to log odds.  Ex. P(H) -> ln(P(H) / P(¬H))
+pub(crate) fn log_odds(value: f64) -> f64 {
+    value.ln() - (-value).ln_1p()
+}
+
+/// Converts log odds back to a probability.  Ex. ln(P(H) / P(¬H)) -> P(H)
+pub(crate) fn probability_from_log_odds(value: f64) -> f64 {
+    // Evaluated so that the exponential never overflows
+    if value >= 0.0_f64 {
+        1.0_f64 / (1.0_f64 + (-value).exp())
+    } else {
+        let odds = value.exp();
+        odds / (1.0_f64 + odds)
+    }
+}
+
+/// The log of the likelihood ratio for the evidence.  Ex. ln(P(E|H) / P(E|¬H)) or ln(P(¬E|H) / P(¬E|¬H))
+fn log_likelihood_ratio(likelihood: f64, likelihood_null: f64, evidence: &Evidence) -> f64 {
+    match *evidence {
+        Evidence::Observed => likelihood.ln() - likelihood_null.ln(),
+        Evidence::NotObserved => (-likelihood).ln_1p() - (-likelihood_null).ln_1p(),
+    }
+}
+
 /// Checks that P(E) is not 0
-fn validate_likelihoods_and_prior(
+pub(crate) fn validate_likelihoods_and_prior(
     prior: f64,
     likelihood: f64,
     likelihood_null: f64,
@@ -379,56 +1656,77 @@ fn validate_likelihoods_and_prior(
 }
 
 ///  P(H) * P(E|H) + P(¬H) * P(E|¬H), otherwise known as P(E)
-fn marginal_likelihood(prior: f64, likelihood: f64, likelihood_null: f64) -> f64 {
+pub(crate) fn marginal_likelihood(prior: f64, likelihood: f64, likelihood_null: f64) -> f64 {
     likelihood.mul_add(prior, likelihood_null * negate(prior))
 }
 
-/// Runs the wizard to guide the update of the prior probability of the hypothesis
+///  P(H) * P(E|H) + P(¬H) * P(E|¬H), otherwise known as P(E), calculated using exact rational arithmetic
+fn exact_marginal_likelihood(
+    prior: &BigRational,
+    likelihood: &BigRational,
+    likelihood_null: &BigRational,
+) -> BigRational {
+    likelihood * prior + likelihood_null * exact_negate(prior)
+}
+
+/// Asks which hypothesis to update: one of the saved hypotheses, found by typing part of its name, or a new one
 /// # Errors
+/// - If the database cannot be opened
 /// - If the prompt cannot be displayed
-#[inline]
 #[cfg(not(tarpaulin_include))]
-pub fn wizard() -> Result<()> {
+fn choose_hypothesis() -> Result<String> {
+    let saved = list_hypotheses()?;
+    if !saved.is_empty() {
+        let mut choices = vec!["Create a new hypothesis".to_owned()];
+        choices.extend(
+            saved
+                .iter()
+                .map(|&(ref name, prior)| format!("{name} (P = {})", Rounded(prior))),
+        );
+        let choice = FuzzySelect::new()
+            .with_prompt("Choose a hypothesis to update, typing to search")
+            .items(&choices)
+            .default(0)
+            .interact()?;
+        if let Some((name, _)) = choice.checked_sub(1).and_then(|index| saved.get(index)) {
+            return Ok(name.clone());
+        }
+    }
     let name = Input::<String>::new()
         .with_prompt("Enter the name of the hypothesis")
         .allow_empty(false)
         .interact_text()?;
+    names::check_name(&name)
+}
 
-    let prior = Input::<f64>::new()
-        .with_prompt(format!(
-            "Enter the prior probability of the hypothesis P({name})"
-        ))
-        .allow_empty(false)
-        .default(0.5_f64)
-        .validate_with(|v: &f64| validate_probability(*v))
-        .interact_text()?;
+/// Runs the wizard to guide the update of the prior probability of the hypothesis.
+///
+/// The hypothesis can be chosen from the saved hypotheses, in which case its saved prior is offered as the prior.  Several pieces of evidence can be applied in turn, each updating the running posterior, and a note can be attached to the update before it is saved.
+/// # Errors
+/// - If the prompt cannot be displayed
+/// - If the database cannot be opened or read
+/// - If the posterior probability is undefined
+/// - If the update cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn wizard() -> Result<()> {
+    let name = choose_hypothesis()?;
 
-    let likelihood = Input::<f64>::new()
-        .with_prompt(format!(
-            "Enter the likelihood of observing evidence given {name} is true P(E|{name})"
-        ))
-        .allow_empty(false)
-        .default(0.5_f64)
-        .validate_with(|v: &f64| validate_probability(*v))
-        .interact_text()?;
+    let default_probability = Probability::from_value(0.5_f64)?;
+    let default_prior = match open_store()?.get_prior(&name)? {
+        Some(saved) => Probability::from_value(saved)?,
+        None => default_probability.clone(),
+    };
 
-    let likelihood_null = Input::<f64>::new()
+    let prior = Input::<Probability>::new()
         .with_prompt(format!(
-            "Enter the likelihood of observing evidence given {name} is false P(E|\u{ac}{name})"
+            "Enter the prior probability of the hypothesis P({name})"
         ))
         .allow_empty(false)
-        .default(0.5_f64)
-        .validate_with(|v: &f64| validate_probability(*v))
+        .default(default_prior)
         .interact_text()?;
-
-    let evidence = Input::<Evidence>::new()
-        .with_prompt("Is evidence observed or not observed?".to_owned())
-        .allow_empty(false)
-        .default(Evidence::Observed)
-        .interact_text()?;
-
-    let posterior_probability =
-        calculate_posterior_probability(prior, likelihood, likelihood_null, &evidence, &name)?;
+    report_probability_interpretation(&format!("P({name})"), &prior);
+    let prior = prior.value();
 
     let output_format = Input::<OutputFormat>::new()
         .with_prompt("How would you like the output?".to_owned())
@@ -436,15 +1734,81 @@ pub fn wizard() -> Result<()> {
         .default(OutputFormat::Table)
         .interact_text()?;
 
-    report_posterior_probability(
-        prior,
-        likelihood,
-        likelihood_null,
-        &evidence,
-        posterior_probability,
-        &name,
-        &output_format,
-    );
+    let mut observations = Vec::new();
+    let mut posterior_probability = prior;
+    loop {
+        let likelihoods = if Confirm::new()
+            .with_prompt("Would you like help estimating the likelihoods?")
+            .default(false)
+            .interact()?
+        {
+            elicit_likelihoods(&name)?
+        } else {
+            let likelihood = Input::<Probability>::new()
+                .with_prompt(format!(
+                    "Enter the likelihood of observing evidence given {name} is true P(E|{name})"
+                ))
+                .allow_empty(false)
+                .default(default_probability.clone())
+                .interact_text()?;
+            report_probability_interpretation(&format!("P(E|{name})"), &likelihood);
+
+            let likelihood_null = Input::<Probability>::new()
+                .with_prompt(format!(
+                    "Enter the likelihood of observing evidence given {name} is false P(E|\u{ac}{name})"
+                ))
+                .allow_empty(false)
+                .default(default_probability.clone())
+                .interact_text()?;
+            report_probability_interpretation(&format!("P(E|\u{ac}{name})"), &likelihood_null);
+            Likelihoods {
+                likelihood: likelihood.value(),
+                likelihood_null: likelihood_null.value(),
+            }
+        };
+
+        let evidence = Input::<Evidence>::new()
+            .with_prompt("Is evidence observed or not observed?".to_owned())
+            .allow_empty(false)
+            .default(Evidence::Observed)
+            .interact_text()?;
+
+        let observation = Observation::new(
+            likelihoods.likelihood,
+            likelihoods.likelihood_null,
+            evidence,
+        );
+        let running_prior = posterior_probability;
+        observations.push(observation.clone());
+        // Every step starts again from the prior so that the log-odds are kept across the chain
+        posterior_probability =
+            calculate_chained_posterior_probability(prior, &observations, &name)?;
+        report_posterior_probability(
+            running_prior,
+            observation.likelihood,
+            observation.likelihood_null,
+            &observation.evidence,
+            posterior_probability,
+            &name,
+            &output_format,
+        );
+        if observations.len() > 1 {
+            info!(
+                "P({name}) is {} after {} pieces of evidence, starting from {}",
+                Rounded(posterior_probability),
+                observations.len(),
+                Rounded(prior)
+            );
+        }
+
+        if !Confirm::new()
+            .with_prompt("Would you like to apply another piece of evidence?")
+            .default(false)
+            .interact()?
+        {
+            break;
+        }
+    }
 
     let update = Input::<UpdateHypothesis>::new()
         .with_prompt("Would you like to update the prior probability?".to_owned())
@@ -453,13 +1817,62 @@ pub fn wizard() -> Result<()> {
         .interact_text()?;
 
     if update == UpdateHypothesis::Update {
-        set_prior(&name, posterior_probability)?;
-        info!("P({name}) has been updated to {}", posterior_probability);
+        let note = Input::<String>::new()
+            .with_prompt("Add a note explaining the update, or leave it empty")
+            .allow_empty(true)
+            .interact_text()?;
+        let note = note.trim();
+        let posterior_probability = update_prior_with_observations(
+            &name,
+            prior,
+            &observations,
+            (!note.is_empty()).then_some(note),
+        )?;
+        info!(
+            "P({name}) has been updated to {}",
+            Rounded(posterior_probability)
+        );
     }
 
     Ok(())
 }
 
+/// Reports how a probability entered by the user was interpreted, if it was not written as a plain decimal.
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_probability_interpretation(label: &str, probability: &Probability) {
+    if let Some(interpretation) = probability.interpretation() {
+        info!("Interpreted {label} = {interpretation}");
+    }
+}
+
+/// The number of decimal places selected with [`use_precision`]
+static PRECISION: OnceLock<usize> = OnceLock::new();
+
+/// Shows numbers with the number of decimal places in all output other than JSON for the rest of the process
+/// # Errors
+/// - If a precision has already been selected
+#[inline]
+pub fn use_precision(precision: usize) -> Result<()> {
+    PRECISION.set(precision).map_err(|precision| {
+        anyhow!("Cannot show {precision} decimal places: a precision has already been selected")
+    })
+}
+
+/// A number shown with the precision selected with [`use_precision`], or in full if none was selected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rounded(pub f64);
+
+impl Display for Rounded {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match PRECISION.get() {
+            Some(&precision) => write!(f, "{:.precision$}", self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
 /// Reports the posterior probability of the hypothesis given the evidence.  Also reports the values of the `prior`, `likelihood`, and `likelihood_null`.
 #[inline]
 #[cfg(not(tarpaulin_include))]
@@ -475,6 +1888,17 @@ pub fn report_posterior_probability(
     match *output_format {
         OutputFormat::Table => {
             report_table(
+                name,
+                &Rounded(prior),
+                &Rounded(likelihood),
+                &Rounded(likelihood_null),
+                &Rounded(marginal_likelihood(prior, likelihood, likelihood_null)),
+                evidence,
+                &Rounded(posterior_probability),
+            );
+        }
+        OutputFormat::Json => {
+            report_json(
                 name,
                 prior,
                 likelihood,
@@ -483,8 +1907,17 @@ pub fn report_posterior_probability(
                 posterior_probability,
             );
         }
-        OutputFormat::Json => {
-            report_json(
+        OutputFormat::Simple => {
+            report_simple(
+                name,
+                &Rounded(prior),
+                &Rounded(likelihood),
+                &Rounded(likelihood_null),
+                &Rounded(posterior_probability),
+            );
+        }
+        OutputFormat::Icons => {
+            report_icon_array(
                 name,
                 prior,
                 likelihood,
@@ -493,31 +1926,121 @@ pub fn report_posterior_probability(
                 posterior_probability,
             );
         }
+    }
+}
+
+/// Reports the exact posterior probability of the hypothesis given the evidence as fractions.  Also reports the values of the `prior`, `likelihood`, and `likelihood_null`.
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_exact_posterior_probability(
+    prior: &BigRational,
+    likelihood: &BigRational,
+    likelihood_null: &BigRational,
+    evidence: &Evidence,
+    posterior_probability: &BigRational,
+    name: &str,
+    output_format: &OutputFormat,
+) {
+    match *output_format {
+        OutputFormat::Table => {
+            report_table(
+                name,
+                &ExactValue(prior),
+                &ExactValue(likelihood),
+                &ExactValue(likelihood_null),
+                &ExactValue(&exact_marginal_likelihood(
+                    prior,
+                    likelihood,
+                    likelihood_null,
+                )),
+                evidence,
+                &ExactValue(posterior_probability),
+            );
+        }
+        OutputFormat::Json => {
+            let json = json!({
+                "name": name,
+                "prior": prior.to_string(),
+                "likelihood": likelihood.to_string(),
+                "likelihood_null": likelihood_null.to_string(),
+                "evidence": match *evidence {
+                    Evidence::Observed => "observed",
+                    Evidence::NotObserved => "not observed",
+                },
+                "posterior_probability": posterior_probability.to_string(),
+                "posterior_probability_approximate": posterior_probability.to_f64(),
+            });
+
+            info!("{}", json.to_string());
+        }
         OutputFormat::Simple => {
-            let output = format!(
-                "
+            report_simple(
+                name,
+                &ExactValue(prior),
+                &ExactValue(likelihood),
+                &ExactValue(likelihood_null),
+                &ExactValue(posterior_probability),
+            );
+        }
+        OutputFormat::Icons => {
+            report_icon_array(
+                name,
+                prior.to_f64().unwrap_or(f64::NAN),
+                likelihood.to_f64().unwrap_or(f64::NAN),
+                likelihood_null.to_f64().unwrap_or(f64::NAN),
+                evidence,
+                posterior_probability.to_f64().unwrap_or(f64::NAN),
+            );
+        }
+    }
+}
+
+/// Displays an exact probability as a fraction followed by its decimal approximation
+struct ExactValue<'a>(&'a BigRational);
+
+impl Display for ExactValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_integer() {
+            return write!(f, "{}", self.0);
+        }
+        match self.0.to_f64() {
+            Some(approximation) => write!(f, "{} (\u{2248} {approximation})", self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Reports the posterior probability of the hypothesis given the evidence in a formatted string.
+#[cfg(not(tarpaulin_include))]
+fn report_simple<T: Display>(
+    name: &str,
+    prior: &T,
+    likelihood: &T,
+    likelihood_null: &T,
+    posterior_probability: &T,
+) {
+    let output = format!(
+        "
                 P({name}) = {prior}
                 P(E|{name}) = {likelihood}
                 P(E|\u{ac}{name}) = {likelihood_null}
                 P({name}|E) = {posterior_probability}
                 "
-            );
-            info!("{output}");
-        }
-    }
+    );
+    info!("{output}");
 }
 
 /// Reports the posterior probability of the hypothesis given the evidence in a table format.
 #[cfg(not(tarpaulin_include))]
-fn report_table(
+fn report_table<T: Display>(
     name: &str,
-    prior: f64,
-    likelihood: f64,
-    likelihood_null: f64,
+    prior: &T,
+    likelihood: &T,
+    likelihood_null: &T,
+    marginal_likelihood: &T,
     evidence: &Evidence,
-    posterior_probability: f64,
+    posterior_probability: &T,
 ) {
-    let marginal_likelihood = marginal_likelihood(prior, likelihood, likelihood_null);
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
     table.set_titles(Row::new(vec![
@@ -899,6 +2422,16 @@ mod tests {
             let result = OutputFormat::from_str(format)?;
             assert_eq!(result, OutputFormat::Simple);
         }
+        {
+            let format = "icons";
+            let result = OutputFormat::from_str(format)?;
+            assert_eq!(result, OutputFormat::Icons);
+        }
+        {
+            let format = "I";
+            let result = OutputFormat::from_str(format)?;
+            assert_eq!(result, OutputFormat::Icons);
+        }
 
         Ok(())
     }
@@ -927,5 +2460,389 @@ mod tests {
             let result = format.to_string();
             assert_eq!(result, "Simple");
         }
+        {
+            let format = OutputFormat::Icons;
+            let result = format.to_string();
+            assert_eq!(result, "Icons");
+        }
+    }
+
+    #[test]
+    fn it_parses_a_fraction_as_an_exact_probability() -> Result<()> {
+        let result = Probability::from_str("1/3")?;
+        assert_eq!(
+            *result.exact(),
+            BigRational::new(BigInt::from(1_u8), BigInt::from(3_u8))
+        );
+        assert!(epsilon_compare(result.value(), 1.0_f64 / 3.0_f64));
+        Ok(())
+    }
+
+    #[test]
+    fn it_creates_a_probability_from_a_value() -> Result<()> {
+        let result = Probability::from_value(0.1_f64)?;
+        assert_eq!(
+            *result.exact(),
+            BigRational::new(BigInt::from(1_u8), BigInt::from(10_u8))
+        );
+        assert_eq!(result.notation(), ProbabilityNotation::Decimal);
+        assert!(Probability::from_value(1.5_f64).is_err());
+        assert!(Probability::from_value(f64::NAN).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_a_decimal_as_an_exact_probability() -> Result<()> {
+        {
+            let result = Probability::from_str("0.75")?;
+            assert_eq!(
+                *result.exact(),
+                BigRational::new(BigInt::from(3_u8), BigInt::from(4_u8))
+            );
+            assert!(epsilon_compare(result.value(), 0.75_f64));
+        }
+        {
+            let result = Probability::from_str("1e-12")?;
+            assert_eq!(
+                *result.exact(),
+                BigRational::new(BigInt::from(1_u8), BigInt::from(1_000_000_000_000_u64))
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_a_decimal_with_a_huge_exponent_without_hanging() -> Result<()> {
+        assert!(Probability::from_str("1e-9999999")?.exact().is_zero());
+        assert!(Probability::from_str("1e-2147483648")?.exact().is_zero());
+        assert!(Probability::from_str("5e-99999999999")?.exact().is_zero());
+        assert!(Probability::from_str("1e9999999").is_err());
+        assert!(Probability::from_str("1e-1x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_parse_an_invalid_exact_probability() {
+        assert!(Probability::from_str("4/3").is_err());
+        assert!(Probability::from_str("1/0").is_err());
+        assert!(Probability::from_str("-1/3").is_err());
+        assert!(Probability::from_str("1/3/4").is_err());
+        assert!(Probability::from_str("--0.5").is_err());
+        assert!(Probability::from_str("NaN").is_err());
+    }
+
+    #[test]
+    fn it_calculates_the_exact_posterior_probability_when_evidence_is_observed() -> Result<()> {
+        let prior = Probability::from_str("1/3")?;
+        let likelihood = Probability::from_str("3/4")?;
+        let likelihood_null = Probability::from_str("1/4")?;
+        let result = calculate_exact_posterior_probability(
+            prior.exact(),
+            likelihood.exact(),
+            likelihood_null.exact(),
+            &Evidence::Observed,
+            "test",
+        )?;
+        assert_eq!(
+            result,
+            BigRational::new(BigInt::from(3_u8), BigInt::from(5_u8))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_calculates_the_exact_posterior_probability_when_evidence_is_not_observed() -> Result<()> {
+        let prior = Probability::from_str("1/3")?;
+        let likelihood = Probability::from_str("3/4")?;
+        let likelihood_null = Probability::from_str("1/4")?;
+        let result = calculate_exact_posterior_probability(
+            prior.exact(),
+            likelihood.exact(),
+            likelihood_null.exact(),
+            &Evidence::NotObserved,
+            "test",
+        )?;
+        assert_eq!(
+            result,
+            BigRational::new(BigInt::from(1_u8), BigInt::from(7_u8))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_calculate_the_exact_posterior_probability_when_the_marginal_likelihood_is_zero()
+    {
+        let prior = BigRational::one();
+        let likelihood = BigRational::zero();
+        let likelihood_null = BigRational::one();
+        let result = calculate_exact_posterior_probability(
+            &prior,
+            &likelihood,
+            &likelihood_null,
+            &Evidence::Observed,
+            "test",
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_calculates_the_posterior_probability_of_an_extreme_prior_without_underflowing(
+    ) -> Result<()> {
+        let observations = vec![Observation::new(1e-10_f64, 0.9_f64, Evidence::Observed); 20];
+        let result = calculate_chained_posterior_probability(1e-12_f64, &observations, "test")?;
+        assert!(result > 0.0_f64);
+        Ok(())
+    }
+
+    #[test]
+    fn it_calculates_the_posterior_probability_of_a_chain_that_passes_near_certainty() -> Result<()>
+    {
+        let mut observations = vec![Observation::new(0.9_f64, 1e-10_f64, Evidence::Observed); 20];
+        observations.extend(vec![
+            Observation::new(1e-10_f64, 0.9_f64, Evidence::Observed);
+            20
+        ]);
+        let result = calculate_chained_posterior_probability(1e-12_f64, &observations, "test")?;
+        assert!((result - 1e-12_f64).abs() < 1e-20_f64);
+        Ok(())
+    }
+
+    #[test]
+    fn it_calculates_a_chained_posterior_probability_equal_to_sequential_updates() -> Result<()> {
+        let first = Observation::new(0.75_f64, 0.5_f64, Evidence::Observed);
+        let second = Observation::new(0.2_f64, 0.6_f64, Evidence::NotObserved);
+        let chained = calculate_chained_posterior_probability(
+            0.3_f64,
+            &[first.clone(), second.clone()],
+            "test",
+        )?;
+        let sequential = calculate_posterior_probability(
+            calculate_posterior_probability(
+                0.3_f64,
+                first.likelihood,
+                first.likelihood_null,
+                &first.evidence,
+                "test",
+            )?,
+            second.likelihood,
+            second.likelihood_null,
+            &second.evidence,
+            "test",
+        )?;
+        assert!((chained - sequential).abs() < 1e-12_f64);
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_calculate_a_chained_posterior_probability_when_the_marginal_likelihood_is_zero()
+    {
+        let observations = [
+            Observation::new(0.5_f64, 0.5_f64, Evidence::Observed),
+            Observation::new(0.0_f64, 0.0_f64, Evidence::Observed),
+        ];
+        let result = calculate_chained_posterior_probability(0.5_f64, &observations, "test");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_parses_a_percentage() -> Result<()> {
+        let result = parse_probability("75%")?;
+        assert!(epsilon_compare(result.value(), 0.75_f64));
+        assert_eq!(result.notation(), ProbabilityNotation::Percentage);
+        assert_eq!(
+            result.interpretation(),
+            Some("75% (percentage) = 3/4 = 0.75".to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_odds() -> Result<()> {
+        let result = parse_probability("3:1")?;
+        assert!(epsilon_compare(result.value(), 0.75_f64));
+        assert_eq!(result.notation(), ProbabilityNotation::Odds);
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_a_natural_frequency() -> Result<()> {
+        {
+            let result = parse_probability("1 in 1000")?;
+            assert!(epsilon_compare(result.value(), 0.001_f64));
+            assert_eq!(result.notation(), ProbabilityNotation::Frequency);
+        }
+        {
+            let result = parse_probability("1 IN 10,000")?;
+            assert!(epsilon_compare(result.value(), 0.0001_f64));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_does_not_describe_the_interpretation_of_a_decimal() -> Result<()> {
+        let result = parse_probability("0.75")?;
+        assert_eq!(result.notation(), ProbabilityNotation::Decimal);
+        assert_eq!(result.interpretation(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_parse_an_invalid_probability_notation() {
+        assert!(parse_probability("175%").is_err());
+        assert!(parse_probability("0:0").is_err());
+        assert!(parse_probability("-1:3").is_err());
+        assert!(parse_probability("-3:-1").is_err());
+        assert!(parse_probability("-1/-4").is_err());
+        assert!(parse_probability("1 in 0").is_err());
+        assert!(parse_probability("-1 in -4").is_err());
+        assert!(parse_probability("2 in 1").is_err());
+        assert!(parse_probability("one in ten").is_err());
+    }
+
+    #[test]
+    fn it_parses_a_batch_of_observations() -> Result<()> {
+        let batch = parse_batch(
+            r#"[
+                {"name": "rain", "likelihood": 0.8, "likelihood_null": 0.2},
+                {"name": "cloud", "likelihood": 0.6, "likelihood_null": 0.4, "evidence": "n"}
+            ]"#,
+        )?;
+        assert_eq!(
+            batch,
+            vec![
+                (
+                    "rain".to_owned(),
+                    Observation::new(0.8, 0.2, Evidence::Observed)
+                ),
+                (
+                    "cloud".to_owned(),
+                    Observation::new(0.6, 0.4, Evidence::NotObserved)
+                ),
+            ]
+        );
+        assert!(
+            parse_batch(r#"[{"name": "rain", "likelihood": 1.5, "likelihood_null": 0.2}]"#)
+                .is_err()
+        );
+        assert!(parse_batch(
+            r#"[{"name": "rain", "likelihood": 0.5, "likelihood_null": 0.2, "evidence": "maybe"}]"#
+        )
+        .is_err());
+        assert!(parse_batch(r#"{"name": "rain"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_records_a_series_of_observations_with_a_note() -> Result<()> {
+        let store = store::SledStore::open(&store::tests::temporary_path("observations"))?;
+        let observed = Observation::new(0.8, 0.2, Evidence::Observed);
+        let not_observed = Observation::new(0.8, 0.2, Evidence::NotObserved);
+        let posterior_probability = record_observations(
+            &store,
+            "rain",
+            0.5,
+            &[observed.clone(), observed, not_observed],
+            Some("Forecast and radar"),
+        )?;
+        assert!((posterior_probability - 0.8).abs() < 1e-9);
+        assert_eq!(store.get_prior("rain")?, Some(posterior_probability));
+        let history = store.get_history("rain")?.unwrap_or_default();
+        let notes: Vec<Option<&str>> = history.iter().map(|entry| entry.note.as_deref()).collect();
+        assert_eq!(notes, vec![None, None, Some("Forecast and radar")]);
+        assert!((history[1].probability - 16.0 / 17.0).abs() < 1e-9);
+        assert!(record_observations(&store, "rain", 0.5, &[], None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_records_and_applies_a_chain_that_passes_near_certainty() -> Result<()> {
+        let mut observations = vec![Observation::new(0.9, 1e-10, Evidence::Observed); 20];
+        observations.extend(vec![Observation::new(1e-10, 0.9, Evidence::Observed); 20]);
+        let store = store::SledStore::open(&store::tests::temporary_path("near-certainty"))?;
+        let posterior_probability =
+            record_observations(&store, "rain", 1e-12, &observations, None)?;
+        assert!((posterior_probability - 1e-12).abs() < 1e-20);
+        let history = store.get_history("rain")?.unwrap_or_default();
+        assert!(epsilon_compare(history[19].probability, 1.0));
+
+        store.apply(&[Operation::SetPrior {
+            name: "cloud".to_owned(),
+            prior: 1e-12,
+        }])?;
+        let updates: Vec<(String, Observation)> = observations
+            .into_iter()
+            .map(|observation| ("cloud".to_owned(), observation))
+            .collect();
+        let results = apply_observations(&store, &updates)?;
+        assert!((results[0].posterior_probability - 1e-12).abs() < 1e-20);
+        assert_eq!(
+            store.get_prior("cloud")?,
+            Some(results[0].posterior_probability)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_applies_a_batch_of_observations_as_one_transaction() -> Result<()> {
+        let store = store::SledStore::open(&store::tests::temporary_path("batch"))?;
+        store.apply(&[
+            Operation::SetPrior {
+                name: "rain".to_owned(),
+                prior: 0.5,
+            },
+            Operation::SetPrior {
+                name: "cloud".to_owned(),
+                prior: 0.5,
+            },
+        ])?;
+        let observed = Observation::new(0.8, 0.2, Evidence::Observed);
+
+        let results = apply_observations(
+            &store,
+            &[
+                ("rain".to_owned(), observed.clone()),
+                ("cloud".to_owned(), observed.clone()),
+                ("rain".to_owned(), observed.clone()),
+            ],
+        )?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].name, "rain");
+        assert!(epsilon_compare(results[0].prior, 0.5));
+        assert!(epsilon_compare(
+            results[0].posterior_probability,
+            16.0 / 17.0
+        ));
+        assert!(epsilon_compare(results[1].posterior_probability, 0.8));
+        assert_eq!(
+            store.get_history("rain")?.map(|history| history.len()),
+            Some(2)
+        );
+
+        let impossible = Observation::new(0.0, 0.0, Evidence::Observed);
+        assert!(apply_observations(
+            &store,
+            &[
+                ("cloud".to_owned(), observed.clone()),
+                ("rain".to_owned(), impossible),
+            ],
+        )
+        .is_err());
+        assert!(apply_observations(
+            &store,
+            &[
+                ("cloud".to_owned(), observed),
+                (
+                    "missing".to_owned(),
+                    Observation::new(0.8, 0.2, Evidence::Observed)
+                ),
+            ],
+        )
+        .is_err());
+        assert_eq!(store.get_prior("cloud")?, Some(0.8));
+        assert_eq!(
+            store.get_history("cloud")?.map(|history| history.len()),
+            Some(1)
+        );
+        Ok(())
     }
 }
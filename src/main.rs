@@ -1,30 +1,47 @@
 use anyhow::Result;
 use ask_bayes::prelude::*;
 use clap::Parser;
-use log::{debug, info, LevelFilter};
-use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
+use log::{debug, info, warn, LevelFilter};
+use num_traits::ToPrimitive;
+use simplelog::{ColorChoice, Config as LogConfig, TermLogger, TerminalMode};
+use std::io::Read;
+use std::path::Path;
 
 #[cfg(not(tarpaulin_include))]
 fn main() -> Result<()> {
     TermLogger::init(
         LevelFilter::Info,
-        Config::default(),
+        LogConfig::default(),
         TerminalMode::Mixed,
         ColorChoice::Auto,
     )?;
 
     let args = Args::parse();
     debug!("{:?}", args);
+    let config = Config::load(args.config.as_deref())?;
+    use_store(config.store_location(args.store.clone())?)?;
+    if let Some(precision) = config.output.precision {
+        use_precision(precision)?;
+    }
     if args.wizard {
         wizard()?;
         return Ok(());
     }
 
+    if let Some(command) = args.command {
+        return run_command(command, &config, args.config.as_deref(), args.store);
+    }
+
     let name = args.name.ok_or(anyhow::anyhow!("name is required"))?;
+    let name = if args.set_prior.is_some() || args.update_prior == Some(UpdateHypothesis::Update) {
+        check_name(&name)?
+    } else {
+        name
+    };
 
     if args.get_prior {
         let p = get_prior(&name)?;
-        info!("P({name}) = {}", p);
+        info!("P({name}) = {}", Rounded(p));
         return Ok(());
     }
 
@@ -35,38 +52,571 @@ fn main() -> Result<()> {
     }
 
     if let Some(prior) = args.set_prior {
-        set_prior(&name, prior)?;
-        info!("P({name}) = {}", prior);
+        report_probability_interpretation(&format!("P({name})"), &prior);
+        set_prior(&name, prior.value())?;
+        info!("P({name}) = {}", Rounded(prior.value()));
         return Ok(());
     }
 
-    let prior = args.prior.ok_or(anyhow::anyhow!("prior is required"))?;
-    let likelihood = args
-        .likelihood
-        .ok_or(anyhow::anyhow!("likelihood is required"))?;
-    let likelihood_not = args
-        .likelihood_null
-        .ok_or(anyhow::anyhow!("likelihood_not is required"))?;
-    let evidence = args
-        .evidence
-        .ok_or(anyhow::anyhow!("evidence is required"))?;
-    let posterior_probability =
-        calculate_posterior_probability(prior, likelihood, likelihood_not, &evidence, &name)?;
-    let output_format = args.output.ok_or(anyhow::anyhow!("output is required"))?;
-
-    report_posterior_probability(
+    let ResolvedUpdate {
         prior,
         likelihood,
-        likelihood_not,
-        &evidence,
-        posterior_probability,
-        &name,
-        &output_format,
-    );
+        likelihood_null: likelihood_not,
+        evidence,
+        ..
+    } = {
+        let config = config.for_hypothesis(&name);
+        match args.with_evidence {
+            Some(ref evidence_name) => {
+                let definition = get_evidence(evidence_name, Some(&name))?;
+                config.resolve_update(
+                    args.prior,
+                    Some(
+                        args.likelihood
+                            .map_or_else(|| Probability::from_value(definition.likelihood), Ok)?,
+                    ),
+                    Some(
+                        args.likelihood_null.map_or_else(
+                            || Probability::from_value(definition.likelihood_null),
+                            Ok,
+                        )?,
+                    ),
+                    args.evidence,
+                    None,
+                )?
+            }
+            None => config.resolve_update(
+                args.prior,
+                args.likelihood,
+                args.likelihood_null,
+                args.evidence,
+                args.preset.as_deref(),
+            )?,
+        }
+    };
+    let output_format = config.output_format(args.output);
+
+    report_probability_interpretation(&format!("P({name})"), &prior);
+    report_probability_interpretation(&format!("P(E|{name})"), &likelihood);
+    report_probability_interpretation(&format!("P(E|\u{ac}{name})"), &likelihood_not);
+
+    let posterior_probability = if args.exact {
+        let posterior_probability = calculate_exact_posterior_probability(
+            prior.exact(),
+            likelihood.exact(),
+            likelihood_not.exact(),
+            &evidence,
+            &name,
+        )?;
+        report_exact_posterior_probability(
+            prior.exact(),
+            likelihood.exact(),
+            likelihood_not.exact(),
+            &evidence,
+            &posterior_probability,
+            &name,
+            &output_format,
+        );
+        posterior_probability.to_f64().ok_or(anyhow::anyhow!(
+            "posterior probability is not representable"
+        ))?
+    } else {
+        let posterior_probability = calculate_posterior_probability(
+            prior.value(),
+            likelihood.value(),
+            likelihood_not.value(),
+            &evidence,
+            &name,
+        )?;
+        report_posterior_probability(
+            prior.value(),
+            likelihood.value(),
+            likelihood_not.value(),
+            &evidence,
+            posterior_probability,
+            &name,
+            &output_format,
+        );
+        posterior_probability
+    };
+
+    // Saved actions are only looked up when the store is used anyway, so a plain calculation never waits on it
+    let uses_store =
+        args.update_prior == Some(UpdateHypothesis::Update) || args.with_evidence.is_some();
+    if uses_store {
+        let actions = find_actions(&name).unwrap_or_else(|error| {
+            warn!("Could not read the actions for {name}: {error}");
+            None
+        });
+        if let Some(actions) = actions {
+            let analysis = analyze_decision(posterior_probability, &actions)?;
+            report_decision_analysis(&name, &analysis, &output_format);
+        }
+    }
 
     if let Some(UpdateHypothesis::Update) = args.update_prior {
-        set_prior(&name, posterior_probability)?;
-        info!("P({name}) has been updated to {}", posterior_probability);
+        update_prior(
+            &name,
+            prior.value(),
+            Observation::new(likelihood.value(), likelihood_not.value(), evidence),
+            posterior_probability,
+        )?;
+        info!(
+            "P({name}) has been updated to {}",
+            Rounded(posterior_probability)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(tarpaulin_include))]
+fn run_command(
+    command: Command,
+    config: &Config,
+    config_path: Option<&Path>,
+    store: Option<StoreLocation>,
+) -> Result<()> {
+    match command {
+        Command::Decide {
+            name,
+            prior,
+            actions,
+            save,
+            remove,
+            output,
+        } => {
+            if remove {
+                remove_actions(&name)?;
+                info!("Actions for {name} removed");
+                return Ok(());
+            }
+            let probability = match prior {
+                Some(prior) => {
+                    report_probability_interpretation(&format!("P({name})"), &prior);
+                    prior.value()
+                }
+                None => get_prior(&name)?,
+            };
+            let actions = if actions.is_empty() {
+                get_actions(&name)?
+            } else {
+                actions
+            };
+            if save {
+                set_actions(&name, &actions)?;
+                info!("Actions for {name} have been saved");
+            }
+            let analysis = analyze_decision(probability, &actions)?;
+            report_decision_analysis(&name, &analysis, &config.output_format(output));
+        }
+        Command::Voi {
+            name,
+            prior,
+            tests,
+            actions,
+            output,
+        } => {
+            let probability = match prior {
+                Some(prior) => {
+                    report_probability_interpretation(&format!("P({name})"), &prior);
+                    prior.value()
+                }
+                None => get_prior(&name)?,
+            };
+            let actions = if actions.is_empty() {
+                get_actions(&name)?
+            } else {
+                actions
+            };
+            let values = tests
+                .iter()
+                .map(|test| calculate_value_of_information(probability, test, &actions, &name))
+                .collect::<Result<Vec<_>>>()?;
+            let expected_value_of_perfect_information =
+                calculate_expected_value_of_perfect_information(probability, &actions)?;
+            report_value_of_information(
+                &name,
+                probability,
+                &values,
+                expected_value_of_perfect_information,
+                &config.output_format(output),
+            );
+        }
+        Command::Compare {
+            likelihoods_a,
+            likelihoods_b,
+            name_a,
+            name_b,
+            prior,
+            output,
+        } => {
+            report_probability_interpretation(&format!("P({name_a})"), &prior);
+            let likelihoods = |likelihoods: Vec<ModelLikelihood>| -> Vec<f64> {
+                likelihoods
+                    .into_iter()
+                    .map(|likelihood| likelihood.0)
+                    .collect()
+            };
+            let comparison = compare_models(
+                &likelihoods(likelihoods_a),
+                &likelihoods(likelihoods_b),
+                prior.value(),
+            )?;
+            report_model_comparison(&name_a, &name_b, &comparison, &config.output_format(output));
+        }
+        Command::Diagnose {
+            name,
+            sensitivity,
+            specificity,
+            prevalence,
+            population,
+            output,
+        } => {
+            report_probability_interpretation(&format!("P(E|{name})"), &sensitivity);
+            report_probability_interpretation(&format!("P(\u{ac}E|\u{ac}{name})"), &specificity);
+            let prevalence = match prevalence {
+                Some(prevalence) => {
+                    report_probability_interpretation(&format!("P({name})"), &prevalence);
+                    prevalence.value()
+                }
+                None => get_prior(&name)?,
+            };
+            let analysis = analyze_diagnostic_test(
+                sensitivity.value(),
+                specificity.value(),
+                prevalence,
+                &name,
+            )?;
+            let frequencies = natural_frequencies(
+                &analysis,
+                population
+                    .to_f64()
+                    .ok_or(anyhow::anyhow!("population is too large"))?,
+            );
+            report_diagnostic_analysis(
+                &name,
+                &analysis,
+                &frequencies,
+                &config.output_format(output),
+            );
+        }
+        Command::Plot {
+            name,
+            svg,
+            width,
+            height,
+            ascii,
+        } => {
+            let points = trajectory(&get_history(&name)?);
+            info!("\n{}", render_chart(&name, &points, width, height, ascii));
+            if let Some(path) = svg {
+                std::fs::write(&path, render_svg(&name, &points)?)?;
+                info!("Chart of {name} written to {}", path.display());
+            }
+        }
+        Command::Tag { name, tags, remove } => {
+            if remove {
+                remove_tags(&name, &tags)?;
+            } else if !tags.is_empty() {
+                get_prior(&name)?;
+                add_tags(&name, &tags)?;
+            }
+            let tags = get_tags(&name)?;
+            if tags.is_empty() {
+                info!("{name} has no tags");
+            } else {
+                info!(
+                    "Tags for {name}: {}",
+                    tags.into_iter().collect::<Vec<_>>().join(", ")
+                );
+            }
+        }
+        Command::Report {
+            mut names,
+            tag,
+            html,
+        } => {
+            if let Some(ref tag) = tag {
+                for name in hypotheses_with_tag(tag)? {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+            if names.is_empty() {
+                return Err(anyhow::anyhow!("No hypotheses to report"));
+            }
+            let hypotheses = names
+                .iter()
+                .map(|name| collect_hypothesis_report(name))
+                .collect::<Result<Vec<_>>>()?;
+            let title = tag.map_or_else(|| names.join(", "), |tag| format!("Report for {tag}"));
+            std::fs::write(&html, render_html_report(&title, &hypotheses)?)?;
+            info!(
+                "Report of {} hypotheses written to {}",
+                hypotheses.len(),
+                html.display()
+            );
+        }
+        Command::Serve { port } => {
+            let server = ApiServer::bind(port)?;
+            if let Some(address) = server.local_addr() {
+                info!("Serving the ask-bayes API on http://{address}");
+            }
+            server.run();
+        }
+        Command::Undo { name } => {
+            let (step, probability) = undo_prior(&name)?;
+            info!(
+                "P({name}) has been reverted to {} (step {step})",
+                Rounded(probability)
+            );
+        }
+        Command::Revert { name, to } => {
+            let (step, probability) = revert_prior(&name, &to)?;
+            info!(
+                "P({name}) has been reverted to {} (step {step})",
+                Rounded(probability)
+            );
+        }
+        Command::Rename { from, to } => {
+            rename_prior(&from, &to)?;
+            info!("{from} has been renamed to {to}");
+        }
+        Command::Merge { from, into } => {
+            let prior = merge_priors(&from, &into)?;
+            info!(
+                "{from} has been merged into {into}.  P({into}) = {}",
+                Rounded(prior)
+            );
+        }
+        Command::Evidence { command } => match command {
+            EvidenceCommand::Set {
+                name,
+                likelihood,
+                likelihood_null,
+                hypothesis,
+                description,
+            } => {
+                set_evidence(
+                    &name,
+                    hypothesis.as_deref(),
+                    &EvidenceDefinition::new(
+                        likelihood.value(),
+                        likelihood_null.value(),
+                        description,
+                    )?,
+                )?;
+                let scope = hypothesis.map_or_else(
+                    || "any hypothesis".to_owned(),
+                    |hypothesis| format!("hypothesis {hypothesis}"),
+                );
+                info!("Evidence {name} saved for {scope}");
+            }
+            EvidenceCommand::Get { name, hypothesis } => {
+                let definition = get_evidence(&name, hypothesis.as_deref())?;
+                info!(
+                    "{name}: P(E|H) = {}, P(E|\u{ac}H) = {}",
+                    Rounded(definition.likelihood),
+                    Rounded(definition.likelihood_null)
+                );
+                if let Some(description) = definition.description {
+                    info!("{description}");
+                }
+            }
+            EvidenceCommand::Remove { name, hypothesis } => {
+                remove_evidence(&name, hypothesis.as_deref())?;
+                info!("Evidence {name} removed");
+            }
+            EvidenceCommand::List { hypothesis } => {
+                report_evidence(&list_evidence(hypothesis.as_deref())?);
+            }
+            _ => unreachable!("every evidence command is handled"),
+        },
+        Command::Quantity { command } => match command {
+            QuantityCommand::Set {
+                name,
+                model,
+                mean,
+                sd,
+                noise_sd,
+                noise_weight,
+                unit,
+            } => {
+                let belief = GaussianBelief::new(model, mean, sd, noise_sd, noise_weight)?;
+                set_quantity(&name, belief, unit.as_deref())?;
+                info!("Belief about {name} saved with the {model} model");
+            }
+            QuantityCommand::Update {
+                name,
+                mut measurements,
+                csv,
+                column,
+                level,
+                output,
+            } => {
+                if let Some(file) = csv {
+                    let contents = if file.as_os_str() == "-" {
+                        let mut contents = String::new();
+                        std::io::stdin().read_to_string(&mut contents)?;
+                        contents
+                    } else {
+                        std::fs::read_to_string(&file)?
+                    };
+                    measurements.extend(parse_measurements_csv(&contents, column.as_deref())?);
+                }
+                let quantity = update_quantity(&name, &measurements)?;
+                info!(
+                    "{name} has been updated with {} measurements",
+                    measurements.len()
+                );
+                report_quantity(
+                    &name,
+                    &quantity,
+                    level.value(),
+                    &config.output_format(output),
+                )?;
+            }
+            QuantityCommand::Show {
+                name,
+                level,
+                output,
+            } => {
+                report_quantity(
+                    &name,
+                    &get_quantity(&name)?,
+                    level.value(),
+                    &config.output_format(output),
+                )?;
+            }
+            QuantityCommand::Remove { name } => {
+                remove_quantity(&name)?;
+                info!("{name} has been removed");
+            }
+            QuantityCommand::List => report_quantities(&list_quantities()?),
+            _ => unreachable!("every quantity command is handled"),
+        },
+        Command::Categories { command } => match command {
+            CategoriesCommand::Set {
+                name,
+                categories,
+                weight,
+            } => {
+                let belief = CategoricalBelief::new(&categories, weight)?;
+                set_categorical(&name, &belief)?;
+                info!(
+                    "Belief about {name} saved with {} categories",
+                    belief.categories.len()
+                );
+            }
+            CategoriesCommand::Update {
+                name,
+                counts,
+                level,
+                output,
+            } => {
+                let belief = update_categorical(&name, &counts)?;
+                info!(
+                    "{name} has been updated with {} observations",
+                    counts.iter().map(|count| count.count).sum::<u64>()
+                );
+                report_categorical(&name, &belief, level.value(), &config.output_format(output))?;
+            }
+            CategoriesCommand::Show {
+                name,
+                level,
+                output,
+            } => {
+                report_categorical(
+                    &name,
+                    &get_categorical(&name)?,
+                    level.value(),
+                    &config.output_format(output),
+                )?;
+            }
+            CategoriesCommand::Remove { name } => {
+                remove_categorical(&name)?;
+                info!("{name} has been removed");
+            }
+            CategoriesCommand::List => report_categoricals(&list_categoricals()?)?,
+            _ => unreachable!("every categories command is handled"),
+        },
+        Command::Completions { shell } => {
+            write_completions(shell, &mut std::io::stdout())?;
+        }
+        Command::Names { namespace } => {
+            let names = match namespace {
+                Some(ref namespace) => list_namespace(namespace)?
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .collect(),
+                None => hypothesis_names()?,
+            };
+            for name in names {
+                println!("{name}");
+            }
+        }
+        Command::List { namespace, output } => {
+            report_hypotheses(
+                &list_namespace(namespace.as_deref().unwrap_or_default())?,
+                &config.output_format(output),
+            );
+        }
+        Command::Dashboard => run_dashboard()?,
+        Command::Export { namespace } => {
+            let hypotheses = export_namespace(namespace.as_deref().unwrap_or_default())?;
+            println!("{}", serde_json::to_string_pretty(&hypotheses)?);
+        }
+        Command::Move { from, to } => {
+            for (old_name, new_name) in move_namespace(&from, &to)? {
+                info!("{old_name} has been moved to {new_name}");
+            }
+        }
+        Command::Config {
+            command: ConfigCommand::Show,
+        } => {
+            let path = match config_path {
+                Some(path) => path.to_path_buf(),
+                None => Config::default_path()?,
+            };
+            let source = if path.exists() {
+                format!("# Configuration file: {}", path.display())
+            } else {
+                format!(
+                    "# Configuration file: {} (not found, using built-in defaults)",
+                    path.display()
+                )
+            };
+            info!(
+                "\n{source}\n# Precedence: command-line flags, then environment variables, then the configuration file, then built-in defaults\n{}",
+                config.effective(store)?
+            );
+        }
+        Command::Batch { file } => {
+            let batch = if file.as_os_str() == "-" {
+                let mut batch = String::new();
+                std::io::stdin().read_to_string(&mut batch)?;
+                batch
+            } else {
+                std::fs::read_to_string(&file)?
+            };
+            for update in update_priors(&parse_batch(&batch)?)? {
+                info!(
+                    "P({}) has been updated from {} to {}",
+                    update.name,
+                    Rounded(update.prior),
+                    Rounded(update.posterior_probability)
+                );
+            }
+        }
+        Command::Migrate { from, to } => {
+            if from == to {
+                return Err(anyhow::anyhow!("Cannot migrate {from} into itself"));
+            }
+            let copied = migrate(&*open_store_at(&from)?, &*open_store_at(&to)?)?;
+            info!("Copied {copied} hypotheses from {from} to {to}");
+        }
+        _ => unreachable!("every command is handled"),
     }
     Ok(())
 }
@@ -0,0 +1,302 @@
+//! Named evidence: likelihood pairs saved once and applied to hypotheses by name, either generically or for a particular hypothesis.
+use crate::store::{
+    collect_records, find_record, open_store, remove_record, set_record, Operation, Store,
+};
+use crate::validate_probability;
+use anyhow::{anyhow, Result};
+use log::info;
+use prettytable::{format, Cell, Row, Table};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The kind of metadata holding generic evidence, keyed by the name of the evidence
+pub(crate) const EVIDENCE_METADATA: &str = "evidence";
+/// The kind of metadata holding the evidence saved for a hypothesis, keyed by the name of the hypothesis
+pub(crate) const HYPOTHESIS_EVIDENCE_METADATA: &str = "hypothesis-evidence";
+
+/// The likelihoods of a piece of evidence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct EvidenceDefinition {
+    /// The likelihood of the evidence P(E|H)
+    pub likelihood: f64,
+    /// The likelihood of the evidence P(E|¬H)
+    pub likelihood_null: f64,
+    /// What the evidence is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl EvidenceDefinition {
+    /// Creates a new evidence definition
+    /// # Errors
+    /// - If either likelihood is not a valid probability
+    #[inline]
+    pub fn new(likelihood: f64, likelihood_null: f64, description: Option<String>) -> Result<Self> {
+        let definition = Self {
+            likelihood,
+            likelihood_null,
+            description,
+        };
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    /// Validates the likelihoods, which should be valid probabilities
+    fn validate(&self) -> Result<()> {
+        validate_probability(self.likelihood)?;
+        validate_probability(self.likelihood_null)
+    }
+}
+
+/// A saved piece of evidence
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct NamedEvidence {
+    /// The name of the evidence
+    pub name: String,
+    /// The hypothesis the likelihoods were saved for, or `None` if they apply to any hypothesis
+    pub hypothesis: Option<String>,
+    /// The likelihoods of the evidence
+    pub definition: EvidenceDefinition,
+}
+
+/// Reads the evidence saved for the hypothesis
+fn read_hypothesis_evidence(
+    store: &dyn Store,
+    hypothesis: &str,
+) -> Result<BTreeMap<String, EvidenceDefinition>> {
+    Ok(find_record(store, HYPOTHESIS_EVIDENCE_METADATA, hypothesis)?.unwrap_or_default())
+}
+
+/// The operation that saves the evidence for the hypothesis, removing the record if there is none left
+fn write_hypothesis_evidence(
+    hypothesis: &str,
+    evidence: &BTreeMap<String, EvidenceDefinition>,
+) -> Result<Operation> {
+    if evidence.is_empty() {
+        return Ok(remove_record(HYPOTHESIS_EVIDENCE_METADATA, hypothesis));
+    }
+    set_record(HYPOTHESIS_EVIDENCE_METADATA, hypothesis, evidence)
+}
+
+/// Saves the evidence in the store
+fn save_evidence(
+    store: &dyn Store,
+    name: &str,
+    hypothesis: Option<&str>,
+    definition: &EvidenceDefinition,
+) -> Result<()> {
+    definition.validate()?;
+    store.transact(&mut |store| {
+        let operation = match hypothesis {
+            Some(hypothesis) => {
+                let mut evidence = read_hypothesis_evidence(store, hypothesis)?;
+                evidence.insert(name.to_owned(), definition.clone());
+                write_hypothesis_evidence(hypothesis, &evidence)?
+            }
+            None => set_record(EVIDENCE_METADATA, name, definition)?,
+        };
+        Ok(vec![operation])
+    })
+}
+
+/// Looks up the evidence in the store: the likelihoods saved for the hypothesis if there are any, otherwise the generic likelihoods
+fn find_evidence(
+    store: &dyn Store,
+    name: &str,
+    hypothesis: Option<&str>,
+) -> Result<EvidenceDefinition> {
+    if let Some(hypothesis) = hypothesis {
+        if let Some(definition) = read_hypothesis_evidence(store, hypothesis)?.remove(name) {
+            return Ok(definition);
+        }
+    }
+    find_record(store, EVIDENCE_METADATA, name)?
+        .ok_or_else(|| anyhow!("Could not find evidence {name}"))
+}
+
+/// Removes the evidence from the store
+fn delete_evidence(store: &dyn Store, name: &str, hypothesis: Option<&str>) -> Result<()> {
+    store.transact(&mut |store| {
+        let operation = if let Some(hypothesis) = hypothesis {
+            let mut evidence = read_hypothesis_evidence(store, hypothesis)?;
+            if evidence.remove(name).is_none() {
+                return Err(anyhow!(
+                    "Could not find evidence {name} for hypothesis {hypothesis}"
+                ));
+            }
+            write_hypothesis_evidence(hypothesis, &evidence)?
+        } else {
+            if store.get_metadata(EVIDENCE_METADATA, name)?.is_none() {
+                return Err(anyhow!("Could not find evidence {name}"));
+            }
+            remove_record(EVIDENCE_METADATA, name)
+        };
+        Ok(vec![operation])
+    })
+}
+
+/// Lists the evidence in the store: the generic evidence followed by the evidence saved for each hypothesis, or only the evidence that applies to the hypothesis if one is given
+fn collect_evidence(store: &dyn Store, hypothesis: Option<&str>) -> Result<Vec<NamedEvidence>> {
+    let mut evidence: Vec<NamedEvidence> = collect_records(store, EVIDENCE_METADATA)?
+        .into_iter()
+        .map(|(name, definition)| NamedEvidence {
+            name,
+            hypothesis: None,
+            definition,
+        })
+        .collect();
+    let owners = match hypothesis {
+        Some(hypothesis) => vec![hypothesis.to_owned()],
+        None => store
+            .list_metadata(HYPOTHESIS_EVIDENCE_METADATA)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+    };
+    for owner in owners {
+        for (name, definition) in read_hypothesis_evidence(store, &owner)? {
+            evidence.push(NamedEvidence {
+                name,
+                hypothesis: Some(owner.clone()),
+                definition,
+            });
+        }
+    }
+    Ok(evidence)
+}
+
+/// Saves the likelihoods of the evidence, for the hypothesis if one is given, otherwise for any hypothesis
+/// # Errors
+/// - If the database cannot be opened
+/// - If the evidence cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn set_evidence(
+    name: &str,
+    hypothesis: Option<&str>,
+    definition: &EvidenceDefinition,
+) -> Result<()> {
+    save_evidence(&*open_store()?, name, hypothesis, definition)
+}
+
+/// Gets the likelihoods of the evidence: those saved for the hypothesis if one is given and has any, otherwise the generic likelihoods
+/// # Errors
+/// - If the database cannot be opened
+/// - If the evidence has not been saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn get_evidence(name: &str, hypothesis: Option<&str>) -> Result<EvidenceDefinition> {
+    find_evidence(&*open_store()?, name, hypothesis)
+}
+
+/// Removes the likelihoods of the evidence saved for the hypothesis if one is given, otherwise the generic likelihoods
+/// # Errors
+/// - If the database cannot be opened
+/// - If the evidence has not been saved
+/// - If the evidence cannot be removed
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn remove_evidence(name: &str, hypothesis: Option<&str>) -> Result<()> {
+    delete_evidence(&*open_store()?, name, hypothesis)
+}
+
+/// Lists the saved evidence: the generic evidence followed by the evidence saved for each hypothesis, or only the evidence that applies to the hypothesis if one is given
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved evidence cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn list_evidence(hypothesis: Option<&str>) -> Result<Vec<NamedEvidence>> {
+    collect_evidence(&*open_store()?, hypothesis)
+}
+
+/// Reports the evidence as a table
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_evidence(evidence: &[NamedEvidence]) {
+    if evidence.is_empty() {
+        info!("No evidence has been saved");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Evidence"),
+        Cell::new("Hypothesis"),
+        Cell::new("P(E|H)"),
+        Cell::new("P(E|\u{ac}H)"),
+        Cell::new("Description"),
+    ]));
+    for item in evidence {
+        table.add_row(Row::new(vec![
+            Cell::new(&item.name),
+            Cell::new(item.hypothesis.as_deref().unwrap_or("any")),
+            Cell::new(&item.definition.likelihood.to_string()),
+            Cell::new(&item.definition.likelihood_null.to_string()),
+            Cell::new(item.definition.description.as_deref().unwrap_or_default()),
+        ]));
+    }
+    table.printstd();
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temporary_path;
+    use crate::store::SledStore;
+
+    #[test]
+    fn it_prefers_evidence_saved_for_the_hypothesis() -> Result<()> {
+        let store = SledStore::open(&temporary_path("named-evidence"))?;
+        let generic =
+            EvidenceDefinition::new(0.3_f64, 0.1_f64, Some("A flaky CI test failed".to_owned()))?;
+        let specific = EvidenceDefinition::new(0.6_f64, 0.1_f64, None)?;
+        save_evidence(&store, "ci-flake", None, &generic)?;
+        save_evidence(&store, "ci-flake", Some("db-overload"), &specific)?;
+
+        assert_eq!(find_evidence(&store, "ci-flake", None)?, generic);
+        assert_eq!(find_evidence(&store, "ci-flake", Some("cache"))?, generic);
+        assert_eq!(
+            find_evidence(&store, "ci-flake", Some("db-overload"))?,
+            specific
+        );
+        assert!(find_evidence(&store, "pager", Some("db-overload")).is_err());
+
+        let listed = collect_evidence(&store, None)?;
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].hypothesis, None);
+        assert_eq!(listed[1].hypothesis.as_deref(), Some("db-overload"));
+        assert_eq!(collect_evidence(&store, Some("cache"))?.len(), 1);
+
+        delete_evidence(&store, "ci-flake", Some("db-overload"))?;
+        assert!(delete_evidence(&store, "ci-flake", Some("db-overload")).is_err());
+        assert_eq!(
+            find_evidence(&store, "ci-flake", Some("db-overload"))?,
+            generic
+        );
+        assert_eq!(
+            store.get_metadata(HYPOTHESIS_EVIDENCE_METADATA, "db-overload")?,
+            None
+        );
+        delete_evidence(&store, "ci-flake", None)?;
+        assert!(collect_evidence(&store, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_invalid_likelihoods() -> Result<()> {
+        let store = SledStore::open(&temporary_path("named-evidence-invalid"))?;
+        assert!(EvidenceDefinition::new(1.5_f64, 0.1_f64, None).is_err());
+        assert!(EvidenceDefinition::new(0.3_f64, f64::NAN, None).is_err());
+
+        let mut definition = EvidenceDefinition::new(0.3_f64, 0.1_f64, None)?;
+        definition.likelihood_null = -0.1_f64;
+        assert!(save_evidence(&store, "ci-flake", None, &definition).is_err());
+        assert!(save_evidence(&store, "ci-flake", Some("db-overload"), &definition).is_err());
+        assert!(collect_evidence(&store, None)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+//! Guards against accidentally creating hypotheses whose names are near-duplicates of saved ones, and renames and merges hypotheses along with their history.
+use crate::history::{first_entry_step, Change, HistoryEntry};
+use crate::named_evidence::HYPOTHESIS_EVIDENCE_METADATA;
+use crate::store::{open_store, Operation, Store};
+use crate::tags::TAGS_METADATA;
+use crate::HYPOTHESIS_METADATA;
+use anyhow::{anyhow, Result};
+use dialoguer::Select;
+use log::warn;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+
+/// The edit distance between two names: the number of characters that must be inserted, removed, replaced or swapped with their neighbour to turn one into the other
+#[inline]
+#[must_use]
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The largest edit distance at which a name is considered a near-duplicate of another.  Short names differ by a single character too often to be considered near-duplicates unless only their case differs.
+fn similarity_threshold(name: &str) -> usize {
+    match name.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// The saved names that are near-duplicates of the name: those that differ from it only in case, or by a small edit distance.  Returns the closest names first.
+#[inline]
+#[must_use]
+pub fn similar_names(name: &str, existing: &[String]) -> Vec<String> {
+    let folded = name.to_lowercase();
+    let mut similar: Vec<(usize, &String)> = existing
+        .iter()
+        .filter(|candidate| candidate.as_str() != name)
+        .map(|candidate| (edit_distance(&folded, &candidate.to_lowercase()), candidate))
+        .filter(|&(distance, _)| distance <= similarity_threshold(name))
+        .collect();
+    similar.sort();
+    similar
+        .into_iter()
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
+}
+
+/// Checks the name of a hypothesis about to be saved against the saved hypotheses.
+///
+/// If the hypothesis is new but its name is a near-duplicate of a saved one, asks whether to use the saved hypothesis instead when run in a terminal, and otherwise warns.  Returns the name to save the hypothesis under.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the prompt cannot be shown
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn check_name(name: &str) -> Result<String> {
+    let existing: Vec<String> = open_store()?
+        .list_priors()?
+        .into_iter()
+        .map(|(saved, _)| saved)
+        .collect();
+    if existing.iter().any(|saved| saved == name) {
+        return Ok(name.to_owned());
+    }
+    let similar = similar_names(name, &existing);
+    if similar.is_empty() {
+        return Ok(name.to_owned());
+    }
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        warn!(
+            "Creating hypothesis {name}, which is similar to the saved hypotheses {}",
+            similar.join(", ")
+        );
+        return Ok(name.to_owned());
+    }
+    let mut choices: Vec<String> = similar
+        .iter()
+        .map(|saved| format!("Use the saved hypothesis {saved}"))
+        .collect();
+    choices.push(format!("Create a new hypothesis {name}"));
+    let choice = Select::new()
+        .with_prompt(format!(
+            "{name} has not been saved, but is similar to saved hypotheses"
+        ))
+        .items(&choices)
+        .default(0)
+        .interact()?;
+    Ok(similar.get(choice).map_or(name, String::as_str).to_owned())
+}
+
+/// Whether anything is saved under the name: a prior, history or metadata
+fn is_saved(store: &dyn Store, name: &str) -> Result<bool> {
+    if store.get_prior(name)?.is_some() || store.get_history(name)?.is_some() {
+        return Ok(true);
+    }
+    for kind in HYPOTHESIS_METADATA {
+        if store.get_metadata(kind, name)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The operations removing everything saved under the name: its prior, history, actions, tags and saved evidence
+pub(crate) fn removal(name: &str) -> Vec<Operation> {
+    let mut operations = vec![
+        Operation::RemovePrior {
+            name: name.to_owned(),
+        },
+        Operation::RemoveHistory {
+            name: name.to_owned(),
+        },
+    ];
+    operations.extend(
+        HYPOTHESIS_METADATA
+            .into_iter()
+            .map(|kind| Operation::RemoveMetadata {
+                kind: kind.to_owned(),
+                name: name.to_owned(),
+            }),
+    );
+    operations
+}
+
+/// The operations moving the prior, history and metadata of a hypothesis to a new name
+/// # Errors
+/// - If the names are the same
+/// - If the hypothesis has not been saved
+/// - If a hypothesis has already been saved under the new name
+/// - If the store cannot be read
+pub(crate) fn rename_operations(store: &dyn Store, from: &str, to: &str) -> Result<Vec<Operation>> {
+    if from == to {
+        return Err(anyhow!("{from} cannot be renamed to itself"));
+    }
+    if !is_saved(store, from)? {
+        return Err(anyhow!("Could not find hypothesis {from}"));
+    }
+    if is_saved(store, to)? {
+        return Err(anyhow!(
+            "Hypothesis {to} has already been saved.  Use merge to combine {from} with it"
+        ));
+    }
+    let mut operations = Vec::new();
+    if let Some(prior) = store.get_prior(from)? {
+        operations.push(Operation::SetPrior {
+            name: to.to_owned(),
+            prior,
+        });
+    }
+    if let Some(history) = store.get_history(from)? {
+        operations.push(Operation::SetHistory {
+            name: to.to_owned(),
+            history,
+        });
+    }
+    for kind in HYPOTHESIS_METADATA {
+        if let Some(value) = store.get_metadata(kind, from)? {
+            operations.push(Operation::SetMetadata {
+                kind: kind.to_owned(),
+                name: to.to_owned(),
+                value,
+            });
+        }
+    }
+    operations.extend(removal(from));
+    Ok(operations)
+}
+
+/// Interleaves two histories in the order their changes were made, renumbering the steps restored by reverts to their steps in the merged history.
+///
+/// A revert to the prior a history started from, before its first update, is renumbered to the step before that update in the merged history.
+fn merge_histories(into: &[HistoryEntry], from: &[HistoryEntry]) -> Vec<HistoryEntry> {
+    let histories = [into, from];
+    let mut order: Vec<(usize, usize)> = (0..into.len())
+        .map(|index| (0, index))
+        .chain((0..from.len()).map(|index| (1, index)))
+        .collect();
+    order.sort_by_key(|&(history, index)| histories[history][index].timestamp);
+    let mut positions = [vec![0; into.len()], vec![0; from.len()]];
+    for (position, &(history, index)) in order.iter().enumerate() {
+        positions[history][index] = position;
+    }
+    let merged_first = order.first().map_or(0, |&(history, index)| {
+        first_entry_step(&histories[history][index..=index])
+    });
+    order
+        .into_iter()
+        .map(|(history, index)| {
+            let mut entry = histories[history][index].clone();
+            if let Change::Revert { ref mut step } = entry.change {
+                let restored = *step;
+                *step = restored
+                    .checked_sub(first_entry_step(histories[history]))
+                    .map_or_else(
+                        || (positions[history][0] + merged_first).saturating_sub(1),
+                        |index| {
+                            positions[history]
+                                .get(index)
+                                .map_or(restored, |&position| position + merged_first)
+                        },
+                    );
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Combines the metadata of the same kind saved for two hypotheses: the union of their tags and saved evidence, and otherwise the metadata of the hypothesis merged into, if it has any
+fn merge_metadata(
+    kind: &str,
+    into: Option<String>,
+    from: Option<String>,
+) -> Result<Option<String>> {
+    let (Some(into), Some(from)) = (into.clone(), from.clone()) else {
+        return Ok(into.or(from));
+    };
+    if kind == TAGS_METADATA {
+        let mut tags: BTreeSet<String> = serde_json::from_str(&into)?;
+        tags.extend(serde_json::from_str::<BTreeSet<String>>(&from)?);
+        return Ok(Some(serde_json::to_string(&tags)?));
+    }
+    if kind == HYPOTHESIS_EVIDENCE_METADATA {
+        let mut evidence: Map<String, Value> = serde_json::from_str(&from)?;
+        evidence.extend(serde_json::from_str::<Map<String, Value>>(&into)?);
+        return Ok(Some(serde_json::to_string(&evidence)?));
+    }
+    Ok(Some(into))
+}
+
+/// The prior of the merged hypothesis and the operations merging one hypothesis into another, removing the hypothesis merged from.
+///
+/// The hypothesis merged into keeps its prior, or takes the prior of the other if it has none, and its history gains the changes made to the other with the merge recorded as a new step.  Tags and saved evidence are combined, with the evidence of the hypothesis merged into taking precedence, and actions are kept from the hypothesis merged into if it has any.
+/// # Errors
+/// - If the names are the same
+/// - If either hypothesis has not been saved
+/// - If the store cannot be read
+fn merge_operations(store: &dyn Store, from: &str, into: &str) -> Result<(f64, Vec<Operation>)> {
+    if from == into {
+        return Err(anyhow!("{from} cannot be merged into itself"));
+    }
+    for name in [from, into] {
+        if !is_saved(store, name)? {
+            return Err(anyhow!("Could not find hypothesis {name}"));
+        }
+    }
+    let prior = store
+        .get_prior(into)?
+        .or(store.get_prior(from)?)
+        .ok_or_else(|| anyhow!("Neither {from} nor {into} has a prior probability"))?;
+    let mut history = merge_histories(
+        &store.get_history(into)?.unwrap_or_default(),
+        &store.get_history(from)?.unwrap_or_default(),
+    );
+    history.push(HistoryEntry::new(
+        prior,
+        Change::Merge {
+            from: from.to_owned(),
+        },
+    ));
+    let mut operations = vec![
+        Operation::SetPrior {
+            name: into.to_owned(),
+            prior,
+        },
+        Operation::SetHistory {
+            name: into.to_owned(),
+            history,
+        },
+    ];
+    for kind in HYPOTHESIS_METADATA {
+        let merged = merge_metadata(
+            kind,
+            store.get_metadata(kind, into)?,
+            store.get_metadata(kind, from)?,
+        )?;
+        if let Some(value) = merged {
+            operations.push(Operation::SetMetadata {
+                kind: kind.to_owned(),
+                name: into.to_owned(),
+                value,
+            });
+        }
+    }
+    operations.extend(removal(from));
+    Ok((prior, operations))
+}
+
+/// Merges one hypothesis into another in a single transaction, with the store locked so that changes made to either by other processes are not lost.  Returns the prior of the merged hypothesis.
+/// # Errors
+/// - If the hypotheses cannot be merged
+/// - If the store cannot be locked, read or written
+fn merge_hypothesis(store: &dyn Store, from: &str, into: &str) -> Result<f64> {
+    let mut merged_prior = None;
+    store.transact(&mut |store| {
+        let (prior, operations) = merge_operations(store, from, into)?;
+        merged_prior = Some(prior);
+        Ok(operations)
+    })?;
+    merged_prior.ok_or_else(|| anyhow!("{from} could not be merged into {into}"))
+}
+
+/// Renames a hypothesis, moving its prior, history, actions, tags and saved evidence to the new name
+/// # Errors
+/// - If the database cannot be opened
+/// - If the names are the same
+/// - If the hypothesis has not been saved
+/// - If a hypothesis has already been saved under the new name
+/// - If the hypothesis cannot be moved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn rename_prior(from: &str, to: &str) -> Result<()> {
+    open_store()?.transact(&mut |store| rename_operations(store, from, to))
+}
+
+/// Merges one hypothesis into another, combining their histories, tags and saved evidence and removing the hypothesis merged from.  Returns the prior of the merged hypothesis.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the names are the same
+/// - If either hypothesis has not been saved
+/// - If the hypotheses cannot be merged
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn merge_priors(from: &str, into: &str) -> Result<f64> {
+    merge_hypothesis(&*open_store()?, from, into)
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temporary_path;
+    use crate::store::SledStore;
+    use chrono::Duration;
+
+    #[test]
+    fn it_finds_near_duplicate_names() {
+        assert_eq!(edit_distance("db-overload", "db-overlaod"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        let existing = vec![
+            "db-overload".to_owned(),
+            "DB-Overload".to_owned(),
+            "cache-miss".to_owned(),
+            "rain".to_owned(),
+        ];
+        assert_eq!(
+            similar_names("db-overlaod", &existing),
+            vec!["DB-Overload".to_owned(), "db-overload".to_owned()]
+        );
+        assert_eq!(
+            similar_names("db-overload", &existing),
+            vec!["DB-Overload".to_owned()]
+        );
+        assert_eq!(similar_names("RAIN", &existing), vec!["rain".to_owned()]);
+        assert_eq!(similar_names("ruin", &existing), vec!["rain".to_owned()]);
+        assert!(similar_names("snow", &existing).is_empty());
+        assert!(similar_names("cache-hit", &existing).is_empty());
+    }
+
+    fn entry_at(minutes: i64, probability: f64, change: Change) -> Result<HistoryEntry> {
+        let mut entry = HistoryEntry::new(probability, change);
+        entry.timestamp = "2022-03-01T12:00:00Z".parse::<chrono::DateTime<chrono::Utc>>()?
+            + Duration::minutes(minutes);
+        Ok(entry)
+    }
+
+    #[test]
+    fn it_interleaves_histories_and_renumbers_reverts() -> Result<()> {
+        let into = vec![
+            entry_at(0, 0.5, Change::Set)?,
+            entry_at(2, 0.7, Change::Set)?,
+            entry_at(4, 0.5, Change::Revert { step: 0 })?,
+        ];
+        let from = vec![
+            entry_at(1, 0.2, Change::Set)?,
+            entry_at(3, 0.2, Change::Revert { step: 0 })?,
+        ];
+        let merged = merge_histories(&into, &from);
+        let probabilities: Vec<f64> = merged.iter().map(|entry| entry.probability).collect();
+        assert_eq!(probabilities, vec![0.5, 0.2, 0.7, 0.2, 0.5]);
+        assert_eq!(merged[3].change, Change::Revert { step: 1 });
+        assert_eq!(merged[4].change, Change::Revert { step: 0 });
+        Ok(())
+    }
+
+    #[test]
+    fn it_renames_and_merges_hypotheses() -> Result<()> {
+        let store = SledStore::open(&temporary_path("names"))?;
+        crate::record_change(&store, "db-overload", &entry_at(0, 0.4, Change::Set)?)?;
+        crate::record_change(&store, "db-overlaod", &entry_at(1, 0.3, Change::Set)?)?;
+        store.apply(&[
+            Operation::SetMetadata {
+                kind: TAGS_METADATA.to_owned(),
+                name: "db-overload".to_owned(),
+                value: r#"["incident"]"#.to_owned(),
+            },
+            Operation::SetMetadata {
+                kind: TAGS_METADATA.to_owned(),
+                name: "db-overlaod".to_owned(),
+                value: r#"["database"]"#.to_owned(),
+            },
+        ])?;
+
+        assert!(rename_operations(&store, "db-overlaod", "db-overload").is_err());
+        assert!(rename_operations(&store, "missing", "elsewhere").is_err());
+        store.apply(&rename_operations(&store, "db-overlaod", "db-typo")?)?;
+        assert_eq!(store.get_prior("db-overlaod")?, None);
+        assert_eq!(store.get_prior("db-typo")?, Some(0.3));
+        assert_eq!(
+            store.get_history("db-typo")?.map(|history| history.len()),
+            Some(1)
+        );
+        assert_eq!(
+            store.get_metadata(TAGS_METADATA, "db-typo")?.as_deref(),
+            Some(r#"["database"]"#)
+        );
+
+        assert!(merge_hypothesis(&store, "db-typo", "db-typo").is_err());
+        assert_eq!(merge_hypothesis(&store, "db-typo", "db-overload")?, 0.4);
+        assert!(!is_saved(&store, "db-typo")?);
+        let history = store.get_history("db-overload")?.unwrap_or_default();
+        let probabilities: Vec<f64> = history.iter().map(|entry| entry.probability).collect();
+        assert_eq!(probabilities, vec![0.4, 0.3, 0.4]);
+        assert_eq!(
+            history.last().map(|entry| entry.change.clone()),
+            Some(Change::Merge {
+                from: "db-typo".to_owned()
+            })
+        );
+        assert_eq!(
+            store.get_metadata(TAGS_METADATA, "db-overload")?.as_deref(),
+            Some(r#"["database","incident"]"#)
+        );
+        Ok(())
+    }
+}
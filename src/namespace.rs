@@ -0,0 +1,422 @@
+//! Hierarchical namespaces of hypotheses, written as path-like names such as `infra/db/overloaded`, for listing, exporting and moving whole subtrees of hypotheses at once.
+use crate::categorical::CATEGORICAL_METADATA;
+use crate::decision::ACTIONS_METADATA;
+use crate::history::HistoryEntry;
+use crate::named_evidence::HYPOTHESIS_EVIDENCE_METADATA;
+use crate::quantity::QUANTITY_METADATA;
+use crate::store::{open_store, Operation, Store};
+use crate::tags::TAGS_METADATA;
+use crate::{OutputFormat, Rounded, HYPOTHESIS_METADATA};
+use anyhow::{anyhow, Result};
+use log::info;
+use prettytable::{format, Cell, Row, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// The separator between the namespaces in the name of a hypothesis
+pub const SEPARATOR: char = '/';
+/// The kinds of metadata attached to the name of a hypothesis or quantity, which move along with the namespace the name is in
+const NAMESPACED_METADATA: [&str; 5] = [
+    ACTIONS_METADATA,
+    TAGS_METADATA,
+    HYPOTHESIS_EVIDENCE_METADATA,
+    QUANTITY_METADATA,
+    CATEGORICAL_METADATA,
+];
+
+/// The prefix shared by the names of the hypotheses in the namespace and in the namespaces nested in it, e.g. `infra/db/` for `infra/db`.  The empty namespace holds every hypothesis.
+#[inline]
+#[must_use]
+pub fn namespace_prefix(namespace: &str) -> String {
+    let namespace = namespace.trim_end_matches(SEPARATOR);
+    if namespace.is_empty() {
+        String::new()
+    } else {
+        format!("{namespace}{SEPARATOR}")
+    }
+}
+
+/// The namespaces enclosing the hypothesis, innermost first, e.g. `infra/db` and then `infra` for `infra/db/overloaded`
+#[inline]
+#[must_use]
+pub fn enclosing_namespaces(name: &str) -> Vec<&str> {
+    let mut namespaces = Vec::new();
+    let mut rest = name;
+    while let Some((namespace, _)) = rest.rsplit_once(SEPARATOR) {
+        if !namespace.is_empty() {
+            namespaces.push(namespace);
+        }
+        rest = namespace;
+    }
+    namespaces
+}
+
+/// Everything saved for a hypothesis, as it is exported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ExportedHypothesis {
+    /// The name of the hypothesis
+    pub name: String,
+    /// The prior probability of the hypothesis
+    pub probability: f64,
+    /// The metadata of the hypothesis by kind, e.g. its actions and tags
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, Value>,
+    /// The history of the hypothesis, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoryEntry>,
+}
+
+/// Reads everything saved for the hypotheses in the namespace and the namespaces nested in it
+fn collect_namespace(store: &dyn Store, namespace: &str) -> Result<Vec<ExportedHypothesis>> {
+    let mut hypotheses = Vec::new();
+    for (name, probability) in store.list_priors_in(namespace)? {
+        let mut metadata = BTreeMap::new();
+        for kind in HYPOTHESIS_METADATA {
+            if let Some(value) = store.get_metadata(kind, &name)? {
+                metadata.insert(kind.to_owned(), serde_json::from_str(&value)?);
+            }
+        }
+        let history = store.get_history(&name)?.unwrap_or_default();
+        hypotheses.push(ExportedHypothesis {
+            name,
+            probability,
+            metadata,
+            history,
+        });
+    }
+    Ok(hypotheses)
+}
+
+/// Everything saved under one name
+#[derive(Debug, Default)]
+struct SavedRecords {
+    /// The prior probability, if one has been saved
+    prior: Option<f64>,
+    /// The history, if any has been recorded
+    history: Option<Vec<HistoryEntry>>,
+    /// The metadata as kinds and JSON
+    metadata: Vec<(&'static str, String)>,
+}
+
+impl SavedRecords {
+    /// The operations saving the records under the name
+    fn operations(self, name: &str) -> Vec<Operation> {
+        let mut operations = Vec::new();
+        if let Some(prior) = self.prior {
+            operations.push(Operation::SetPrior {
+                name: name.to_owned(),
+                prior,
+            });
+        }
+        if let Some(history) = self.history {
+            operations.push(Operation::SetHistory {
+                name: name.to_owned(),
+                history,
+            });
+        }
+        operations.extend(
+            self.metadata
+                .into_iter()
+                .map(|(kind, value)| Operation::SetMetadata {
+                    kind: kind.to_owned(),
+                    name: name.to_owned(),
+                    value,
+                }),
+        );
+        operations
+    }
+}
+
+/// Reads everything saved under the names starting with the prefix, keyed by name, including the history and metadata of hypotheses and quantities without a prior
+fn records_with_prefix(store: &dyn Store, prefix: &str) -> Result<BTreeMap<String, SavedRecords>> {
+    let mut records: BTreeMap<String, SavedRecords> = BTreeMap::new();
+    for (name, prior) in store.list_priors_in(prefix)? {
+        records.entry(name).or_default().prior = Some(prior);
+    }
+    for name in store.history_names()? {
+        if name.starts_with(prefix) {
+            let history = store.get_history(&name)?;
+            records.entry(name).or_default().history = history;
+        }
+    }
+    for kind in NAMESPACED_METADATA {
+        for (name, value) in store.list_metadata(kind)? {
+            if name.starts_with(prefix) {
+                records
+                    .entry(name)
+                    .or_default()
+                    .metadata
+                    .push((kind, value));
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// The operations removing everything under the name that moves with its namespace
+fn removal(name: &str) -> Vec<Operation> {
+    let mut operations = vec![
+        Operation::RemovePrior {
+            name: name.to_owned(),
+        },
+        Operation::RemoveHistory {
+            name: name.to_owned(),
+        },
+    ];
+    operations.extend(
+        NAMESPACED_METADATA
+            .into_iter()
+            .map(|kind| Operation::RemoveMetadata {
+                kind: kind.to_owned(),
+                name: name.to_owned(),
+            }),
+    );
+    operations
+}
+
+/// Moves everything saved under the names in the namespace, and in the namespaces nested in it, into another namespace as a single transaction, with the store locked so that nothing is saved in either namespace in between.  Returns the old and new name of each hypothesis or quantity moved.
+/// # Errors
+/// - If either namespace is empty, or the namespace would be moved into itself
+/// - If nothing has been saved in the namespace
+/// - If something has already been saved under one of the new names
+/// - If the store cannot be locked, read or written
+fn move_subtree(store: &dyn Store, from: &str, to: &str) -> Result<Vec<(String, String)>> {
+    let from_prefix = namespace_prefix(from);
+    let to_prefix = namespace_prefix(to);
+    if from_prefix.is_empty() || to_prefix.is_empty() {
+        return Err(anyhow!(
+            "Hypotheses can only be moved between named namespaces"
+        ));
+    }
+    if to_prefix.starts_with(&from_prefix) || from_prefix.starts_with(&to_prefix) {
+        return Err(anyhow!(
+            "{from} cannot be moved into {to}, which overlaps it"
+        ));
+    }
+    let mut moved = Vec::new();
+    store.transact(&mut |store| {
+        let records = records_with_prefix(store, &from_prefix)?;
+        if records.is_empty() {
+            return Err(anyhow!("There are no hypotheses in {from}"));
+        }
+        let occupied = records_with_prefix(store, &to_prefix)?;
+        moved.clear();
+        let mut operations = Vec::new();
+        for (name, saved) in records {
+            let new_name = format!("{to_prefix}{}", &name[from_prefix.len()..]);
+            if occupied.contains_key(&new_name) {
+                return Err(anyhow!("Hypothesis {new_name} has already been saved"));
+            }
+            operations.extend(saved.operations(&new_name));
+            operations.extend(removal(&name));
+            moved.push((name, new_name));
+        }
+        Ok(operations)
+    })?;
+    Ok(moved)
+}
+
+/// Lists the hypotheses in the namespace, and in the namespaces nested in it, with their prior probabilities in alphabetical order.  The empty namespace lists every hypothesis.
+/// # Errors
+/// - If the database cannot be opened
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn list_namespace(namespace: &str) -> Result<Vec<(String, f64)>> {
+    open_store()?.list_priors_in(namespace)
+}
+
+/// Gets everything saved for the hypotheses in the namespace, and in the namespaces nested in it: their prior probabilities, metadata and history
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved metadata cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn export_namespace(namespace: &str) -> Result<Vec<ExportedHypothesis>> {
+    collect_namespace(&*open_store()?, namespace)
+}
+
+/// Moves every hypothesis and quantity in the namespace, and in the namespaces nested in it, into another namespace.
+///
+/// Their priors, history, actions, tags, saved evidence, quantities and category counts are moved, including those of names without a prior.  Either everything is moved, or nothing is.  Returns the old and new name of each hypothesis or quantity moved.
+/// # Errors
+/// - If the database cannot be opened
+/// - If either namespace is empty, or the namespace would be moved into itself
+/// - If nothing has been saved in the namespace
+/// - If something has already been saved under one of the new names
+/// - If the hypotheses cannot be moved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn move_namespace(from: &str, to: &str) -> Result<Vec<(String, String)>> {
+    move_subtree(&*open_store()?, from, to)
+}
+
+/// Reports the hypotheses with their prior probabilities
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_hypotheses(hypotheses: &[(String, f64)], output_format: &OutputFormat) {
+    match *output_format {
+        OutputFormat::Json => {
+            let json: Vec<Value> = hypotheses
+                .iter()
+                .map(|&(ref name, probability)| json!({ "name": name, "probability": probability }))
+                .collect();
+            info!("{}", Value::Array(json));
+        }
+        OutputFormat::Simple => {
+            for &(ref name, probability) in hypotheses {
+                info!("P({name}) = {}", Rounded(probability));
+            }
+        }
+        OutputFormat::Table | OutputFormat::Icons => {
+            if hypotheses.is_empty() {
+                info!("No hypotheses have been saved");
+                return;
+            }
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(Row::new(vec![Cell::new("Hypothesis"), Cell::new("P(H)")]));
+            for &(ref name, probability) in hypotheses {
+                table.add_row(Row::new(vec![
+                    Cell::new(name),
+                    Cell::new(&Rounded(probability).to_string()),
+                ]));
+            }
+            table.printstd();
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::history::Change;
+    use crate::record_change;
+    use crate::store::tests::temporary_path;
+    use crate::store::Operation;
+    use crate::store::SledStore;
+
+    #[test]
+    fn it_finds_the_enclosing_namespaces() {
+        assert_eq!(namespace_prefix("infra/db"), "infra/db/");
+        assert_eq!(namespace_prefix("infra/db/"), "infra/db/");
+        assert_eq!(namespace_prefix(""), "");
+        assert_eq!(
+            enclosing_namespaces("infra/db/overloaded"),
+            vec!["infra/db", "infra"]
+        );
+        assert!(enclosing_namespaces("rain").is_empty());
+    }
+
+    #[test]
+    fn it_lists_exports_and_moves_subtrees() -> Result<()> {
+        let store = SledStore::open(&temporary_path("namespace"))?;
+        for (name, prior) in [
+            ("infra/db/overloaded", 0.4),
+            ("infra/db/replica-lag", 0.2),
+            ("infra/dbx", 0.1),
+            ("infra/cache", 0.3),
+        ] {
+            record_change(&store, name, &HistoryEntry::new(prior, Change::Set))?;
+        }
+        store.apply(&[
+            Operation::SetMetadata {
+                kind: TAGS_METADATA.to_owned(),
+                name: "infra/db/overloaded".to_owned(),
+                value: r#"["incident-42"]"#.to_owned(),
+            },
+            Operation::SetMetadata {
+                kind: HYPOTHESIS_EVIDENCE_METADATA.to_owned(),
+                name: "infra/db/slow-queries".to_owned(),
+                value: r#"{"ci-flake":{"likelihood":0.3,"likelihood_null":0.1}}"#.to_owned(),
+            },
+            Operation::SetMetadata {
+                kind: QUANTITY_METADATA.to_owned(),
+                name: "infra/db/latency".to_owned(),
+                value: r#"{"unit":"ms"}"#.to_owned(),
+            },
+            Operation::SetHistory {
+                name: "infra/db/retired".to_owned(),
+                history: vec![HistoryEntry::new(0.9_f64, Change::Set)],
+            },
+        ])?;
+
+        let names = |namespace| -> Result<Vec<String>> {
+            Ok(store
+                .list_priors_in(namespace)?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect())
+        };
+        assert_eq!(
+            names("infra/db")?,
+            vec!["infra/db/overloaded", "infra/db/replica-lag"]
+        );
+        assert_eq!(names("infra")?.len(), 4);
+        assert_eq!(names("")?.len(), 4);
+
+        let exported = collect_namespace(&store, "infra/db")?;
+        assert_eq!(exported.len(), 2);
+        assert_eq!(
+            exported[0].metadata.get(TAGS_METADATA),
+            Some(&json!(["incident-42"]))
+        );
+        assert_eq!(exported[0].history.len(), 1);
+
+        assert!(move_subtree(&store, "infra/db", "infra/db/old").is_err());
+        assert!(move_subtree(&store, "storage", "archive").is_err());
+        let moved = move_subtree(&store, "infra/db", "storage/db")?;
+        assert_eq!(moved.len(), 5);
+        assert_eq!(
+            moved[1],
+            (
+                "infra/db/overloaded".to_owned(),
+                "storage/db/overloaded".to_owned()
+            )
+        );
+        assert!(names("infra/db")?.is_empty());
+        assert!(records_with_prefix(&store, "infra/db/")?.is_empty());
+        assert_eq!(
+            store
+                .get_metadata(QUANTITY_METADATA, "storage/db/latency")?
+                .as_deref(),
+            Some(r#"{"unit":"ms"}"#)
+        );
+        assert!(store
+            .get_metadata(HYPOTHESIS_EVIDENCE_METADATA, "storage/db/slow-queries")?
+            .is_some());
+        assert_eq!(
+            store
+                .get_history("storage/db/retired")?
+                .map(|history| history.len()),
+            Some(1)
+        );
+        assert_eq!(store.get_prior("storage/db/retired")?, None);
+        assert_eq!(
+            names("storage")?,
+            vec!["storage/db/overloaded", "storage/db/replica-lag"]
+        );
+        assert_eq!(
+            store
+                .get_metadata(TAGS_METADATA, "storage/db/overloaded")?
+                .as_deref(),
+            Some(r#"["incident-42"]"#)
+        );
+        store.apply(&[Operation::SetMetadata {
+            kind: QUANTITY_METADATA.to_owned(),
+            name: "archive/dbx".to_owned(),
+            value: r#"{"unit":"ms"}"#.to_owned(),
+        }])?;
+        assert!(move_subtree(&store, "infra", "archive").is_err());
+        record_change(
+            &store,
+            "archive/cache",
+            &HistoryEntry::new(0.5, Change::Set),
+        )?;
+        assert!(move_subtree(&store, "infra", "archive").is_err());
+        assert_eq!(names("infra")?, vec!["infra/cache", "infra/dbx"]);
+        Ok(())
+    }
+}
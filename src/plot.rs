@@ -0,0 +1,337 @@
+//! Charts of how the probability of a hypothesis has evolved across its updates.
+use crate::history::TrajectoryPoint;
+use anyhow::Result;
+use num_traits::ToPrimitive;
+use std::fmt::Write;
+
+/// The width of the labels on the probability axis of a terminal chart
+const AXIS_LABEL_WIDTH: usize = 5;
+/// The width of an SVG chart
+const SVG_WIDTH: f64 = 720.0_f64;
+/// The height of the plotting area of an SVG chart
+const SVG_PLOT_HEIGHT: f64 = 300.0_f64;
+/// The margin around the plotting area of an SVG chart
+const SVG_MARGIN: f64 = 50.0_f64;
+/// The height of each line of the legend of an SVG chart
+const SVG_LINE_HEIGHT: f64 = 18.0_f64;
+
+/// The characters used to draw a terminal chart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChartGlyphs {
+    /// Marks a point on the trajectory
+    point: char,
+    /// Marks the line between two points
+    line: char,
+    /// The probability axis
+    axis: char,
+    /// A labelled tick on the probability axis
+    tick: char,
+    /// The corner where the axes meet
+    corner: char,
+    /// The step axis
+    baseline: char,
+}
+
+/// Glyphs for terminals that support Unicode
+const UNICODE_GLYPHS: ChartGlyphs = ChartGlyphs {
+    point: '\u{25cf}',
+    line: '\u{b7}',
+    axis: '\u{2502}',
+    tick: '\u{2524}',
+    corner: '\u{2514}',
+    baseline: '\u{2500}',
+};
+
+/// Glyphs for terminals that only support ASCII
+const ASCII_GLYPHS: ChartGlyphs = ChartGlyphs {
+    point: '*',
+    line: '.',
+    axis: '|',
+    tick: '+',
+    corner: '+',
+    baseline: '-',
+};
+
+/// The column of each step of the trajectory on a chart `width` columns wide
+fn columns(count: usize, width: usize) -> Vec<usize> {
+    let last = count.saturating_sub(1);
+    (0..count)
+        .map(|step| {
+            (step * width.saturating_sub(1))
+                .checked_div(last)
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// The row of a probability on a chart `height` rows tall, with 1 on the top row
+fn row(probability: f64, height: usize) -> usize {
+    let last = height.saturating_sub(1);
+    ((1.0_f64 - probability.clamp(0.0_f64, 1.0_f64)) * last.to_f64().unwrap_or_default())
+        .round()
+        .to_usize()
+        .unwrap_or_default()
+        .min(last)
+}
+
+/// Renders the trajectory of the hypothesis as a line chart followed by a legend annotating each point.
+///
+/// Unicode box-drawing characters are used unless `ascii` is set.
+#[inline]
+#[must_use]
+pub fn render_chart(
+    name: &str,
+    points: &[TrajectoryPoint],
+    width: usize,
+    height: usize,
+    ascii: bool,
+) -> String {
+    let glyphs = if ascii { ASCII_GLYPHS } else { UNICODE_GLYPHS };
+    let width = width.max(2);
+    let height = height.max(3);
+    let mut grid = vec![vec![' '; width]; height];
+    let columns = columns(points.len(), width);
+    let rows = points
+        .iter()
+        .map(|point| row(point.probability, height))
+        .collect::<Vec<_>>();
+
+    for step in 1..points.len() {
+        let (start, end) = (columns[step - 1], columns[step]);
+        let (from, to) = (points[step - 1].probability, points[step].probability);
+        let mut previous_row = rows[step - 1];
+        for column in start + 1..=end {
+            let fraction = (column - start).to_f64().unwrap_or_default()
+                / (end - start).to_f64().unwrap_or(1.0_f64);
+            let current_row = row((to - from).mul_add(fraction, from), height);
+            for cells in grid
+                .iter_mut()
+                .take(previous_row.max(current_row) + 1)
+                .skip(previous_row.min(current_row))
+            {
+                cells[column] = glyphs.line;
+            }
+            previous_row = current_row;
+        }
+    }
+    for (column, row) in columns.iter().zip(&rows) {
+        grid[*row][*column] = glyphs.point;
+    }
+
+    let mut lines = vec![format!("P({name}) over {} steps", points.len())];
+    for (index, cells) in grid.iter().enumerate() {
+        let label = if index == 0 {
+            Some(1.0_f64)
+        } else if index == height - 1 {
+            Some(0.0_f64)
+        } else if index * 2 == height - 1 {
+            Some(0.5_f64)
+        } else {
+            None
+        };
+        let (label, axis) = label.map_or_else(
+            || (String::new(), glyphs.axis),
+            |label| (format!("{label:.2}"), glyphs.tick),
+        );
+        lines.push(
+            format!(
+                "{label:>AXIS_LABEL_WIDTH$} {axis}{}",
+                cells.iter().collect::<String>()
+            )
+            .trim_end()
+            .to_owned(),
+        );
+    }
+    lines.push(format!(
+        "{:>AXIS_LABEL_WIDTH$} {}{}",
+        "",
+        glyphs.corner,
+        glyphs.baseline.to_string().repeat(width)
+    ));
+
+    let mut step_labels = String::new();
+    for (step, column) in columns.iter().enumerate() {
+        let label = step.to_string();
+        let position = AXIS_LABEL_WIDTH + 2 + column;
+        let length = step_labels.chars().count();
+        if position > length || step == 0 {
+            step_labels.push_str(&" ".repeat(position.saturating_sub(length)));
+            step_labels.push_str(&label);
+        }
+    }
+    lines.push(step_labels);
+    lines.push(String::new());
+    lines.extend(points.iter().enumerate().map(|(step, point)| {
+        let timestamp = point.timestamp.map_or_else(
+            || "-".to_owned(),
+            |timestamp| timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        );
+        format!(
+            "{step:>3}  {timestamp:<23}  {:<20}  {}",
+            point.probability, point.annotation
+        )
+    }));
+    lines.join("\n")
+}
+
+/// Escapes text for inclusion in an SVG or HTML document
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The vertical position of a probability on an SVG chart
+fn svg_y(probability: f64) -> f64 {
+    (1.0_f64 - probability.clamp(0.0_f64, 1.0_f64)).mul_add(SVG_PLOT_HEIGHT, SVG_MARGIN)
+}
+
+/// Renders the trajectory of the hypothesis as a standalone SVG document, annotating each point with the change that caused it.
+/// # Errors
+/// - If the document cannot be written
+#[inline]
+pub fn render_svg(name: &str, points: &[TrajectoryPoint]) -> Result<String> {
+    let plot_width = SVG_MARGIN.mul_add(-2.0_f64, SVG_WIDTH);
+    let last = points.len().saturating_sub(1).to_f64().unwrap_or_default();
+    let coordinates = points
+        .iter()
+        .enumerate()
+        .map(|(step, point)| {
+            let x = if last > 0.0_f64 {
+                (step.to_f64().unwrap_or_default() / last).mul_add(plot_width, SVG_MARGIN)
+            } else {
+                SVG_MARGIN
+            };
+            (x, svg_y(point.probability))
+        })
+        .collect::<Vec<_>>();
+    let legend_top = SVG_MARGIN.mul_add(2.0_f64, SVG_PLOT_HEIGHT);
+    let height = points
+        .len()
+        .to_f64()
+        .unwrap_or_default()
+        .mul_add(SVG_LINE_HEIGHT, legend_top)
+        + SVG_MARGIN;
+    let title = escape_xml(&format!("P({name}) over {} steps", points.len()));
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{SVG_WIDTH}" height="{height}" viewBox="0 0 {SVG_WIDTH} {height}" font-family="sans-serif" font-size="12">"#
+    )?;
+    writeln!(svg, "<title>{title}</title>")?;
+    writeln!(
+        svg,
+        r#"<rect width="{SVG_WIDTH}" height="{height}" fill="white"/>"#
+    )?;
+    writeln!(
+        svg,
+        r#"<text x="{SVG_MARGIN}" y="{}" font-size="16">{title}</text>"#,
+        SVG_MARGIN / 2.0_f64
+    )?;
+    for tick in [0.0_f64, 0.25_f64, 0.5_f64, 0.75_f64, 1.0_f64] {
+        let y = svg_y(tick);
+        writeln!(
+            svg,
+            r##"<line x1="{SVG_MARGIN}" y1="{y}" x2="{}" y2="{y}" stroke="#ddd"/>"##,
+            SVG_MARGIN + plot_width
+        )?;
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end">{tick:.2}</text>"#,
+            SVG_MARGIN - 6.0_f64,
+            y + 4.0_f64
+        )?;
+    }
+    let polyline = coordinates
+        .iter()
+        .map(|(x, y)| format!("{x:.1},{y:.1}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    writeln!(
+        svg,
+        r##"<polyline points="{polyline}" fill="none" stroke="#1f77b4" stroke-width="2"/>"##
+    )?;
+    for (step, ((x, y), point)) in coordinates.iter().zip(points).enumerate() {
+        let annotation = escape_xml(&point.annotation);
+        writeln!(
+            svg,
+            r##"<circle cx="{x:.1}" cy="{y:.1}" r="4" fill="#1f77b4"><title>{step}: {} ({annotation})</title></circle>"##,
+            point.probability
+        )?;
+        writeln!(
+            svg,
+            r#"<text x="{x:.1}" y="{:.1}" text-anchor="middle">{step}</text>"#,
+            y - 8.0_f64
+        )?;
+        let timestamp = point.timestamp.map_or_else(
+            || "-".to_owned(),
+            |timestamp| timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        );
+        writeln!(
+            svg,
+            r#"<text x="{SVG_MARGIN}" y="{:.1}">{step}: {timestamp}  P = {}  {annotation}</text>"#,
+            step.to_f64()
+                .unwrap_or_default()
+                .mul_add(SVG_LINE_HEIGHT, legend_top),
+            point.probability
+        )?;
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    fn point(probability: f64, annotation: &str) -> TrajectoryPoint {
+        TrajectoryPoint {
+            timestamp: None,
+            probability,
+            annotation: annotation.to_owned(),
+        }
+    }
+
+    #[test]
+    fn it_places_points_on_the_chart() {
+        assert_eq!(columns(3, 21), vec![0, 10, 20]);
+        assert_eq!(columns(1, 21), vec![0]);
+        assert_eq!(row(1.0_f64, 11), 0);
+        assert_eq!(row(0.5_f64, 11), 5);
+        assert_eq!(row(0.0_f64, 11), 10);
+    }
+
+    #[test]
+    fn it_renders_an_ascii_chart() {
+        let points = [point(0.5_f64, "set"), point(1.0_f64, "evidence observed")];
+        let chart = render_chart("rain", &points, 11, 11, true);
+        let lines = chart.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "P(rain) over 2 steps");
+        assert_eq!(lines[1], " 1.00 +          *");
+        assert_eq!(lines[6], " 0.50 +*..");
+        assert_eq!(lines[11], " 0.00 +");
+        assert_eq!(lines[12], "      +-----------");
+        assert_eq!(lines[13], "       0         1");
+        assert!(lines[15].contains("0.5") && lines[15].ends_with("set"));
+        assert!(chart.is_ascii());
+    }
+
+    #[test]
+    fn it_renders_an_svg_chart() -> Result<()> {
+        let points = [
+            point(0.5_f64, "set"),
+            point(0.8_f64, "P(E|H) > P(E|\u{ac}H)"),
+        ];
+        let svg = render_svg("a<b", &points)?;
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(svg.contains("P(a&lt;b) over 2 steps"));
+        assert!(svg.contains("P(E|H) &gt; P(E|\u{ac}H)"));
+        assert!(svg.contains(r#"<polyline points="50.0,200.0 670.0,110.0""#));
+        assert_eq!(svg.matches("<circle").count(), 2);
+        Ok(())
+    }
+}
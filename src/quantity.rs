@@ -0,0 +1,799 @@
+//! Beliefs about continuous quantities, such as latencies and costs, held as Gaussian conjugate priors and updated with measurements.
+//!
+//! The measurements scatter normally around the quantity, with a spread that is either known in advance or learned along with the quantity.
+use crate::store::{collect_records, find_record, open_store, remove_record, set_record, Store};
+use crate::{OutputFormat, Rounded};
+use anyhow::{anyhow, Error, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use prettytable::{format, Cell, Row, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use statrs::distribution::{ContinuousCDF, Normal, StudentsT};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The kind of metadata holding the beliefs about quantities, keyed by the name of the quantity
+pub(crate) const QUANTITY_METADATA: &str = "quantity";
+
+/// How the measurements of a quantity are modelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GaussianModel {
+    /// Measurements are normally distributed around the quantity with a known standard deviation
+    KnownVariance,
+    /// Measurements are normally distributed around the quantity with a standard deviation that is also learned from them
+    NormalInverseGamma,
+}
+
+impl FromStr for GaussianModel {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "known-variance" | "known" | "normal" => Ok(Self::KnownVariance),
+            "normal-inverse-gamma" | "nig" => Ok(Self::NormalInverseGamma),
+            _ => Err(anyhow!("Invalid model: {}", s)),
+        }
+    }
+}
+
+impl Display for GaussianModel {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::KnownVariance => write!(f, "known-variance"),
+            Self::NormalInverseGamma => write!(f, "normal-inverse-gamma"),
+        }
+    }
+}
+
+/// A belief about a quantity μ, measured with normally distributed noise
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum GaussianBelief {
+    /// A normal belief about μ, with measurements of known variance σ²
+    KnownVariance {
+        /// The mean of the belief about μ
+        mean: f64,
+        /// The variance of the belief about μ
+        variance: f64,
+        /// The known variance of the measurements σ²
+        noise_variance: f64,
+    },
+    /// A normal-inverse-gamma belief about μ and the variance of the measurements σ², where μ | σ² ~ N(mean, σ² / weight) and σ² ~ Inv-Gamma(shape, scale)
+    NormalInverseGamma {
+        /// The mean of the belief about μ
+        mean: f64,
+        /// How many measurements the belief about μ is worth
+        weight: f64,
+        /// The shape of the belief about σ², half the number of measurements it is worth
+        shape: f64,
+        /// The scale of the belief about σ²
+        scale: f64,
+    },
+}
+
+/// Checks that a standard deviation is a positive number
+fn validate_standard_deviation(label: &str, value: f64) -> Result<()> {
+    if !value.is_finite() || value <= 0.0_f64 {
+        return Err(anyhow!("{label} must be a number above 0"));
+    }
+    Ok(())
+}
+
+impl GaussianBelief {
+    /// Creates a prior belief about a quantity estimated at `mean`, give or take `standard_deviation`, from the model of its measurements.
+    ///
+    /// With a known variance, the measurements vary by exactly `noise_standard_deviation`.  With a normal-inverse-gamma prior, `noise_standard_deviation` is a guess at how much they vary, worth `noise_weight` measurements.
+    /// # Errors
+    /// - If the mean is not a finite number
+    /// - If a standard deviation or the weight is not a number above 0
+    #[inline]
+    pub fn new(
+        model: GaussianModel,
+        mean: f64,
+        standard_deviation: f64,
+        noise_standard_deviation: f64,
+        noise_weight: f64,
+    ) -> Result<Self> {
+        if !mean.is_finite() {
+            return Err(anyhow!("The mean must be a finite number"));
+        }
+        validate_standard_deviation("The standard deviation", standard_deviation)?;
+        validate_standard_deviation(
+            "The standard deviation of the measurements",
+            noise_standard_deviation,
+        )?;
+        let noise_variance = noise_standard_deviation.powi(2);
+        match model {
+            GaussianModel::KnownVariance => Ok(Self::KnownVariance {
+                mean,
+                variance: standard_deviation.powi(2),
+                noise_variance,
+            }),
+            GaussianModel::NormalInverseGamma => {
+                validate_standard_deviation("The weight of the measurements", noise_weight)?;
+                Ok(Self::NormalInverseGamma {
+                    mean,
+                    weight: noise_variance / standard_deviation.powi(2),
+                    shape: noise_weight / 2.0_f64,
+                    scale: noise_weight * noise_variance / 2.0_f64,
+                })
+            }
+        }
+    }
+
+    /// The model of the measurements
+    #[inline]
+    #[must_use]
+    pub const fn model(&self) -> GaussianModel {
+        match *self {
+            Self::KnownVariance { .. } => GaussianModel::KnownVariance,
+            Self::NormalInverseGamma { .. } => GaussianModel::NormalInverseGamma,
+        }
+    }
+
+    /// The belief after the measurements, by the conjugate update of the model
+    /// # Errors
+    /// - If there are no measurements
+    /// - If any measurement is not a finite number
+    #[inline]
+    pub fn update(&self, measurements: &[f64]) -> Result<Self> {
+        if measurements.is_empty() {
+            return Err(anyhow!("There are no measurements to update with"));
+        }
+        if measurements
+            .iter()
+            .any(|measurement| !measurement.is_finite())
+        {
+            return Err(anyhow!("Measurements must be finite numbers"));
+        }
+        let count = measurements.iter().map(|_| 1.0_f64).sum::<f64>();
+        let sample_mean = measurements.iter().sum::<f64>() / count;
+        match *self {
+            Self::KnownVariance {
+                mean,
+                variance,
+                noise_variance,
+            } => {
+                let precision = variance.recip() + count / noise_variance;
+                Ok(Self::KnownVariance {
+                    mean: (mean / variance + count * sample_mean / noise_variance) / precision,
+                    variance: precision.recip(),
+                    noise_variance,
+                })
+            }
+            Self::NormalInverseGamma {
+                mean,
+                weight,
+                shape,
+                scale,
+            } => {
+                let sum_of_squares = measurements
+                    .iter()
+                    .map(|measurement| (measurement - sample_mean).powi(2))
+                    .sum::<f64>();
+                let updated_weight = weight + count;
+                Ok(Self::NormalInverseGamma {
+                    mean: weight.mul_add(mean, count * sample_mean) / updated_weight,
+                    weight: updated_weight,
+                    shape: shape + count / 2.0_f64,
+                    scale: scale
+                        + sum_of_squares / 2.0_f64
+                        + weight * count * (sample_mean - mean).powi(2)
+                            / (2.0_f64 * updated_weight),
+                })
+            }
+        }
+    }
+
+    /// The expected value of the quantity
+    #[inline]
+    #[must_use]
+    pub const fn mean(&self) -> f64 {
+        match *self {
+            Self::KnownVariance { mean, .. } | Self::NormalInverseGamma { mean, .. } => mean,
+        }
+    }
+
+    /// The standard deviation of the belief about the quantity.  Undefined for a normal-inverse-gamma belief worth two measurements or fewer of the variance.
+    #[inline]
+    #[must_use]
+    pub fn standard_deviation(&self) -> Option<f64> {
+        match *self {
+            Self::KnownVariance { variance, .. } => Some(variance.sqrt()),
+            Self::NormalInverseGamma {
+                weight,
+                shape,
+                scale,
+                ..
+            } => (shape > 1.0_f64).then(|| (scale / ((shape - 1.0_f64) * weight)).sqrt()),
+        }
+    }
+
+    /// The standard deviation of the measurements: known, or the square root of the expected variance.  Undefined for a normal-inverse-gamma belief worth two measurements or fewer of the variance.
+    #[inline]
+    #[must_use]
+    pub fn noise_standard_deviation(&self) -> Option<f64> {
+        match *self {
+            Self::KnownVariance { noise_variance, .. } => Some(noise_variance.sqrt()),
+            Self::NormalInverseGamma { shape, scale, .. } => {
+                (shape > 1.0_f64).then(|| (scale / (shape - 1.0_f64)).sqrt())
+            }
+        }
+    }
+
+    /// The central interval holding the quantity with probability `level`
+    /// # Errors
+    /// - If the level is not between 0 and 1
+    #[inline]
+    pub fn credible_interval(&self, level: f64) -> Result<(f64, f64)> {
+        match *self {
+            Self::KnownVariance { mean, variance, .. } => {
+                normal_interval(mean, variance.sqrt(), level)
+            }
+            Self::NormalInverseGamma {
+                mean,
+                weight,
+                shape,
+                scale,
+            } => student_interval(
+                mean,
+                (scale / (shape * weight)).sqrt(),
+                2.0_f64 * shape,
+                level,
+            ),
+        }
+    }
+
+    /// The central interval holding the next measurement with probability `level`
+    /// # Errors
+    /// - If the level is not between 0 and 1
+    #[inline]
+    pub fn predictive_interval(&self, level: f64) -> Result<(f64, f64)> {
+        match *self {
+            Self::KnownVariance {
+                mean,
+                variance,
+                noise_variance,
+            } => normal_interval(mean, (variance + noise_variance).sqrt(), level),
+            Self::NormalInverseGamma {
+                mean,
+                weight,
+                shape,
+                scale,
+            } => student_interval(
+                mean,
+                (scale * (weight + 1.0_f64) / (shape * weight)).sqrt(),
+                2.0_f64 * shape,
+                level,
+            ),
+        }
+    }
+}
+
+/// Checks that a credible level is strictly between 0 and 1
+fn validate_level(level: f64) -> Result<()> {
+    if !(level > 0.0_f64 && level < 1.0_f64) {
+        return Err(anyhow!("The credible level must be between 0 and 1"));
+    }
+    Ok(())
+}
+
+/// The central interval of a normal distribution holding probability `level`
+fn normal_interval(mean: f64, standard_deviation: f64, level: f64) -> Result<(f64, f64)> {
+    validate_level(level)?;
+    let distribution = Normal::new(mean, standard_deviation)?;
+    let tail = (1.0_f64 - level) / 2.0_f64;
+    Ok((
+        distribution.inverse_cdf(tail),
+        distribution.inverse_cdf(1.0_f64 - tail),
+    ))
+}
+
+/// The central interval of a Student's t distribution holding probability `level`
+fn student_interval(location: f64, scale: f64, freedom: f64, level: f64) -> Result<(f64, f64)> {
+    validate_level(level)?;
+    let distribution = StudentsT::new(location, scale, freedom)?;
+    let tail = (1.0_f64 - level) / 2.0_f64;
+    Ok((
+        distribution.inverse_cdf(tail),
+        distribution.inverse_cdf(1.0_f64 - tail),
+    ))
+}
+
+/// A change to the belief about a quantity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct QuantityEntry {
+    /// When the change was made
+    pub timestamp: DateTime<Utc>,
+    /// The belief after the change
+    pub belief: GaussianBelief,
+    /// The measurements the belief was updated with, or none if it was set directly
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub measurements: Vec<f64>,
+}
+
+/// The saved belief about a quantity, with its history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Quantity {
+    /// The current belief about the quantity
+    pub belief: GaussianBelief,
+    /// The unit the quantity is measured in, e.g. `ms`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// The changes to the belief, oldest first
+    #[serde(default)]
+    pub history: Vec<QuantityEntry>,
+}
+
+impl Quantity {
+    /// The number of measurements the belief has been updated with since it was last set
+    #[inline]
+    #[must_use]
+    pub fn measurement_count(&self) -> usize {
+        self.history
+            .iter()
+            .rev()
+            .take_while(|entry| !entry.measurements.is_empty())
+            .map(|entry| entry.measurements.len())
+            .sum()
+    }
+
+    /// Formats a value of the quantity with its unit
+    fn with_unit(&self, value: f64) -> String {
+        self.unit.as_ref().map_or_else(
+            || Rounded(value).to_string(),
+            |unit| format!("{} {unit}", Rounded(value)),
+        )
+    }
+}
+
+/// Parses measurements from CSV: the first column, or the column with the header, skipping blank lines
+///
+/// The first row is a header only when none of its fields is a number, so a malformed first measurement is an error rather than a skipped header.
+/// # Errors
+/// - If the column is named but there is no header row with that column
+/// - If a row has no value in the column, or the value is not a finite number
+#[inline]
+pub fn parse_measurements_csv(contents: &str, column: Option<&str>) -> Result<Vec<f64>> {
+    let unquote = |field: &str| field.trim().trim_matches('"').to_owned();
+    let mut rows = contents
+        .lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty())
+        .peekable();
+    let header = rows
+        .peek()
+        .map(|&(_, line)| line.split(',').map(unquote).collect::<Vec<_>>())
+        .filter(|fields| fields.iter().all(|field| field.parse::<f64>().is_err()));
+    if header.is_some() {
+        rows.next();
+    }
+    let index = match column {
+        Some(column) => header
+            .as_ref()
+            .and_then(|fields| fields.iter().position(|field| field == column))
+            .ok_or_else(|| anyhow!("There is no column named {column}"))?,
+        None => 0,
+    };
+    let mut measurements = Vec::new();
+    for (number, line) in rows {
+        let field = line
+            .split(',')
+            .nth(index)
+            .map(unquote)
+            .ok_or_else(|| anyhow!("Line {} has no value in the column", number + 1))?;
+        let measurement = field
+            .parse::<f64>()
+            .ok()
+            .filter(|measurement| measurement.is_finite())
+            .ok_or_else(|| anyhow!("Line {} is not a number: {field}", number + 1))?;
+        measurements.push(measurement);
+    }
+    Ok(measurements)
+}
+
+/// Reads the quantity from the store
+fn find_quantity(store: &dyn Store, name: &str) -> Result<Quantity> {
+    find_record(store, QUANTITY_METADATA, name)?
+        .ok_or_else(|| anyhow!("Could not find quantity {name}"))
+}
+
+/// Saves the belief about the quantity, appending the change to the history of any quantity saved before.  The unit is kept if none is given.
+fn save_quantity(
+    store: &dyn Store,
+    name: &str,
+    belief: GaussianBelief,
+    unit: Option<&str>,
+) -> Result<Quantity> {
+    let mut saved_quantity = None;
+    store.transact(&mut |store| {
+        let (mut history, saved_unit) = find_record::<Quantity>(store, QUANTITY_METADATA, name)?
+            .map_or_else(|| (Vec::new(), None), |saved| (saved.history, saved.unit));
+        history.push(QuantityEntry {
+            timestamp: Utc::now(),
+            belief,
+            measurements: Vec::new(),
+        });
+        let quantity = Quantity {
+            belief,
+            unit: unit.map(str::to_owned).or(saved_unit),
+            history,
+        };
+        let operation = set_record(QUANTITY_METADATA, name, &quantity)?;
+        saved_quantity = Some(quantity);
+        Ok(vec![operation])
+    })?;
+    saved_quantity.ok_or_else(|| anyhow!("Could not save quantity {name}"))
+}
+
+/// Updates the belief about the quantity with the measurements, recording the update in its history
+fn record_measurements(store: &dyn Store, name: &str, measurements: &[f64]) -> Result<Quantity> {
+    let mut updated_quantity = None;
+    store.transact(&mut |store| {
+        let mut quantity = find_quantity(store, name)?;
+        quantity.belief = quantity.belief.update(measurements)?;
+        quantity.history.push(QuantityEntry {
+            timestamp: Utc::now(),
+            belief: quantity.belief,
+            measurements: measurements.to_vec(),
+        });
+        let operation = set_record(QUANTITY_METADATA, name, &quantity)?;
+        updated_quantity = Some(quantity);
+        Ok(vec![operation])
+    })?;
+    updated_quantity.ok_or_else(|| anyhow!("Could not update quantity {name}"))
+}
+
+/// Removes the quantity from the store
+fn delete_quantity(store: &dyn Store, name: &str) -> Result<()> {
+    store.transact(&mut |store| {
+        find_quantity(store, name)?;
+        Ok(vec![remove_record(QUANTITY_METADATA, name)])
+    })
+}
+
+/// Sets the prior belief about the quantity, saving it to the database.  The history of a quantity saved before is kept, with the new prior appended to it, and so is its unit if none is given.
+/// # Errors
+/// - If the database cannot be opened
+/// - If the quantity cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn set_quantity(name: &str, belief: GaussianBelief, unit: Option<&str>) -> Result<Quantity> {
+    save_quantity(&*open_store()?, name, belief, unit)
+}
+
+/// Updates the belief about the quantity with the measurements, saving it to the database and recording the update in its history
+/// # Errors
+/// - If the database cannot be opened
+/// - If the quantity has not been saved
+/// - If there are no measurements, or any is not a finite number
+/// - If the quantity cannot be saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn update_quantity(name: &str, measurements: &[f64]) -> Result<Quantity> {
+    record_measurements(&*open_store()?, name, measurements)
+}
+
+/// Gets the saved belief about the quantity
+/// # Errors
+/// - If the database cannot be opened
+/// - If the quantity has not been saved
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn get_quantity(name: &str) -> Result<Quantity> {
+    find_quantity(&*open_store()?, name)
+}
+
+/// Removes the quantity and its history from the database
+/// # Errors
+/// - If the database cannot be opened
+/// - If the quantity has not been saved
+/// - If the quantity cannot be removed
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn remove_quantity(name: &str) -> Result<()> {
+    delete_quantity(&*open_store()?, name)
+}
+
+/// Lists the saved quantities in alphabetical order
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved quantities cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn list_quantities() -> Result<Vec<(String, Quantity)>> {
+    collect_records(&*open_store()?, QUANTITY_METADATA)
+}
+
+/// Reports the belief about the quantity: its mean and standard deviation, and the intervals holding it and the next measurement with probability `level`
+/// # Errors
+/// - If the level is not between 0 and 1
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_quantity(
+    name: &str,
+    quantity: &Quantity,
+    level: f64,
+    output_format: &OutputFormat,
+) -> Result<()> {
+    let belief = &quantity.belief;
+    let (low, high) = belief.credible_interval(level)?;
+    let (predicted_low, predicted_high) = belief.predictive_interval(level)?;
+    let percentage = format!("{}%", Rounded(level * 100.0_f64));
+    let optional = |value: Option<f64>| {
+        value.map_or_else(|| "undefined".to_owned(), |value| quantity.with_unit(value))
+    };
+    match *output_format {
+        OutputFormat::Json => {
+            let json = json!({
+                "name": name,
+                "model": belief.model().to_string(),
+                "unit": quantity.unit,
+                "measurements": quantity.measurement_count(),
+                "mean": belief.mean(),
+                "standard_deviation": belief.standard_deviation(),
+                "noise_standard_deviation": belief.noise_standard_deviation(),
+                "level": level,
+                "credible_interval": [low, high],
+                "predictive_interval": [predicted_low, predicted_high],
+            });
+            info!("{}", json.to_string());
+        }
+        OutputFormat::Simple => {
+            let output = format!(
+                "
+                E[{name}] = {}
+                SD[{name}] = {}
+                {percentage} credible interval = [{}, {}]
+                SD of measurements = {}
+                {percentage} predictive interval = [{}, {}]
+                ",
+                quantity.with_unit(belief.mean()),
+                optional(belief.standard_deviation()),
+                quantity.with_unit(low),
+                quantity.with_unit(high),
+                optional(belief.noise_standard_deviation()),
+                quantity.with_unit(predicted_low),
+                quantity.with_unit(predicted_high),
+            );
+            info!("{output}");
+        }
+        OutputFormat::Table | OutputFormat::Icons => {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(Row::new(vec![
+                Cell::new("Name"),
+                Cell::new("Quantity"),
+                Cell::new("Value"),
+            ]));
+            let rows = [
+                ("Model", String::new(), belief.model().to_string()),
+                (
+                    "Measurements",
+                    "n".to_owned(),
+                    quantity.measurement_count().to_string(),
+                ),
+                (
+                    "Posterior Mean",
+                    format!("E[{name}]"),
+                    quantity.with_unit(belief.mean()),
+                ),
+                (
+                    "Standard Deviation",
+                    format!("SD[{name}]"),
+                    optional(belief.standard_deviation()),
+                ),
+                (
+                    "Credible Interval",
+                    format!("{percentage} of {name}"),
+                    format!(
+                        "[{}, {}]",
+                        quantity.with_unit(low),
+                        quantity.with_unit(high)
+                    ),
+                ),
+                (
+                    "Measurement Noise",
+                    "\u{3c3}".to_owned(),
+                    optional(belief.noise_standard_deviation()),
+                ),
+                (
+                    "Predictive Interval",
+                    format!("{percentage} of the next measurement"),
+                    format!(
+                        "[{}, {}]",
+                        quantity.with_unit(predicted_low),
+                        quantity.with_unit(predicted_high)
+                    ),
+                ),
+            ];
+            for (label, symbol, value) in rows {
+                table.add_row(Row::new(vec![
+                    Cell::new(label),
+                    Cell::new(&symbol),
+                    Cell::new(&value),
+                ]));
+            }
+            table.printstd();
+        }
+    }
+    Ok(())
+}
+
+/// Reports the quantities with their means and standard deviations
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_quantities(quantities: &[(String, Quantity)]) {
+    if quantities.is_empty() {
+        info!("No quantities have been saved");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("Quantity"),
+        Cell::new("Model"),
+        Cell::new("Mean"),
+        Cell::new("SD"),
+        Cell::new("Measurements"),
+    ]));
+    for (name, quantity) in quantities {
+        table.add_row(Row::new(vec![
+            Cell::new(name),
+            Cell::new(&quantity.belief.model().to_string()),
+            Cell::new(&quantity.with_unit(quantity.belief.mean())),
+            Cell::new(&quantity.belief.standard_deviation().map_or_else(
+                || "undefined".to_owned(),
+                |standard_deviation| quantity.with_unit(standard_deviation),
+            )),
+            Cell::new(&quantity.measurement_count().to_string()),
+        ]));
+    }
+    table.printstd();
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temporary_path;
+    use crate::store::SledStore;
+    use anyhow::bail;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn it_updates_a_normal_belief_with_known_variance() -> Result<()> {
+        let prior = GaussianBelief::new(GaussianModel::KnownVariance, 100.0, 20.0, 10.0, 1.0)?;
+        let posterior = prior.update(&[110.0, 130.0, 120.0, 120.0])?;
+        // Precision 1/400 + 4/100 = 17/400, mean (100/400 + 480/100) / (17/400) = 2020/17
+        assert!(close(posterior.mean(), 2020.0 / 17.0));
+        assert!(close(
+            posterior.standard_deviation().unwrap_or_default(),
+            (400.0_f64 / 17.0).sqrt()
+        ));
+        let (low, high) = posterior.credible_interval(0.95)?;
+        assert!(close(
+            high - posterior.mean(),
+            1.959_963_984_540_054 * (400.0_f64 / 17.0).sqrt()
+        ));
+        assert!(close(posterior.mean() - low, high - posterior.mean()));
+        let (predicted_low, predicted_high) = posterior.predictive_interval(0.95)?;
+        assert!(predicted_low < low && predicted_high > high);
+        assert!(prior.update(&[]).is_err());
+        assert!(prior.credible_interval(1.0).is_err());
+        assert!(GaussianBelief::new(GaussianModel::KnownVariance, 100.0, 0.0, 10.0, 1.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_learns_the_variance_with_a_normal_inverse_gamma_belief() -> Result<()> {
+        let prior = GaussianBelief::new(GaussianModel::NormalInverseGamma, 0.0, 10.0, 10.0, 2.0)?;
+        assert_eq!(
+            prior,
+            GaussianBelief::NormalInverseGamma {
+                mean: 0.0,
+                weight: 1.0,
+                shape: 1.0,
+                scale: 100.0,
+            }
+        );
+        assert_eq!(prior.standard_deviation(), None);
+        let posterior = prior.update(&[2.0, 4.0, 6.0])?;
+        let GaussianBelief::NormalInverseGamma {
+            mean,
+            weight,
+            shape,
+            scale,
+        } = posterior
+        else {
+            bail!("The model changed");
+        };
+        assert!(close(mean, 3.0));
+        assert!(close(weight, 4.0));
+        assert!(close(shape, 2.5));
+        // 100 + 8 / 2 + 1 * 3 * 16 / 8
+        assert!(close(scale, 110.0));
+        assert!(close(
+            posterior.standard_deviation().unwrap_or_default(),
+            (110.0_f64 / 6.0).sqrt()
+        ));
+        assert!(close(
+            posterior.noise_standard_deviation().unwrap_or_default(),
+            (110.0_f64 / 1.5).sqrt()
+        ));
+        let (low, high) = posterior.credible_interval(0.9)?;
+        assert!(close(mean - low, high - mean));
+        // The t distribution with 5 degrees of freedom has a 95th percentile of 2.015
+        assert!((high - mean - 2.015_048 * (110.0_f64 / 10.0).sqrt()).abs() < 1e-4);
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_measurements_from_csv() -> Result<()> {
+        assert_eq!(
+            parse_measurements_csv("12.5\n\n-3\n1e2\n", None)?,
+            vec![12.5, -3.0, 100.0]
+        );
+        let csv = "host,\"latency\"\nweb-1,120\nweb-2,\"135.5\"\n";
+        assert_eq!(
+            parse_measurements_csv(csv, Some("latency"))?,
+            vec![120.0, 135.5]
+        );
+        assert!(parse_measurements_csv(csv, None).is_err());
+        assert!(parse_measurements_csv(csv, Some("cost")).is_err());
+        assert!(parse_measurements_csv("1\n2\n", Some("latency")).is_err());
+        let error = |contents| {
+            parse_measurements_csv(contents, None)
+                .err()
+                .map(|error| error.to_string())
+        };
+        assert_eq!(
+            error("twelve,5\n13\n").as_deref(),
+            Some("Line 1 is not a number: twelve")
+        );
+        assert_eq!(
+            error("latency\n12\n\nn/a\n").as_deref(),
+            Some("Line 4 is not a number: n/a")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_the_history_and_unit_when_a_quantity_is_set_again() -> Result<()> {
+        let store = SledStore::open(&temporary_path("quantity"))?;
+        let prior = GaussianBelief::new(GaussianModel::KnownVariance, 100.0, 20.0, 10.0, 1.0)?;
+        save_quantity(&store, "latency", prior, Some("ms"))?;
+        assert!(record_measurements(&store, "cost", &[1.0]).is_err());
+        let quantity = record_measurements(&store, "latency", &[110.0, 130.0])?;
+        assert_eq!(quantity.measurement_count(), 2);
+        assert!(record_measurements(&store, "latency", &[f64::NAN]).is_err());
+        let saved = find_quantity(&store, "latency")?;
+        assert!(close(saved.belief.mean(), quantity.belief.mean()));
+        assert_eq!(saved.measurement_count(), 2);
+
+        let learned = GaussianBelief::new(GaussianModel::NormalInverseGamma, 0.0, 10.0, 10.0, 2.0)?;
+        let reset = save_quantity(&store, "latency", learned, None)?;
+        assert_eq!(reset.belief, learned);
+        assert_eq!(reset.unit.as_deref(), Some("ms"));
+        assert_eq!(reset.measurement_count(), 0);
+        let measured: Vec<usize> = reset
+            .history
+            .iter()
+            .map(|entry| entry.measurements.len())
+            .collect();
+        assert_eq!(measured, vec![0, 2, 0]);
+        delete_quantity(&store, "latency")?;
+        assert!(delete_quantity(&store, "latency").is_err());
+        Ok(())
+    }
+}
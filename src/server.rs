@@ -0,0 +1,532 @@
+//! A local HTTP server exposing hypotheses and updates as a JSON API.
+use crate::history::{find_history, Change, HistoryEntry};
+use crate::store::{open_store, Store};
+use crate::{
+    append_to_history, calculate_posterior_probability, list_hypotheses, remove_prior, set_prior,
+    validate_likelihoods_and_prior, validate_probability, Evidence, Observation,
+};
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt::{Debug, Formatter};
+use std::io::Read;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// The largest request body the server will read, in bytes
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// The body of a request to calculate a posterior probability
+#[derive(Debug, Deserialize)]
+struct PosteriorRequest {
+    /// The prior probability of the hypothesis P(H)
+    prior: f64,
+    /// The likelihood of the evidence P(E|H)
+    likelihood: f64,
+    /// The likelihood of the evidence P(E|¬H)
+    likelihood_null: f64,
+    /// Whether or not the evidence was observed
+    #[serde(default = "observed")]
+    evidence: String,
+}
+
+/// The body of a request to set the prior probability of a hypothesis
+#[derive(Debug, Deserialize)]
+struct SetPriorRequest {
+    /// The new prior probability of the hypothesis P(H)
+    probability: f64,
+}
+
+/// The body of a request to apply evidence to a saved hypothesis
+#[derive(Debug, Deserialize)]
+struct EvidenceRequest {
+    /// The likelihood of the evidence P(E|H)
+    likelihood: f64,
+    /// The likelihood of the evidence P(E|¬H)
+    likelihood_null: f64,
+    /// Whether or not the evidence was observed
+    #[serde(default = "observed")]
+    evidence: String,
+    /// Whether to save the posterior probability as the new prior
+    #[serde(default = "update")]
+    update: bool,
+}
+
+/// Evidence is observed unless a request says otherwise
+fn observed() -> String {
+    "observed".to_owned()
+}
+
+/// Applying evidence updates the hypothesis unless a request says otherwise
+const fn update() -> bool {
+    true
+}
+
+/// A response from the API: a status code and a JSON body
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ApiResponse {
+    /// The HTTP status code
+    pub status: u16,
+    /// The JSON body
+    pub body: Value,
+}
+
+impl ApiResponse {
+    /// A successful response
+    const fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    /// An error response with a machine-readable code and a human-readable message
+    fn error(status: u16, code: &str, message: &str) -> Self {
+        Self {
+            status,
+            body: json!({
+                "error": {
+                    "status": status,
+                    "code": code,
+                    "message": message,
+                }
+            }),
+        }
+    }
+
+    /// A response for a request body that is not valid JSON of the expected shape
+    fn invalid_body(error: &serde_json::Error) -> Self {
+        Self::error(400, "invalid_body", &error.to_string())
+    }
+
+    /// A response for a probability outside of [0, 1]
+    fn invalid_probability(field: &str, error: &anyhow::Error) -> Self {
+        Self::error(400, "invalid_probability", &format!("{field}: {error}"))
+    }
+
+    /// A response for an unexpected failure, such as the database being unavailable
+    fn internal(error: &anyhow::Error) -> Self {
+        Self::error(500, "internal_error", &error.to_string())
+    }
+
+    /// A response for a hypothesis that is not in the database
+    fn hypothesis_not_found(name: &str) -> Self {
+        Self::error(
+            404,
+            "hypothesis_not_found",
+            &format!("Could not find hypothesis {name}"),
+        )
+    }
+}
+
+/// Parses the evidence field of a request
+fn parse_evidence(evidence: &str) -> Result<Evidence, ApiResponse> {
+    Evidence::from_str(evidence)
+        .map_err(|error| ApiResponse::error(400, "invalid_evidence", &error.to_string()))
+}
+
+/// Checks that each named value is a probability
+fn validate_probabilities(values: &[(&str, f64)]) -> Result<(), ApiResponse> {
+    for &(field, value) in values {
+        validate_probability(value)
+            .map_err(|error| ApiResponse::invalid_probability(field, &error))?;
+    }
+    Ok(())
+}
+
+/// Checks that the evidence can be explained, i.e. that P(E) is not 0
+fn validate_update(
+    prior: f64,
+    likelihood: f64,
+    likelihood_null: f64,
+    evidence: &Evidence,
+    name: &str,
+) -> Result<(), ApiResponse> {
+    validate_likelihoods_and_prior(prior, likelihood, likelihood_null, evidence, name)
+        .map_err(|error| ApiResponse::error(422, "impossible_evidence", &error.to_string()))
+}
+
+/// Decodes the percent-encoded characters in a path segment
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut remaining = segment.bytes();
+    while let Some(byte) = remaining.next() {
+        if byte == b'%' {
+            let high = char::from(remaining.next()?).to_digit(16)?;
+            let low = char::from(remaining.next()?).to_digit(16)?;
+            bytes.push(u8::try_from(high * 16 + low).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Routes a request to the operation it asks for and returns the response
+#[inline]
+#[must_use]
+pub fn handle_request(method: &Method, url: &str, body: &str) -> ApiResponse {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect::<Option<Vec<_>>>();
+    let segments = match segments {
+        Some(segments) => segments,
+        None => return ApiResponse::error(400, "invalid_path", "Invalid percent-encoding"),
+    };
+    let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+    let result = match (method, segments.as_slice()) {
+        (&Method::Post, ["posterior"]) => posterior(body),
+        (&Method::Get, ["hypotheses"]) => list(),
+        (&Method::Get, ["hypotheses", name]) => get(name),
+        (&Method::Put, ["hypotheses", name]) => set(name, body),
+        (&Method::Delete, ["hypotheses", name]) => remove(name),
+        (&Method::Post, ["hypotheses", name, "evidence"]) => apply_evidence(name, body),
+        (&Method::Get, ["hypotheses", name, "history"]) => history(name),
+        (
+            _,
+            ["posterior" | "hypotheses"]
+            | ["hypotheses", _]
+            | ["hypotheses", _, "evidence" | "history"],
+        ) => Err(ApiResponse::error(
+            405,
+            "method_not_allowed",
+            &format!("{method} is not allowed on {path}"),
+        )),
+        _ => Err(ApiResponse::error(
+            404,
+            "not_found",
+            &format!("No such endpoint {path}"),
+        )),
+    };
+    result.unwrap_or_else(|response| response)
+}
+
+/// `POST /posterior`: calculates a posterior probability without touching the database
+fn posterior(body: &str) -> Result<ApiResponse, ApiResponse> {
+    let request: PosteriorRequest =
+        serde_json::from_str(body).map_err(|error| ApiResponse::invalid_body(&error))?;
+    let evidence = parse_evidence(&request.evidence)?;
+    validate_probabilities(&[
+        ("prior", request.prior),
+        ("likelihood", request.likelihood),
+        ("likelihood_null", request.likelihood_null),
+    ])?;
+    validate_update(
+        request.prior,
+        request.likelihood,
+        request.likelihood_null,
+        &evidence,
+        "H",
+    )?;
+    let posterior_probability = calculate_posterior_probability(
+        request.prior,
+        request.likelihood,
+        request.likelihood_null,
+        &evidence,
+        "H",
+    )
+    .map_err(|error| ApiResponse::error(422, "impossible_evidence", &error.to_string()))?;
+    Ok(ApiResponse::ok(json!({
+        "prior": request.prior,
+        "likelihood": request.likelihood,
+        "likelihood_null": request.likelihood_null,
+        "evidence": evidence_label(&evidence),
+        "posterior_probability": posterior_probability,
+    })))
+}
+
+/// The label used for evidence in responses, matching the JSON output of the CLI
+const fn evidence_label(evidence: &Evidence) -> &'static str {
+    match *evidence {
+        Evidence::Observed => "observed",
+        Evidence::NotObserved => "not observed",
+    }
+}
+
+/// Looks up the prior probability of a hypothesis, distinguishing a missing hypothesis from a database failure
+#[cfg(not(tarpaulin_include))]
+fn find_prior(name: &str) -> Result<f64, ApiResponse> {
+    match open_store().and_then(|store| store.get_prior(name)) {
+        Ok(Some(prior)) => Ok(prior),
+        Ok(None) => Err(ApiResponse::hypothesis_not_found(name)),
+        Err(error) => Err(ApiResponse::internal(&error)),
+    }
+}
+
+/// `GET /hypotheses`: lists the saved hypotheses
+#[cfg(not(tarpaulin_include))]
+fn list() -> Result<ApiResponse, ApiResponse> {
+    let hypotheses = list_hypotheses().map_err(|error| ApiResponse::internal(&error))?;
+    Ok(ApiResponse::ok(json!({
+        "hypotheses": hypotheses
+            .into_iter()
+            .map(|(name, probability)| json!({ "name": name, "probability": probability }))
+            .collect::<Vec<_>>(),
+    })))
+}
+
+/// `GET /hypotheses/{name}`: gets the prior probability of a hypothesis
+#[cfg(not(tarpaulin_include))]
+fn get(name: &str) -> Result<ApiResponse, ApiResponse> {
+    let probability = find_prior(name)?;
+    Ok(ApiResponse::ok(
+        json!({ "name": name, "probability": probability }),
+    ))
+}
+
+/// `PUT /hypotheses/{name}`: sets the prior probability of a hypothesis
+#[cfg(not(tarpaulin_include))]
+fn set(name: &str, body: &str) -> Result<ApiResponse, ApiResponse> {
+    let request: SetPriorRequest =
+        serde_json::from_str(body).map_err(|error| ApiResponse::invalid_body(&error))?;
+    validate_probabilities(&[("probability", request.probability)])?;
+    set_prior(name, request.probability).map_err(|error| ApiResponse::internal(&error))?;
+    Ok(ApiResponse::ok(
+        json!({ "name": name, "probability": request.probability }),
+    ))
+}
+
+/// `DELETE /hypotheses/{name}`: removes a hypothesis along with its history
+#[cfg(not(tarpaulin_include))]
+fn remove(name: &str) -> Result<ApiResponse, ApiResponse> {
+    find_prior(name)?;
+    remove_prior(name).map_err(|error| ApiResponse::internal(&error))?;
+    Ok(ApiResponse::ok(json!({ "name": name, "removed": true })))
+}
+
+/// `POST /hypotheses/{name}/evidence`: applies evidence to a saved hypothesis, saving the posterior unless `update` is false.  The prior is read and the posterior saved with the store locked, so the response reports the prior the update was applied to.
+#[cfg(not(tarpaulin_include))]
+fn apply_evidence(name: &str, body: &str) -> Result<ApiResponse, ApiResponse> {
+    let request: EvidenceRequest =
+        serde_json::from_str(body).map_err(|error| ApiResponse::invalid_body(&error))?;
+    let evidence = parse_evidence(&request.evidence)?;
+    validate_probabilities(&[
+        ("likelihood", request.likelihood),
+        ("likelihood_null", request.likelihood_null),
+    ])?;
+    let label = evidence_label(&evidence);
+    let observation = Observation::new(request.likelihood, request.likelihood_null, evidence);
+    let store = open_store().map_err(|error| ApiResponse::internal(&error))?;
+    let mut outcome = None;
+    store
+        .transact(&mut |store| {
+            let calculated = calculate_saved_posterior(store, name, &observation);
+            let operations = match calculated {
+                Ok((prior, posterior_probability)) if request.update => append_to_history(
+                    store,
+                    name,
+                    &[HistoryEntry::new(
+                        posterior_probability,
+                        Change::Update {
+                            prior,
+                            observation: observation.clone(),
+                        },
+                    )],
+                )?,
+                Ok(_) | Err(_) => Vec::new(),
+            };
+            outcome = Some(calculated);
+            Ok(operations)
+        })
+        .map_err(|error| ApiResponse::internal(&error))?;
+    let (prior, posterior_probability) = outcome.ok_or_else(|| {
+        ApiResponse::internal(&anyhow!("The evidence could not be applied to {name}"))
+    })??;
+    Ok(ApiResponse::ok(json!({
+        "name": name,
+        "prior": prior,
+        "likelihood": request.likelihood,
+        "likelihood_null": request.likelihood_null,
+        "evidence": label,
+        "posterior_probability": posterior_probability,
+        "updated": request.update,
+    })))
+}
+
+/// Reads the saved prior of the hypothesis and calculates its posterior after the observation, returning both
+#[cfg(not(tarpaulin_include))]
+fn calculate_saved_posterior(
+    store: &dyn Store,
+    name: &str,
+    observation: &Observation,
+) -> Result<(f64, f64), ApiResponse> {
+    let prior = store
+        .get_prior(name)
+        .map_err(|error| ApiResponse::internal(&error))?
+        .ok_or_else(|| ApiResponse::hypothesis_not_found(name))?;
+    validate_update(
+        prior,
+        observation.likelihood,
+        observation.likelihood_null,
+        &observation.evidence,
+        name,
+    )?;
+    let posterior_probability = calculate_posterior_probability(
+        prior,
+        observation.likelihood,
+        observation.likelihood_null,
+        &observation.evidence,
+        name,
+    )
+    .map_err(|error| ApiResponse::error(422, "impossible_evidence", &error.to_string()))?;
+    Ok((prior, posterior_probability))
+}
+
+/// `GET /hypotheses/{name}/history`: gets the recorded history of a hypothesis
+#[cfg(not(tarpaulin_include))]
+fn history(name: &str) -> Result<ApiResponse, ApiResponse> {
+    find_prior(name)?;
+    let history = find_history(name)
+        .map_err(|error| ApiResponse::internal(&error))?
+        .unwrap_or_default();
+    Ok(ApiResponse::ok(json!({ "name": name, "history": history })))
+}
+
+/// A JSON API server bound to localhost
+#[non_exhaustive]
+pub struct ApiServer {
+    /// The underlying HTTP server
+    server: Server,
+}
+
+impl Debug for ApiServer {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiServer")
+            .field("address", &self.local_addr())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ApiServer {
+    /// Binds the server to the port on localhost.  Port 0 binds to an ephemeral port.
+    /// # Errors
+    /// - If the port cannot be bound
+    #[inline]
+    pub fn bind(port: u16) -> Result<Self> {
+        let server = Server::http(("127.0.0.1", port)).map_err(|error| anyhow!(error))?;
+        Ok(Self { server })
+    }
+
+    /// The address the server is listening on
+    #[inline]
+    #[must_use]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Serves requests until the process is stopped
+    #[inline]
+    #[cfg(not(tarpaulin_include))]
+    pub fn run(&self) {
+        for request in self.server.incoming_requests() {
+            respond(request);
+        }
+    }
+}
+
+/// Reads a request, handles it and sends the response
+#[cfg(not(tarpaulin_include))]
+fn respond(mut request: Request) {
+    let mut body = String::new();
+    let response = match request
+        .as_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_string(&mut body)
+    {
+        Ok(_) => handle_request(request.method(), request.url(), &body),
+        Err(error) => ApiResponse::error(400, "invalid_body", &error.to_string()),
+    };
+    info!("{} {} {}", request.method(), request.url(), response.status);
+    let content_type = Header::from_bytes("Content-Type", "application/json");
+    let mut http_response =
+        Response::from_string(response.body.to_string()).with_status_code(response.status);
+    if let Ok(content_type) = content_type {
+        http_response.add_header(content_type);
+    }
+    if let Err(error) = request.respond(http_response) {
+        info!("Failed to send response: {error}");
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_calculates_a_posterior_probability() {
+        let response = handle_request(
+            &Method::Post,
+            "/posterior",
+            r#"{"prior": 0.75, "likelihood": 0.75, "likelihood_null": 0.5}"#,
+        );
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["evidence"], "observed");
+        let posterior = response.body["posterior_probability"]
+            .as_f64()
+            .unwrap_or_default();
+        assert!((posterior - 0.818_181_818_181_818_1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_rejects_an_invalid_probability() {
+        let response = handle_request(
+            &Method::Post,
+            "/posterior",
+            r#"{"prior": 1.5, "likelihood": 0.75, "likelihood_null": 0.5}"#,
+        );
+        assert_eq!(response.status, 400);
+        assert_eq!(response.body["error"]["code"], "invalid_probability");
+    }
+
+    #[test]
+    fn it_rejects_impossible_evidence() {
+        let response = handle_request(
+            &Method::Post,
+            "/posterior",
+            r#"{"prior": 0.5, "likelihood": 0.0, "likelihood_null": 0.0, "evidence": "observed"}"#,
+        );
+        assert_eq!(response.status, 422);
+        assert_eq!(response.body["error"]["code"], "impossible_evidence");
+    }
+
+    #[test]
+    fn it_rejects_an_invalid_body() {
+        let response = handle_request(&Method::Post, "/posterior", r#"{"prior": 0.5"#);
+        assert_eq!(response.status, 400);
+        assert_eq!(response.body["error"]["code"], "invalid_body");
+        let response = handle_request(
+            &Method::Post,
+            "/posterior",
+            r#"{"prior": 0.5, "likelihood": 0.5, "likelihood_null": 0.5, "evidence": "maybe"}"#,
+        );
+        assert_eq!(response.body["error"]["code"], "invalid_evidence");
+    }
+
+    #[test]
+    fn it_rejects_unknown_routes_and_methods() {
+        assert_eq!(handle_request(&Method::Get, "/nothing", "").status, 404);
+        assert_eq!(handle_request(&Method::Get, "/posterior", "").status, 405);
+        assert_eq!(
+            handle_request(&Method::Patch, "/hypotheses/rain", "").status,
+            405
+        );
+        assert_eq!(
+            handle_request(&Method::Get, "/hypotheses/%zz", "").status,
+            400
+        );
+    }
+
+    #[test]
+    fn it_decodes_percent_encoded_names() {
+        assert_eq!(
+            percent_decode("infra%2Fdb%20load"),
+            Some("infra/db load".to_owned())
+        );
+        assert_eq!(percent_decode("%2"), None);
+    }
+}
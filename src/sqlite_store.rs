@@ -0,0 +1,295 @@
+//! A store kept in a SQLite database, with hypotheses, history and metadata in tables that can be inspected with any SQLite client.
+use crate::history::HistoryEntry;
+use crate::namespace::{namespace_prefix, SEPARATOR};
+use crate::store::{lock_timeout, Operation, Store};
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::path::Path;
+
+/// The tables of the database
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS hypotheses (
+    name TEXT PRIMARY KEY NOT NULL,
+    probability REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS history (
+    name TEXT NOT NULL,
+    step INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    probability REAL NOT NULL,
+    entry TEXT NOT NULL,
+    PRIMARY KEY (name, step)
+);
+CREATE TABLE IF NOT EXISTS metadata (
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (kind, name)
+);
+";
+
+/// A store kept in a SQLite database.
+///
+/// Each hypothesis is a row of `hypotheses`, each step of its history a row of `history` (with the full entry as JSON in `entry`), and each metadata record a row of `metadata`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SqliteStore {
+    /// The open connection
+    connection: Connection,
+}
+
+impl SqliteStore {
+    /// Opens the SQLite database in the file, creating it and its tables if needed.  Waits up to the lock timeout for other processes writing to it.
+    /// # Errors
+    /// - If the database cannot be opened or created
+    #[inline]
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(lock_timeout()?)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+}
+
+impl Store for SqliteStore {
+    #[inline]
+    fn get_prior(&self, name: &str) -> Result<Option<f64>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT probability FROM hypotheses WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    #[inline]
+    fn list_priors(&self) -> Result<Vec<(String, f64)>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, probability FROM hypotheses ORDER BY name")?;
+        let priors = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(priors)
+    }
+
+    #[inline]
+    fn list_priors_in(&self, namespace: &str) -> Result<Vec<(String, f64)>> {
+        let Some((start, end)) = namespace_range(namespace) else {
+            return self.list_priors();
+        };
+        let mut statement = self.connection.prepare(
+            "SELECT name, probability FROM hypotheses WHERE name >= ?1 AND name < ?2 ORDER BY name",
+        )?;
+        let priors = statement
+            .query_map(params![start, end], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(priors)
+    }
+
+    #[inline]
+    fn get_history(&self, name: &str) -> Result<Option<Vec<HistoryEntry>>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT entry FROM history WHERE name = ?1 ORDER BY step")?;
+        let entries = statement
+            .query_map(params![name], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let history = entries
+            .iter()
+            .map(|entry| serde_json::from_str(entry))
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        Ok(Some(history))
+    }
+
+    #[inline]
+    fn history_names(&self) -> Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT DISTINCT name FROM history ORDER BY name")?;
+        let names = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(names)
+    }
+
+    #[inline]
+    fn get_metadata(&self, kind: &str, name: &str) -> Result<Option<String>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT value FROM metadata WHERE kind = ?1 AND name = ?2",
+                params![kind, name],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    #[inline]
+    fn list_metadata(&self, kind: &str) -> Result<Vec<(String, String)>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, value FROM metadata WHERE kind = ?1 ORDER BY name")?;
+        let records = statement
+            .query_map(params![kind], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(records)
+    }
+
+    #[inline]
+    fn metadata_kinds(&self) -> Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT DISTINCT kind FROM metadata ORDER BY kind")?;
+        let kinds = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(kinds)
+    }
+
+    #[inline]
+    fn apply(&self, operations: &[Operation]) -> Result<()> {
+        let transaction =
+            Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        write(&transaction, operations)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    #[inline]
+    fn transact(&self, change: &mut dyn FnMut(&dyn Store) -> Result<Vec<Operation>>) -> Result<()> {
+        // An immediate transaction takes the write lock before the first read, and is rolled back if it is dropped before being committed
+        let transaction =
+            Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let operations = change(self)?;
+        write(&transaction, &operations)?;
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// The range of names in the namespace, and in the namespaces nested in it, or `None` for the empty namespace, which holds every name.
+///
+/// Names are compared byte by byte, so those starting with the prefix of the namespace sort from the prefix up to the prefix with its trailing separator replaced by the character after it.
+fn namespace_range(namespace: &str) -> Option<(String, String)> {
+    let prefix = namespace_prefix(namespace);
+    let parent = prefix.strip_suffix(SEPARATOR)?;
+    let after_separator = char::from_u32(u32::from(SEPARATOR) + 1)?;
+    Some((prefix.clone(), format!("{parent}{after_separator}")))
+}
+
+/// Applies the operations in order within the transaction open on the connection
+/// # Errors
+/// - If the database cannot be written
+fn write(connection: &Connection, operations: &[Operation]) -> Result<()> {
+    for operation in operations {
+        match *operation {
+            Operation::SetPrior { ref name, prior } => {
+                connection.execute(
+                    "INSERT INTO hypotheses (name, probability) VALUES (?1, ?2)
+                     ON CONFLICT (name) DO UPDATE SET probability = excluded.probability",
+                    params![name, prior],
+                )?;
+            }
+            Operation::RemovePrior { ref name } => {
+                connection.execute("DELETE FROM hypotheses WHERE name = ?1", params![name])?;
+            }
+            Operation::SetHistory {
+                ref name,
+                ref history,
+            } => {
+                connection.execute("DELETE FROM history WHERE name = ?1", params![name])?;
+                for (step, entry) in history.iter().enumerate() {
+                    connection.execute(
+                        "INSERT INTO history (name, step, timestamp, probability, entry) VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![
+                            name,
+                            i64::try_from(step)?,
+                            entry.timestamp.to_rfc3339(),
+                            entry.probability,
+                            serde_json::to_string(entry)?,
+                        ],
+                    )?;
+                }
+            }
+            Operation::RemoveHistory { ref name } => {
+                connection.execute("DELETE FROM history WHERE name = ?1", params![name])?;
+            }
+            Operation::SetMetadata {
+                ref kind,
+                ref name,
+                ref value,
+            } => {
+                connection.execute(
+                    "INSERT INTO metadata (kind, name, value) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (kind, name) DO UPDATE SET value = excluded.value",
+                    params![kind, name, value],
+                )?;
+            }
+            Operation::RemoveMetadata { ref kind, ref name } => {
+                connection.execute(
+                    "DELETE FROM metadata WHERE kind = ?1 AND name = ?2",
+                    params![kind, name],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::store::tests::{
+        it_behaves_like_a_store, it_keeps_concurrent_changes, temporary_path,
+    };
+    use crate::store::{migrate, SledStore};
+
+    #[test]
+    fn it_stores_hypotheses_in_sqlite() -> Result<()> {
+        let store = SqliteStore::open(&temporary_path("sqlite-store.sqlite3"))?;
+        it_behaves_like_a_store(&store)
+    }
+
+    #[test]
+    fn it_keeps_changes_made_by_concurrent_connections() -> Result<()> {
+        it_keeps_concurrent_changes(
+            |path| Ok(Box::new(SqliteStore::open(path)?)),
+            &temporary_path("sqlite-concurrent.sqlite3"),
+        )
+    }
+
+    #[test]
+    fn it_keeps_history_in_an_inspectable_table() -> Result<()> {
+        let from = SledStore::open(&temporary_path("sqlite-migrate-from"))?;
+        let to = SqliteStore::open(&temporary_path("sqlite-migrate-to.sqlite3"))?;
+        from.apply(&[Operation::SetPrior {
+            name: "rain".to_owned(),
+            prior: 0.8,
+        }])?;
+        from.apply(&[Operation::SetHistory {
+            name: "rain".to_owned(),
+            history: vec![
+                HistoryEntry::new(0.5, crate::history::Change::Set),
+                HistoryEntry::new(0.8, crate::history::Change::Set),
+            ],
+        }])?;
+        assert_eq!(migrate(&from, &to)?, 1);
+        let probabilities = to
+            .connection
+            .prepare("SELECT probability FROM history WHERE name = 'rain' ORDER BY step")?
+            .query_map([], |row| row.get::<_, f64>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        assert_eq!(probabilities, vec![0.5, 0.8]);
+        Ok(())
+    }
+}
@@ -0,0 +1,843 @@
+//! Storage backends for hypotheses, their history, and metadata such as actions and tags.
+use crate::dir_store::DirStore;
+use crate::history::HistoryEntry;
+use crate::namespace::namespace_prefix;
+use anyhow::{anyhow, Error, Result};
+use dirs::home_dir;
+use fs2::{lock_contended_error, FileExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sled::transaction::{TransactionResult, Transactional};
+use sled::{Db, Tree};
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The name of the sled tree holding the history of each hypothesis
+const SLED_HISTORY_TREE: &str = "history";
+/// The name of the default sled tree, which holds the prior probabilities
+const SLED_PRIORS_TREE: &str = "__sled__default";
+/// The file in a sled database directory that sled locks while the database is open
+const SLED_LOCK_FILE: &str = "db";
+/// How long to wait for another process to release the database before giving up, unless overridden by `ASK_BAYES_LOCK_TIMEOUT`
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait between attempts to open a database held by another process
+pub(crate) const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The store selected with [`use_store`]
+static SELECTED_STORE: OnceLock<StoreLocation> = OnceLock::new();
+
+/// A change to the store.  Changes are applied in batches with [`Store::apply`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Sets the prior probability of a hypothesis
+    SetPrior {
+        /// The name of the hypothesis
+        name: String,
+        /// The new prior probability
+        prior: f64,
+    },
+    /// Removes the prior probability of a hypothesis
+    RemovePrior {
+        /// The name of the hypothesis
+        name: String,
+    },
+    /// Replaces the history of a hypothesis
+    SetHistory {
+        /// The name of the hypothesis
+        name: String,
+        /// The full history of the hypothesis, oldest first
+        history: Vec<HistoryEntry>,
+    },
+    /// Removes the history of a hypothesis
+    RemoveHistory {
+        /// The name of the hypothesis
+        name: String,
+    },
+    /// Sets a metadata record, such as the actions or tags of a hypothesis
+    SetMetadata {
+        /// The kind of metadata, e.g. `actions`
+        kind: String,
+        /// The name the metadata is attached to
+        name: String,
+        /// The metadata, serialized as JSON
+        value: String,
+    },
+    /// Removes a metadata record
+    RemoveMetadata {
+        /// The kind of metadata, e.g. `actions`
+        kind: String,
+        /// The name the metadata is attached to
+        name: String,
+    },
+}
+
+/// A place hypotheses, their history and their metadata are kept
+pub trait Store {
+    /// Gets the prior probability of the hypothesis, if it is in the store
+    /// # Errors
+    /// - If the store cannot be read
+    fn get_prior(&self, name: &str) -> Result<Option<f64>>;
+
+    /// Lists the hypotheses with their prior probabilities, in alphabetical order
+    /// # Errors
+    /// - If the store cannot be read
+    fn list_priors(&self) -> Result<Vec<(String, f64)>>;
+
+    /// Lists the hypotheses in the namespace, and in the namespaces nested in it, with their prior probabilities in alphabetical order
+    /// # Errors
+    /// - If the store cannot be read
+    #[inline]
+    fn list_priors_in(&self, namespace: &str) -> Result<Vec<(String, f64)>> {
+        let prefix = namespace_prefix(namespace);
+        Ok(self
+            .list_priors()?
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .collect())
+    }
+
+    /// Gets the history of the hypothesis, if any has been recorded
+    /// # Errors
+    /// - If the store cannot be read
+    fn get_history(&self, name: &str) -> Result<Option<Vec<HistoryEntry>>>;
+
+    /// Lists the names of the hypotheses with a recorded history, in alphabetical order
+    /// # Errors
+    /// - If the store cannot be read
+    fn history_names(&self) -> Result<Vec<String>>;
+
+    /// Gets a metadata record as JSON
+    /// # Errors
+    /// - If the store cannot be read
+    fn get_metadata(&self, kind: &str, name: &str) -> Result<Option<String>>;
+
+    /// Lists the metadata records of a kind as names and JSON, in alphabetical order of name
+    /// # Errors
+    /// - If the store cannot be read
+    fn list_metadata(&self, kind: &str) -> Result<Vec<(String, String)>>;
+
+    /// Lists the kinds of metadata in the store
+    /// # Errors
+    /// - If the store cannot be read
+    fn metadata_kinds(&self) -> Result<Vec<String>>;
+
+    /// Applies the operations in order as a single transaction: either every operation takes effect, or none do
+    /// # Errors
+    /// - If the store cannot be written
+    fn apply(&self, operations: &[Operation]) -> Result<()>;
+
+    /// Reads from the store with `change` and applies the operations it returns as a single transaction.
+    ///
+    /// The store is locked for writing from the first read to the last write, so no other process can change what was read before the operations are applied.
+    /// # Errors
+    /// - If `change` fails, in which case nothing is written
+    /// - If the store cannot be locked, read or written
+    fn transact(&self, change: &mut dyn FnMut(&dyn Store) -> Result<Vec<Operation>>) -> Result<()>;
+}
+
+/// Where a store is kept and which backend it uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StoreLocation {
+    /// A sled database in the directory
+    Sled(PathBuf),
+    /// A SQLite database in the file.  Requires the `sqlite` feature
+    Sqlite(PathBuf),
+    /// A directory of plain-text files, one per hypothesis
+    Dir(PathBuf),
+}
+
+impl StoreLocation {
+    /// The default location: the sled database in `~/.ask-bayes`
+    /// # Errors
+    /// - If the home directory cannot be found
+    #[inline]
+    pub fn default_location() -> Result<Self> {
+        Ok(Self::Sled(data_dir()?.join("hypotheses.db")))
+    }
+}
+
+impl FromStr for StoreLocation {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (backend, path) = s.split_once(':').map_or((s, None), |(backend, path)| {
+            (backend, Some(PathBuf::from(path)))
+        });
+        match backend {
+            "sled" => Ok(Self::Sled(match path {
+                Some(path) => path,
+                None => data_dir()?.join("hypotheses.db"),
+            })),
+            "sqlite" => Ok(Self::Sqlite(match path {
+                Some(path) => path,
+                None => data_dir()?.join("hypotheses.sqlite3"),
+            })),
+            "dir" => Ok(Self::Dir(match path {
+                Some(path) => path,
+                None => data_dir()?.join("beliefs"),
+            })),
+            _ => Err(anyhow!(
+                "Invalid store: {s}.  Expected `sled`, `sqlite` or `dir`, optionally followed by `:PATH`"
+            )),
+        }
+    }
+}
+
+impl Display for StoreLocation {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Sled(ref path) => write!(f, "sled:{}", path.display()),
+            Self::Sqlite(ref path) => write!(f, "sqlite:{}", path.display()),
+            Self::Dir(ref path) => write!(f, "dir:{}", path.display()),
+        }
+    }
+}
+
+/// The directory holding the default databases
+/// # Errors
+/// - If the home directory cannot be found
+fn data_dir() -> Result<PathBuf> {
+    home_dir()
+        .map(|hd| hd.join(".ask-bayes"))
+        .ok_or_else(|| anyhow!("Could not find home directory"))
+}
+
+/// Selects the store for the rest of the process, taking precedence over `ASK_BAYES_STORE`
+/// # Errors
+/// - If a store has already been selected
+#[inline]
+pub fn use_store(location: StoreLocation) -> Result<()> {
+    SELECTED_STORE
+        .set(location)
+        .map_err(|location| anyhow!("Cannot select {location}: a store has already been selected"))
+}
+
+/// The store to use: the one selected with [`use_store`], otherwise the one named by `ASK_BAYES_STORE` if it is set, otherwise the default sled database
+/// # Errors
+/// - If `ASK_BAYES_STORE` does not name a valid store
+/// - If the home directory cannot be found
+#[inline]
+pub fn store_location() -> Result<StoreLocation> {
+    if let Some(location) = SELECTED_STORE.get() {
+        return Ok(location.clone());
+    }
+    std::env::var("ASK_BAYES_STORE").map_or_else(
+        |_not_set| StoreLocation::default_location(),
+        |location| location.parse(),
+    )
+}
+
+/// Opens the store in use
+/// # Errors
+/// - If the store cannot be opened
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn open_store() -> Result<Box<dyn Store>> {
+    open_store_at(&store_location()?)
+}
+
+/// Opens the store at the location
+/// # Errors
+/// - If the store cannot be opened
+/// - If the location uses a backend this build does not support
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn open_store_at(location: &StoreLocation) -> Result<Box<dyn Store>> {
+    match *location {
+        StoreLocation::Sled(ref path) => Ok(Box::new(SledStore::open(path)?)),
+        #[cfg(feature = "sqlite")]
+        StoreLocation::Sqlite(ref path) => {
+            Ok(Box::new(crate::sqlite_store::SqliteStore::open(path)?))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        StoreLocation::Sqlite(_) => Err(anyhow!(
+            "This build of ask-bayes does not support SQLite.  Rebuild it with `--features sqlite`"
+        )),
+        StoreLocation::Dir(ref path) => Ok(Box::new(DirStore::open(path)?)),
+    }
+}
+
+/// Copies every hypothesis, history and metadata record from one store into another in a single batch, returning the number of hypotheses copied
+/// # Errors
+/// - If the source cannot be read
+/// - If the destination cannot be written
+#[inline]
+pub fn migrate(from: &dyn Store, to: &dyn Store) -> Result<usize> {
+    let mut operations = Vec::new();
+    let priors = from.list_priors()?;
+    for &(ref name, prior) in &priors {
+        operations.push(Operation::SetPrior {
+            name: name.clone(),
+            prior,
+        });
+        if let Some(history) = from.get_history(name)? {
+            operations.push(Operation::SetHistory {
+                name: name.clone(),
+                history,
+            });
+        }
+    }
+    for kind in from.metadata_kinds()? {
+        for (name, value) in from.list_metadata(&kind)? {
+            operations.push(Operation::SetMetadata {
+                kind: kind.clone(),
+                name,
+                value,
+            });
+        }
+    }
+    to.apply(&operations)?;
+    Ok(priors.len())
+}
+
+/// Reads the metadata record of the kind saved under the name, deserialized from JSON
+/// # Errors
+/// - If the store cannot be read
+/// - If the record cannot be deserialized
+pub(crate) fn find_record<T: DeserializeOwned>(
+    store: &dyn Store,
+    kind: &str,
+    name: &str,
+) -> Result<Option<T>> {
+    store
+        .get_metadata(kind, name)?
+        .map(|value| Ok(serde_json::from_str(&value)?))
+        .transpose()
+}
+
+/// The operation that saves the record under the name as metadata of the kind, serialized as JSON
+/// # Errors
+/// - If the record cannot be serialized
+pub(crate) fn set_record<T: Serialize>(kind: &str, name: &str, record: &T) -> Result<Operation> {
+    Ok(Operation::SetMetadata {
+        kind: kind.to_owned(),
+        name: name.to_owned(),
+        value: serde_json::to_string(record)?,
+    })
+}
+
+/// The operation that removes the metadata record of the kind saved under the name
+pub(crate) fn remove_record(kind: &str, name: &str) -> Operation {
+    Operation::RemoveMetadata {
+        kind: kind.to_owned(),
+        name: name.to_owned(),
+    }
+}
+
+/// Lists the metadata records of the kind in alphabetical order of name, deserialized from JSON
+/// # Errors
+/// - If the store cannot be read
+/// - If a record cannot be deserialized
+pub(crate) fn collect_records<T: DeserializeOwned>(
+    store: &dyn Store,
+    kind: &str,
+) -> Result<Vec<(String, T)>> {
+    store
+        .list_metadata(kind)?
+        .into_iter()
+        .map(|(name, value)| Ok((name, serde_json::from_str(&value)?)))
+        .collect()
+}
+
+/// How long to wait for a store held by another process, read from `ASK_BAYES_LOCK_TIMEOUT` in seconds if it is set
+/// # Errors
+/// - If `ASK_BAYES_LOCK_TIMEOUT` is not a non-negative number of seconds
+pub(crate) fn lock_timeout() -> Result<Duration> {
+    match std::env::var("ASK_BAYES_LOCK_TIMEOUT") {
+        Ok(seconds) => Duration::try_from_secs_f64(seconds.trim().parse()?).map_err(|error| {
+            anyhow!("ASK_BAYES_LOCK_TIMEOUT must be a non-negative number of seconds: {error}")
+        }),
+        Err(_) => Ok(DEFAULT_LOCK_TIMEOUT),
+    }
+}
+
+/// Whether opening the database at the path failed because another process holds its lock.
+///
+/// sled reports a held lock as an I/O error of kind `Other`, keeping only the text of the underlying error, so the lock on its `db` file is probed directly instead.
+fn is_lock_error(error: &sled::Error, path: &Path) -> bool {
+    if !matches!(*error, sled::Error::Io(_)) {
+        return false;
+    }
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path.join(SLED_LOCK_FILE))
+        .is_ok_and(|file| {
+            // The probe's own lock, if it gets one, is released when the file is closed
+            matches!(file.try_lock_exclusive(), Err(ref lock_error) if lock_error.kind() == lock_contended_error().kind())
+        })
+}
+
+/// Opens the database at the path, retrying while another process holds its lock
+/// # Errors
+/// - If the database cannot be opened
+/// - If the database is still locked once the timeout has passed
+fn open_db_with_retry(path: &Path, timeout: Duration) -> Result<Db> {
+    let started = Instant::now();
+    loop {
+        match sled::open(path) {
+            Ok(db) => return Ok(db),
+            Err(error) if is_lock_error(&error, path) => {
+                if started.elapsed() >= timeout {
+                    return Err(anyhow!(
+                        "The database at {} is busy: another ask-bayes process, such as `ask-bayes serve`, is using it.  \
+                         Gave up after waiting {:.1}s.  Try again once it has finished, or set ASK_BAYES_LOCK_TIMEOUT to wait longer",
+                        path.display(),
+                        timeout.as_secs_f64()
+                    ));
+                }
+                thread::sleep(LOCK_RETRY_INTERVAL);
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+/// A store kept in a sled database.  Prior probabilities are kept in the default tree, history in the `history` tree, and each kind of metadata in a tree of its own.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SledStore {
+    /// The open database
+    db: Db,
+}
+
+impl SledStore {
+    /// Opens the sled database in the directory, waiting for other processes using it to finish
+    /// # Errors
+    /// - If the database cannot be opened
+    /// - If another process holds the database for longer than the lock timeout
+    #[inline]
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            db: open_db_with_retry(path, lock_timeout()?)?,
+        })
+    }
+}
+
+impl Store for SledStore {
+    #[inline]
+    fn get_prior(&self, name: &str) -> Result<Option<f64>> {
+        match self.db.get(name)? {
+            Some(prior_serialized) => Ok(Some(f64::from_be_bytes(
+                prior_serialized.as_ref().try_into()?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn list_priors(&self) -> Result<Vec<(String, f64)>> {
+        let mut priors = Vec::new();
+        for entry in self.db.iter() {
+            let (name, prior_serialized) = entry?;
+            priors.push((
+                String::from_utf8(name.to_vec())?,
+                f64::from_be_bytes(prior_serialized.as_ref().try_into()?),
+            ));
+        }
+        Ok(priors)
+    }
+
+    #[inline]
+    fn list_priors_in(&self, namespace: &str) -> Result<Vec<(String, f64)>> {
+        let mut priors = Vec::new();
+        for entry in self.db.scan_prefix(namespace_prefix(namespace)) {
+            let (name, prior_serialized) = entry?;
+            priors.push((
+                String::from_utf8(name.to_vec())?,
+                f64::from_be_bytes(prior_serialized.as_ref().try_into()?),
+            ));
+        }
+        Ok(priors)
+    }
+
+    #[inline]
+    fn get_history(&self, name: &str) -> Result<Option<Vec<HistoryEntry>>> {
+        match self.db.open_tree(SLED_HISTORY_TREE)?.get(name)? {
+            Some(history_serialized) => {
+                Ok(Some(serde_json::from_slice(history_serialized.as_ref())?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn get_metadata(&self, kind: &str, name: &str) -> Result<Option<String>> {
+        match self.db.open_tree(kind)?.get(name)? {
+            Some(value) => Ok(Some(String::from_utf8(value.to_vec())?)),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn history_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for name in self.db.open_tree(SLED_HISTORY_TREE)?.iter().keys() {
+            names.push(String::from_utf8(name?.to_vec())?);
+        }
+        Ok(names)
+    }
+
+    #[inline]
+    fn list_metadata(&self, kind: &str) -> Result<Vec<(String, String)>> {
+        let mut records = Vec::new();
+        for entry in &self.db.open_tree(kind)? {
+            let (name, value) = entry?;
+            records.push((
+                String::from_utf8(name.to_vec())?,
+                String::from_utf8(value.to_vec())?,
+            ));
+        }
+        Ok(records)
+    }
+
+    #[inline]
+    fn metadata_kinds(&self) -> Result<Vec<String>> {
+        self.db
+            .tree_names()
+            .into_iter()
+            .filter(|name| {
+                name != SLED_PRIORS_TREE.as_bytes() && name != SLED_HISTORY_TREE.as_bytes()
+            })
+            .map(|name| Ok(String::from_utf8(name.to_vec())?))
+            .collect()
+    }
+
+    #[inline]
+    fn apply(&self, operations: &[Operation]) -> Result<()> {
+        let mut tree_names: Vec<&str> = vec![SLED_PRIORS_TREE];
+        let mut writes = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let (tree_name, name, value) = match *operation {
+                Operation::SetPrior { ref name, prior } => {
+                    (SLED_PRIORS_TREE, name, Some(prior.to_be_bytes().to_vec()))
+                }
+                Operation::RemovePrior { ref name } => (SLED_PRIORS_TREE, name, None),
+                Operation::SetHistory {
+                    ref name,
+                    ref history,
+                } => (SLED_HISTORY_TREE, name, Some(serde_json::to_vec(history)?)),
+                Operation::RemoveHistory { ref name } => (SLED_HISTORY_TREE, name, None),
+                Operation::SetMetadata {
+                    ref kind,
+                    ref name,
+                    ref value,
+                } => (kind.as_str(), name, Some(value.as_bytes().to_vec())),
+                Operation::RemoveMetadata { ref kind, ref name } => (kind.as_str(), name, None),
+            };
+            let tree = tree_names
+                .iter()
+                .position(|&known| known == tree_name)
+                .unwrap_or_else(|| {
+                    tree_names.push(tree_name);
+                    tree_names.len() - 1
+                });
+            writes.push((tree, name.as_str(), value));
+        }
+        let mut trees: Vec<Tree> = vec![Tree::clone(&self.db)];
+        for &tree_name in tree_names.iter().skip(1) {
+            trees.push(self.db.open_tree(tree_name)?);
+        }
+        let result: TransactionResult<(), Infallible> = trees.as_slice().transaction(|trees| {
+            for &(tree, name, ref value) in &writes {
+                match *value {
+                    Some(ref value) => {
+                        trees[tree].insert(name, value.as_slice())?;
+                    }
+                    None => {
+                        trees[tree].remove(name)?;
+                    }
+                }
+            }
+            Ok(())
+        });
+        result.map_err(|error| anyhow!("Could not save the changes: {error}"))
+    }
+
+    #[inline]
+    fn transact(&self, change: &mut dyn FnMut(&dyn Store) -> Result<Vec<Operation>>) -> Result<()> {
+        // sled locks the database for as long as it is open, so no other process can write to it in between
+        let operations = change(self)?;
+        self.apply(&operations)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::history::Change;
+    use crate::{Evidence, Observation};
+
+    pub(crate) fn temporary_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ask-bayes-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Checks the behaviour every store must share
+    pub(crate) fn it_behaves_like_a_store(store: &dyn Store) -> Result<()> {
+        assert_eq!(store.get_prior("rain")?, None);
+        let history = vec![
+            HistoryEntry::new(0.5, Change::Set),
+            HistoryEntry::new(
+                0.8,
+                Change::Update {
+                    prior: 0.5,
+                    observation: Observation::new(0.8, 0.2, Evidence::Observed),
+                },
+            ),
+        ];
+        store.apply(&[
+            Operation::SetPrior {
+                name: "rain".to_owned(),
+                prior: 0.8,
+            },
+            Operation::SetPrior {
+                name: "cloud".to_owned(),
+                prior: 0.3,
+            },
+            Operation::SetHistory {
+                name: "rain".to_owned(),
+                history: history.clone(),
+            },
+            Operation::SetMetadata {
+                kind: "tags".to_owned(),
+                name: "rain".to_owned(),
+                value: r#"["weather"]"#.to_owned(),
+            },
+        ])?;
+        assert_eq!(store.get_prior("rain")?, Some(0.8));
+        assert_eq!(
+            store.list_priors()?,
+            vec![("cloud".to_owned(), 0.3), ("rain".to_owned(), 0.8)]
+        );
+        assert_eq!(store.get_history("rain")?, Some(history));
+        assert_eq!(store.get_history("cloud")?, None);
+        assert_eq!(store.history_names()?, vec!["rain".to_owned()]);
+        assert_eq!(
+            store.get_metadata("tags", "rain")?,
+            Some(r#"["weather"]"#.to_owned())
+        );
+        assert_eq!(
+            store.list_metadata("tags")?,
+            vec![("rain".to_owned(), r#"["weather"]"#.to_owned())]
+        );
+        assert!(store.metadata_kinds()?.contains(&"tags".to_owned()));
+
+        store.apply(&[
+            Operation::RemovePrior {
+                name: "rain".to_owned(),
+            },
+            Operation::RemoveHistory {
+                name: "rain".to_owned(),
+            },
+            Operation::RemoveMetadata {
+                kind: "tags".to_owned(),
+                name: "rain".to_owned(),
+            },
+        ])?;
+        assert_eq!(store.get_prior("rain")?, None);
+        assert_eq!(store.get_history("rain")?, None);
+        assert_eq!(store.get_metadata("tags", "rain")?, None);
+        assert_eq!(store.list_priors()?, vec![("cloud".to_owned(), 0.3)]);
+        assert!(store.history_names()?.is_empty());
+
+        store.apply(&[
+            Operation::SetPrior {
+                name: "weather/rain".to_owned(),
+                prior: 0.8,
+            },
+            Operation::SetPrior {
+                name: "weather/wind/gale".to_owned(),
+                prior: 0.1,
+            },
+            Operation::SetPrior {
+                name: "weatherproof".to_owned(),
+                prior: 0.9,
+            },
+            Operation::SetPrior {
+                name: "weather.x".to_owned(),
+                prior: 0.2,
+            },
+            Operation::SetPrior {
+                name: "weather0".to_owned(),
+                prior: 0.4,
+            },
+        ])?;
+        assert_eq!(
+            store.list_priors_in("weather/")?,
+            vec![
+                ("weather/rain".to_owned(), 0.8),
+                ("weather/wind/gale".to_owned(), 0.1)
+            ]
+        );
+        assert_eq!(store.list_priors_in("")?.len(), 6);
+
+        store.transact(&mut |store| {
+            let prior = store.get_prior("cloud")?.unwrap_or_default();
+            Ok(vec![Operation::SetPrior {
+                name: "cloud".to_owned(),
+                prior: prior * 2.0,
+            }])
+        })?;
+        assert_eq!(store.get_prior("cloud")?, Some(0.6));
+        let failed = store.transact(&mut |_| Err(anyhow!("Nothing to change")));
+        assert!(failed.is_err());
+        assert_eq!(store.get_prior("cloud")?, Some(0.6));
+        Ok(())
+    }
+
+    /// Checks that changes made at once by several writers, each with its own connection to the store, are all kept
+    pub(crate) fn it_keeps_concurrent_changes(
+        open: fn(&Path) -> Result<Box<dyn Store>>,
+        path: &Path,
+    ) -> Result<()> {
+        const WRITERS: usize = 4;
+        const CHANGES: usize = 10;
+        open(path)?.apply(&[Operation::SetPrior {
+            name: "rain".to_owned(),
+            prior: 0.5,
+        }])?;
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|_| {
+                let path = path.to_path_buf();
+                thread::spawn(move || -> Result<()> {
+                    let store = open(&path)?;
+                    for _ in 0..CHANGES {
+                        crate::record_change(
+                            &*store,
+                            "rain",
+                            &HistoryEntry::new(0.5, Change::Set),
+                        )?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().map_err(|_| anyhow!("A writer panicked"))??;
+        }
+        assert_eq!(
+            open(path)?
+                .get_history("rain")?
+                .map(|history| history.len()),
+            Some(WRITERS * CHANGES)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_stores_hypotheses_in_sled() -> Result<()> {
+        let store = SledStore::open(&temporary_path("sled-store"))?;
+        it_behaves_like_a_store(&store)
+    }
+
+    #[test]
+    fn it_migrates_between_stores() -> Result<()> {
+        let from = SledStore::open(&temporary_path("migrate-from"))?;
+        let to = SledStore::open(&temporary_path("migrate-to"))?;
+        from.apply(&[
+            Operation::SetPrior {
+                name: "rain".to_owned(),
+                prior: 0.8,
+            },
+            Operation::SetHistory {
+                name: "rain".to_owned(),
+                history: vec![HistoryEntry::new(0.8, Change::Set)],
+            },
+            Operation::SetMetadata {
+                kind: "actions".to_owned(),
+                name: "rain".to_owned(),
+                value: "[]".to_owned(),
+            },
+        ])?;
+        assert_eq!(migrate(&from, &to)?, 1);
+        assert_eq!(to.get_prior("rain")?, Some(0.8));
+        assert_eq!(to.get_history("rain")?, from.get_history("rain")?);
+        assert_eq!(to.get_metadata("actions", "rain")?, Some("[]".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_json_records_as_metadata() -> Result<()> {
+        let store = SledStore::open(&temporary_path("records"))?;
+        assert_eq!(find_record::<Vec<u64>>(&store, "counts", "rain")?, None);
+        store.apply(&[
+            set_record("counts", "rain", &vec![3_u64, 1])?,
+            set_record("counts", "cloud", &Vec::<u64>::new())?,
+        ])?;
+        assert_eq!(find_record(&store, "counts", "rain")?, Some(vec![3_u64, 1]));
+        assert_eq!(
+            collect_records::<Vec<u64>>(&store, "counts")?,
+            vec![
+                ("cloud".to_owned(), vec![]),
+                ("rain".to_owned(), vec![3, 1])
+            ]
+        );
+        store.apply(&[remove_record("counts", "rain")])?;
+        assert_eq!(collect_records::<Vec<u64>>(&store, "counts")?.len(), 1);
+        assert!(find_record::<String>(&store, "counts", "cloud").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_a_store_location() -> Result<()> {
+        assert_eq!(
+            StoreLocation::from_str("sled:/tmp/beliefs")?,
+            StoreLocation::Sled(PathBuf::from("/tmp/beliefs"))
+        );
+        assert_eq!(
+            StoreLocation::from_str("sqlite:beliefs.sqlite3")?,
+            StoreLocation::Sqlite(PathBuf::from("beliefs.sqlite3"))
+        );
+        assert_eq!(
+            StoreLocation::from_str("dir:./beliefs")?,
+            StoreLocation::Dir(PathBuf::from("./beliefs"))
+        );
+        assert!(StoreLocation::from_str("postgres:db").is_err());
+        assert_eq!(
+            StoreLocation::Sqlite(PathBuf::from("b.sqlite3")).to_string(),
+            "sqlite:b.sqlite3"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_a_busy_database_after_the_lock_timeout() -> Result<()> {
+        let path = temporary_path("busy");
+        let _held = sled::open(&path)?;
+        let result = open_db_with_retry(&path, Duration::from_millis(200));
+        let message = result
+            .err()
+            .map(|error| error.to_string())
+            .unwrap_or_default();
+        assert!(message.contains("is busy"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_waits_for_the_database_lock_to_be_released() -> Result<()> {
+        let path = temporary_path("released");
+        let held = sled::open(&path)?;
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(held);
+        });
+        let db = open_db_with_retry(&path, Duration::from_secs(10))?;
+        drop(db);
+        assert!(releaser.join().is_ok());
+        Ok(())
+    }
+}
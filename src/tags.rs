@@ -0,0 +1,81 @@
+//! Tags for grouping related hypotheses, e.g. all the hypotheses considered during an incident.
+use crate::store::{
+    collect_records, find_record, open_store, remove_record, set_record, Operation, Store,
+};
+use anyhow::Result;
+use std::collections::BTreeSet;
+
+/// The kind of metadata holding the tags of each hypothesis
+pub(crate) const TAGS_METADATA: &str = "tags";
+
+/// Reads the tags of the hypothesis from an open store
+fn read_tags(store: &dyn Store, name: &str) -> Result<BTreeSet<String>> {
+    Ok(find_record(store, TAGS_METADATA, name)?.unwrap_or_default())
+}
+
+/// The operation that saves the tags of the hypothesis, removing the record if there are none left
+fn write_tags(name: &str, tags: &BTreeSet<String>) -> Result<Operation> {
+    if tags.is_empty() {
+        return Ok(remove_record(TAGS_METADATA, name));
+    }
+    set_record(TAGS_METADATA, name, tags)
+}
+
+/// Gets the tags attached to the hypothesis, in alphabetical order
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved tags cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn get_tags(name: &str) -> Result<BTreeSet<String>> {
+    let store = open_store()?;
+    read_tags(&*store, name)
+}
+
+/// Attaches the tags to the hypothesis, keeping any tags already attached
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved tags cannot be deserialized
+/// - If the tags cannot be inserted into the database
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn add_tags(name: &str, tags: &[String]) -> Result<()> {
+    open_store()?.transact(&mut |store| {
+        let mut current = read_tags(store, name)?;
+        current.extend(tags.iter().cloned());
+        Ok(vec![write_tags(name, &current)?])
+    })
+}
+
+/// Detaches the tags from the hypothesis
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved tags cannot be deserialized
+/// - If the tags cannot be updated in the database
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn remove_tags(name: &str, tags: &[String]) -> Result<()> {
+    open_store()?.transact(&mut |store| {
+        let mut current = read_tags(store, name)?;
+        for tag in tags {
+            current.remove(tag);
+        }
+        Ok(vec![write_tags(name, &current)?])
+    })
+}
+
+/// Gets the names of the hypotheses with the tag, in alphabetical order
+/// # Errors
+/// - If the database cannot be opened
+/// - If the saved tags cannot be deserialized
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn hypotheses_with_tag(tag: &str) -> Result<Vec<String>> {
+    Ok(
+        collect_records::<BTreeSet<String>>(&*open_store()?, TAGS_METADATA)?
+            .into_iter()
+            .filter(|record| record.1.contains(tag))
+            .map(|(name, _)| name)
+            .collect(),
+    )
+}
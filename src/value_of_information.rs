@@ -0,0 +1,471 @@
+//! The value of collecting evidence before deciding between actions.
+use crate::decision::{analyze_decision, Action};
+use crate::{
+    calculate_posterior_probability, marginal_likelihood, negate, parse_probability, Evidence,
+    OutputFormat, Rounded,
+};
+use anyhow::{anyhow, Error, Result};
+use log::info;
+use prettytable::{format, Cell, Row, Table};
+use serde_json::json;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A test that can be run to collect evidence about a hypothesis
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct EvidenceTest {
+    /// The name of the test
+    pub name: String,
+    /// The likelihood of a positive result P(E|H)
+    pub likelihood: f64,
+    /// The likelihood of a positive result P(E|¬H)
+    pub likelihood_null: f64,
+    /// The cost of running the test, in the same units as the utilities of the actions
+    pub cost: f64,
+}
+
+impl EvidenceTest {
+    /// Creates a new test
+    #[inline]
+    #[must_use]
+    pub const fn new(name: String, likelihood: f64, likelihood_null: f64, cost: f64) -> Self {
+        Self {
+            name,
+            likelihood,
+            likelihood_null,
+            cost,
+        }
+    }
+}
+
+impl FromStr for EvidenceTest {
+    type Err = Error;
+
+    /// Parses a test in the form `name=likelihood,likelihood_null` or `name=likelihood,likelihood_null,cost`
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "Invalid test: {}.  Expected name=likelihood,likelihood_null or name=likelihood,likelihood_null,cost",
+                s
+            )
+        };
+        let (name, values) = s.split_once('=').ok_or_else(invalid)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Invalid test: {}.  The test must have a name", s));
+        }
+        let values = values.split(',').map(str::trim).collect::<Vec<_>>();
+        let (likelihood, likelihood_null, cost) = match values.as_slice() {
+            [likelihood, likelihood_null] => (likelihood, likelihood_null, 0.0_f64),
+            [likelihood, likelihood_null, cost] => {
+                let cost = cost.parse::<f64>()?;
+                if !cost.is_finite() {
+                    return Err(anyhow!("Invalid test: {}.  The cost must be finite", s));
+                }
+                (likelihood, likelihood_null, cost)
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(Self::new(
+            name.to_owned(),
+            parse_probability(likelihood)?.value(),
+            parse_probability(likelihood_null)?.value(),
+            cost,
+        ))
+    }
+}
+
+impl Display for EvidenceTest {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}={},{},{}",
+            self.name, self.likelihood, self.likelihood_null, self.cost
+        )
+    }
+}
+
+/// What is expected to happen after one of the outcomes of a test
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct TestOutcome {
+    /// The probability of the outcome before running the test, i.e. the preposterior probability.  Ex. P(E) or P(¬E)
+    pub probability: f64,
+    /// The posterior probability of the hypothesis after the outcome, if the outcome is possible.  Ex. P(H|E) or P(H|¬E)
+    pub posterior_probability: Option<f64>,
+    /// The best action after the outcome, if the outcome is possible
+    pub best_action: Option<String>,
+    /// The expected utility of the best action after the outcome, if the outcome is possible
+    pub expected_utility: Option<f64>,
+}
+
+/// The value of running a test before choosing an action
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ValueOfInformation {
+    /// The test that was evaluated
+    pub test: EvidenceTest,
+    /// What is expected to happen if the test is positive
+    pub observed: TestOutcome,
+    /// What is expected to happen if the test is negative
+    pub not_observed: TestOutcome,
+    /// The expected utility of the best action without running the test
+    pub expected_utility_without_test: f64,
+    /// The expected utility of running the test and then taking the best action for its outcome, before the cost of the test
+    pub expected_utility_with_test: f64,
+    /// The expected value of sample information.  Ex. EVSI = E[U | test] - E[U]
+    pub expected_value_of_sample_information: f64,
+    /// The expected value of sample information less the cost of the test
+    pub net_value: f64,
+}
+
+/// Calculates the expected value of running the test before choosing one of the actions
+/// # Errors
+/// - If no actions are given
+/// - If the prior or the likelihoods of the test are not valid probabilities
+#[inline]
+pub fn calculate_value_of_information(
+    prior: f64,
+    test: &EvidenceTest,
+    actions: &[Action],
+    name: &str,
+) -> Result<ValueOfInformation> {
+    crate::validate_probability(test.likelihood)?;
+    crate::validate_probability(test.likelihood_null)?;
+    let without_test = analyze_decision(prior, actions)?;
+    let expected_utility_without_test = without_test.best_expected_utility;
+    let p_e = marginal_likelihood(prior, test.likelihood, test.likelihood_null);
+    let observed = outcome(prior, test, &Evidence::Observed, p_e, actions, name)?;
+    let not_observed = outcome(
+        prior,
+        test,
+        &Evidence::NotObserved,
+        negate(p_e),
+        actions,
+        name,
+    )?;
+    // Outcomes that cannot happen contribute nothing to the expected utility
+    let expected_utility_with_test = [&observed, &not_observed]
+        .iter()
+        .filter_map(|outcome| {
+            outcome
+                .expected_utility
+                .map(|expected_utility| outcome.probability * expected_utility)
+        })
+        .sum::<f64>();
+    // The value of information can never be negative, so rounding errors are clamped away
+    let expected_value_of_sample_information =
+        (expected_utility_with_test - expected_utility_without_test).max(0.0_f64);
+    Ok(ValueOfInformation {
+        test: test.clone(),
+        observed,
+        not_observed,
+        expected_utility_without_test,
+        expected_utility_with_test,
+        expected_value_of_sample_information,
+        net_value: expected_value_of_sample_information - test.cost,
+    })
+}
+
+/// Calculates the expected value of perfect information, i.e. the most any test could be worth before choosing one of the actions
+/// # Errors
+/// - If no actions are given
+/// - If the prior is not a valid probability
+#[inline]
+pub fn calculate_expected_value_of_perfect_information(
+    prior: f64,
+    actions: &[Action],
+) -> Result<f64> {
+    let without_test = analyze_decision(prior, actions)?;
+    let best_if_true = analyze_decision(1.0_f64, actions)?;
+    let best_if_false = analyze_decision(0.0_f64, actions)?;
+    let expected_utility_with_perfect_information = best_if_true
+        .best_expected_utility
+        .mul_add(prior, best_if_false.best_expected_utility * negate(prior));
+    Ok(
+        (expected_utility_with_perfect_information - without_test.best_expected_utility)
+            .max(0.0_f64),
+    )
+}
+
+/// What is expected to happen after one of the outcomes of the test
+fn outcome(
+    prior: f64,
+    test: &EvidenceTest,
+    evidence: &Evidence,
+    probability: f64,
+    actions: &[Action],
+    name: &str,
+) -> Result<TestOutcome> {
+    if probability <= 0.0_f64 {
+        return Ok(TestOutcome {
+            probability: 0.0_f64,
+            posterior_probability: None,
+            best_action: None,
+            expected_utility: None,
+        });
+    }
+    let posterior_probability = calculate_posterior_probability(
+        prior,
+        test.likelihood,
+        test.likelihood_null,
+        evidence,
+        name,
+    )?;
+    let analysis = analyze_decision(posterior_probability, actions)?;
+    Ok(TestOutcome {
+        probability,
+        posterior_probability: Some(posterior_probability),
+        expected_utility: Some(analysis.best_expected_utility),
+        best_action: Some(analysis.best_action),
+    })
+}
+
+/// Formats an optional value, showing `-` when it is missing
+fn format_optional<T: Display>(value: Option<&T>) -> String {
+    value.map_or_else(|| "-".to_owned(), ToString::to_string)
+}
+
+/// Reports the value of information of each test, along with the expected value of perfect information
+#[inline]
+#[cfg(not(tarpaulin_include))]
+pub fn report_value_of_information(
+    name: &str,
+    prior: f64,
+    values: &[ValueOfInformation],
+    expected_value_of_perfect_information: f64,
+    output_format: &OutputFormat,
+) {
+    match *output_format {
+        OutputFormat::Table | OutputFormat::Icons => {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+            table.set_titles(Row::new(vec![
+                Cell::new("Test"),
+                Cell::new("P(E)"),
+                Cell::new(&format!("P({name}|E)")),
+                Cell::new("Best if E"),
+                Cell::new(&format!("P({name}|\u{ac}E)")),
+                Cell::new("Best if \u{ac}E"),
+                Cell::new("EVSI"),
+                Cell::new("Cost"),
+                Cell::new("Net Value"),
+            ]));
+            for value in values {
+                table.add_row(Row::new(vec![
+                    Cell::new(&value.test.name),
+                    Cell::new(&Rounded(value.observed.probability).to_string()),
+                    Cell::new(&format_optional(
+                        value.observed.posterior_probability.map(Rounded).as_ref(),
+                    )),
+                    Cell::new(&format_optional(value.observed.best_action.as_ref())),
+                    Cell::new(&format_optional(
+                        value
+                            .not_observed
+                            .posterior_probability
+                            .map(Rounded)
+                            .as_ref(),
+                    )),
+                    Cell::new(&format_optional(value.not_observed.best_action.as_ref())),
+                    Cell::new(&Rounded(value.expected_value_of_sample_information).to_string()),
+                    Cell::new(&Rounded(value.test.cost).to_string()),
+                    Cell::new(&Rounded(value.net_value).to_string()),
+                ]));
+            }
+            info!("Value of information at P({name}) = {}", Rounded(prior));
+            table.printstd();
+            info!(
+                "Expected value of perfect information: {}",
+                Rounded(expected_value_of_perfect_information)
+            );
+        }
+        OutputFormat::Json => {
+            let outcome_json = |outcome: &TestOutcome| {
+                json!({
+                    "probability": outcome.probability,
+                    "posterior_probability": outcome.posterior_probability,
+                    "best_action": outcome.best_action,
+                    "expected_utility": outcome.expected_utility,
+                })
+            };
+            let json = json!({
+                "name": name,
+                "prior": prior,
+                "expected_value_of_perfect_information": expected_value_of_perfect_information,
+                "tests": values.iter().map(|value| json!({
+                    "test": value.test.name,
+                    "likelihood": value.test.likelihood,
+                    "likelihood_null": value.test.likelihood_null,
+                    "cost": value.test.cost,
+                    "observed": outcome_json(&value.observed),
+                    "not_observed": outcome_json(&value.not_observed),
+                    "expected_utility_without_test": value.expected_utility_without_test,
+                    "expected_utility_with_test": value.expected_utility_with_test,
+                    "expected_value_of_sample_information": value.expected_value_of_sample_information,
+                    "net_value": value.net_value,
+                })).collect::<Vec<_>>(),
+            });
+
+            info!("{}", json.to_string());
+        }
+        OutputFormat::Simple => {
+            let mut lines = Vec::new();
+            for value in values {
+                let test = &value.test.name;
+                lines.push(format!(
+                    "{test}: P(E) = {}, P({name}|E) = {}, best if E: {}",
+                    Rounded(value.observed.probability),
+                    format_optional(value.observed.posterior_probability.map(Rounded).as_ref()),
+                    format_optional(value.observed.best_action.as_ref())
+                ));
+                lines.push(format!(
+                    "{test}: P(\u{ac}E) = {}, P({name}|\u{ac}E) = {}, best if \u{ac}E: {}",
+                    Rounded(value.not_observed.probability),
+                    format_optional(
+                        value
+                            .not_observed
+                            .posterior_probability
+                            .map(Rounded)
+                            .as_ref()
+                    ),
+                    format_optional(value.not_observed.best_action.as_ref())
+                ));
+                lines.push(format!(
+                    "{test}: EVSI = {}, cost = {}, net value = {}",
+                    Rounded(value.expected_value_of_sample_information),
+                    Rounded(value.test.cost),
+                    Rounded(value.net_value)
+                ));
+            }
+            lines.push(format!(
+                "EVPI = {}",
+                Rounded(expected_value_of_perfect_information)
+            ));
+            let output = format!(
+                "
+                {}
+                ",
+                lines.join("\n                ")
+            );
+            info!("{output}");
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    fn epsilon_compare(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9_f64
+    }
+
+    fn actions() -> Vec<Action> {
+        vec![
+            Action::new("wait".to_owned(), 0.0_f64, 0.0_f64),
+            Action::new("treat".to_owned(), 100.0_f64, -20.0_f64),
+        ]
+    }
+
+    #[test]
+    fn it_parses_a_valid_test() -> Result<()> {
+        {
+            let result = EvidenceTest::from_str("scan=0.9,0.2")?;
+            assert_eq!(
+                result,
+                EvidenceTest::new("scan".to_owned(), 0.9_f64, 0.2_f64, 0.0_f64)
+            );
+        }
+        {
+            let result = EvidenceTest::from_str("scan=90%,1 in 5,3.5")?;
+            assert_eq!(
+                result,
+                EvidenceTest::new("scan".to_owned(), 0.9_f64, 0.2_f64, 3.5_f64)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_to_parse_an_invalid_test() {
+        assert!(EvidenceTest::from_str("scan").is_err());
+        assert!(EvidenceTest::from_str("scan=0.9").is_err());
+        assert!(EvidenceTest::from_str("=0.9,0.2").is_err());
+        assert!(EvidenceTest::from_str("scan=1.9,0.2").is_err());
+        assert!(EvidenceTest::from_str("scan=0.9,0.2,a").is_err());
+        assert!(EvidenceTest::from_str("scan=0.9,0.2,1,2").is_err());
+    }
+
+    #[test]
+    fn it_calculates_the_value_of_information_of_a_test() -> Result<()> {
+        let test = EvidenceTest::new("scan".to_owned(), 0.9_f64, 0.2_f64, 1.0_f64);
+        let result = calculate_value_of_information(0.1_f64, &test, &actions(), "test")?;
+        // Without the test, waiting (0) beats treating (100 * 0.1 - 20 * 0.9 = -8)
+        assert!(epsilon_compare(
+            result.expected_utility_without_test,
+            0.0_f64
+        ));
+        // P(E) = 0.1 * 0.9 + 0.9 * 0.2 = 0.27
+        assert!(epsilon_compare(result.observed.probability, 0.27_f64));
+        assert!(epsilon_compare(result.not_observed.probability, 0.73_f64));
+        // P(H|E) = 0.09 / 0.27 = 1/3, so treating is worth 100 / 3 - 40 / 3 = 20
+        assert!(epsilon_compare(
+            result.observed.posterior_probability.unwrap_or_default(),
+            1.0_f64 / 3.0_f64
+        ));
+        assert_eq!(result.observed.best_action, Some("treat".to_owned()));
+        assert_eq!(result.not_observed.best_action, Some("wait".to_owned()));
+        // EVSI = 0.27 * 20 + 0.73 * 0 = 5.4
+        assert!(epsilon_compare(
+            result.expected_value_of_sample_information,
+            5.4_f64
+        ));
+        assert!(epsilon_compare(result.net_value, 4.4_f64));
+        Ok(())
+    }
+
+    #[test]
+    fn it_calculates_no_value_for_an_uninformative_test() -> Result<()> {
+        let test = EvidenceTest::new("coin".to_owned(), 0.5_f64, 0.5_f64, 0.0_f64);
+        let result = calculate_value_of_information(0.1_f64, &test, &actions(), "test")?;
+        assert!(epsilon_compare(
+            result.expected_value_of_sample_information,
+            0.0_f64
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_skips_outcomes_that_cannot_happen() -> Result<()> {
+        let test = EvidenceTest::new("always".to_owned(), 1.0_f64, 1.0_f64, 0.0_f64);
+        let result = calculate_value_of_information(0.1_f64, &test, &actions(), "test")?;
+        assert!(epsilon_compare(result.not_observed.probability, 0.0_f64));
+        assert_eq!(result.not_observed.posterior_probability, None);
+        assert!(epsilon_compare(
+            result.expected_value_of_sample_information,
+            0.0_f64
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_calculates_the_expected_value_of_perfect_information() -> Result<()> {
+        // With perfect information you treat only when sick: 0.1 * 100 + 0.9 * 0 = 10
+        let result = calculate_expected_value_of_perfect_information(0.1_f64, &actions())?;
+        assert!(epsilon_compare(result, 10.0_f64));
+        Ok(())
+    }
+
+    #[test]
+    fn it_never_values_a_test_above_perfect_information() -> Result<()> {
+        let test = EvidenceTest::new("scan".to_owned(), 0.99_f64, 0.01_f64, 0.0_f64);
+        let result = calculate_value_of_information(0.1_f64, &test, &actions(), "test")?;
+        let perfect = calculate_expected_value_of_perfect_information(0.1_f64, &actions())?;
+        assert!(result.expected_value_of_sample_information <= perfect);
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use ask_bayes::prelude::ApiServer;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::OnceLock;
+use std::thread;
+
+/// Starts a single server for all tests on an ephemeral port, with the database in a temporary home directory
+fn server() -> SocketAddr {
+    static ADDRESS: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDRESS.get_or_init(|| {
+        let home =
+            std::env::temp_dir().join(format!("ask-bayes-server-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(&home).expect("temporary home directory");
+        std::env::set_var("HOME", &home);
+        let server = ApiServer::bind(0).expect("bind to an ephemeral port");
+        let address = server.local_addr().expect("server address");
+        thread::spawn(move || server.run());
+        address
+    })
+}
+
+/// Sends a request to the server and returns the status code and JSON body of the response
+fn request(method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let mut stream = TcpStream::connect(server()).expect("connect to the server");
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .expect("send the request");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .expect("read the response");
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .expect("response headers and body");
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .expect("status code");
+    assert!(head.contains("application/json"));
+    (status, serde_json::from_str(body).expect("JSON body"))
+}
+
+#[test]
+fn it_calculates_a_posterior_probability() {
+    let (status, body) = request(
+        "POST",
+        "/posterior",
+        Some(json!({"prior": 0.5, "likelihood": 0.75, "likelihood_null": 0.25, "evidence": "n"})),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["evidence"], "not observed");
+    assert!((body["posterior_probability"].as_f64().unwrap() - 0.25).abs() < 1e-12);
+}
+
+#[test]
+fn it_returns_structured_validation_errors() {
+    let (status, body) = request(
+        "POST",
+        "/posterior",
+        Some(json!({"prior": 0.5, "likelihood": -0.1, "likelihood_null": 0.25})),
+    );
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "invalid_probability");
+    assert_eq!(body["error"]["status"], 400);
+
+    let (status, body) = request(
+        "POST",
+        "/posterior",
+        Some(json!({"prior": 1.0, "likelihood": 0.0, "likelihood_null": 0.5})),
+    );
+    assert_eq!(status, 422);
+    assert_eq!(body["error"]["code"], "impossible_evidence");
+}
+
+#[test]
+fn it_sets_gets_lists_and_removes_a_hypothesis() {
+    let (status, _) = request(
+        "PUT",
+        "/hypotheses/server-test-crud",
+        Some(json!({"probability": 0.3})),
+    );
+    assert_eq!(status, 200);
+
+    let (status, body) = request("GET", "/hypotheses/server-test-crud", None);
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        json!({"name": "server-test-crud", "probability": 0.3})
+    );
+
+    let (status, body) = request("GET", "/hypotheses", None);
+    assert_eq!(status, 200);
+    assert!(body["hypotheses"]
+        .as_array()
+        .unwrap()
+        .contains(&json!({"name": "server-test-crud", "probability": 0.3})));
+
+    let (status, _) = request("DELETE", "/hypotheses/server-test-crud", None);
+    assert_eq!(status, 200);
+    let (status, body) = request("GET", "/hypotheses/server-test-crud", None);
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "hypothesis_not_found");
+}
+
+#[test]
+fn it_applies_evidence_to_a_hypothesis() {
+    request(
+        "PUT",
+        "/hypotheses/server-test%2Fevidence",
+        Some(json!({"probability": 0.5})),
+    );
+
+    let (status, body) = request(
+        "POST",
+        "/hypotheses/server-test%2Fevidence/evidence",
+        Some(json!({"likelihood": 0.8, "likelihood_null": 0.2, "update": false})),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["updated"], false);
+    assert!((body["posterior_probability"].as_f64().unwrap() - 0.8).abs() < 1e-12);
+    let (_, body) = request("GET", "/hypotheses/server-test%2Fevidence", None);
+    assert_eq!(body["probability"], 0.5);
+
+    let (status, body) = request(
+        "POST",
+        "/hypotheses/server-test%2Fevidence/evidence",
+        Some(json!({"likelihood": 0.8, "likelihood_null": 0.2})),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["updated"], true);
+    let (_, body) = request("GET", "/hypotheses/server-test%2Fevidence", None);
+    assert!((body["probability"].as_f64().unwrap() - 0.8).abs() < 1e-12);
+
+    let (status, body) = request("GET", "/hypotheses/server-test%2Fevidence/history", None);
+    assert_eq!(status, 200);
+    assert_eq!(body["history"].as_array().unwrap().len(), 2);
+
+    let (status, body) = request(
+        "POST",
+        "/hypotheses/server-test-missing/evidence",
+        Some(json!({"likelihood": 0.8, "likelihood_null": 0.2})),
+    );
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "hypothesis_not_found");
+}